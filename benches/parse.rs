@@ -0,0 +1,57 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Parsing owned and borrowed requests at `Stage::Data`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use stalwart_mta_hook_types::borrowed::RequestRef;
+use stalwart_mta_hook_types::{Request, Stage};
+
+/// A `Stage::Data` request with `recipients` recipients, 40 headers and a
+/// body of about `body_size` bytes, as JSON.
+fn data_request(recipients: usize, body_size: usize) -> Vec<u8> {
+    let mut raw = String::new();
+    for i in 0..40 {
+        raw.push_str(&format!("X-Header-{}: value number {}\r\n", i, i));
+    }
+    raw.push_str("\r\n");
+    let line = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod.\r\n";
+    while raw.len() < body_size {
+        raw.push_str(line);
+    }
+    let mut builder = Request::builder()
+        .stage(Stage::Data)
+        .helo("client.example.com")
+        .from("john@example.com")
+        .from_param("SIZE", raw.len().to_string())
+        .raw_message(&raw);
+    for i in 0..recipients {
+        builder = builder.to(format!("user{}@example.org", i));
+    }
+    serde_json::to_vec(&builder.build()).unwrap()
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_data");
+    for (name, recipients, body_size) in [
+        ("10kb", 5, 10 << 10),
+        ("1mb", 5, 1 << 20),
+        ("1000_rcpt", 1000, 10 << 10),
+    ] {
+        let json = data_request(recipients, body_size);
+        group.throughput(Throughput::Bytes(json.len() as u64));
+        group.bench_with_input(BenchmarkId::new("owned", name), &json, |b, json| {
+            b.iter(|| serde_json::from_slice::<Request>(black_box(json)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed", name), &json, |b, json| {
+            b.iter(|| serde_json::from_slice::<RequestRef>(black_box(json)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,417 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! `proptest::Arbitrary` implementations for requests and responses.
+//!
+//! The generated values look like real SMTP data: addresses, header lines
+//! with the leading space and line end as sent by Stalwart, ESMTP
+//! parameters and SMTP status codes. The strategies for single values are
+//! public for building custom generators.
+//!
+//! ```
+//! use proptest::prelude::*;
+//! use proptest::test_runner::TestRunner;
+//! use stalwart_mta_hook_types::Request;
+//!
+//! TestRunner::default()
+//!     .run(&any::<Request>(), |request| {
+//!         prop_assert!(!request.context.client.ip.is_empty());
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! ```
+
+use crate::contents::Contents;
+use crate::modifications::Modification;
+use crate::request::{
+    Address, Client, Context, Envelope, Message, Protocol, Queue, Request, Sasl, Server, Stage, Tls,
+};
+use crate::response::{Action, Response, SmtpResponse};
+use proptest::collection::{hash_map, vec};
+use proptest::option;
+use proptest::prelude::*;
+use std::net::IpAddr;
+
+/// A domain such as `mail.example.org`.
+pub fn domain() -> impl Strategy<Value = String> {
+    "[a-z][a-z0-9]{0,9}(\\.[a-z][a-z0-9-]{0,7}[a-z0-9]){0,2}\\.(com|org|net|de|example)"
+}
+
+/// An address such as `john.doe+tag@example.com`.
+pub fn email_address() -> impl Strategy<Value = String> {
+    (
+        "[a-z0-9][a-z0-9_+-]{0,7}(\\.[a-z0-9_+-]{1,8}){0,2}",
+        domain(),
+    )
+        .prop_map(|(local, domain)| format!("{}@{}", local, domain))
+}
+
+/// An IPv4 or IPv6 address as a string.
+pub fn ip_address() -> impl Strategy<Value = String> {
+    any::<IpAddr>().prop_map(|ip| ip.to_string())
+}
+
+/// A header line as found in hook requests, with a leading space and a
+/// trailing CRLF in the value.
+pub fn header() -> impl Strategy<Value = (String, String)> {
+    (
+        "[A-Z][a-z]{1,10}(-[A-Z][a-z]{1,10}){0,2}",
+        "[!-~]{1,20}( [!-~]{1,20}){0,3}",
+    )
+        .prop_map(|(name, value)| (name, format!(" {}\r\n", value)))
+}
+
+/// ESMTP parameters such as `SIZE` or `BODY`.
+pub fn parameters() -> impl Strategy<Value = std::collections::HashMap<String, String>> {
+    hash_map("[A-Z]{2,10}", "[A-Za-z0-9+/.=-]{1,20}", 0..3)
+}
+
+/// A message body of CRLF-terminated lines.
+pub fn body() -> impl Strategy<Value = String> {
+    vec("[ -~]{0,76}", 0..10).prop_map(|lines| {
+        lines
+            .into_iter()
+            .map(|line| format!("{}\r\n", line))
+            .collect()
+    })
+}
+
+impl Arbitrary for Contents {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Mostly UTF-8 bodies, with some in ISO-8859-1.
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            3 => body().prop_map(Contents::from),
+            1 => vec("[ -~\u{a0}-\u{ff}]{0,76}", 0..10).prop_map(|lines| {
+                let mut bytes = Vec::new();
+                for line in lines {
+                    bytes.extend(line.chars().map(|ch| ch as u8));
+                    bytes.extend_from_slice(b"\r\n");
+                }
+                Contents::from(bytes)
+            }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Stage {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            Just(Stage::Connect),
+            Just(Stage::Ehlo),
+            Just(Stage::Auth),
+            Just(Stage::Mail),
+            Just(Stage::Rcpt),
+            Just(Stage::Data),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Client {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            ip_address(),
+            any::<u16>(),
+            option::of(domain()),
+            option::of(domain()),
+            1..100u32,
+        )
+            .prop_map(|(ip, port, ptr, helo, active_connections)| Client {
+                ip,
+                port,
+                ptr,
+                helo,
+                active_connections,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Sasl {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            prop_oneof!["[a-z]{1,12}", email_address()],
+            option::of(prop_oneof![
+                Just("plain".to_string()),
+                Just("login".to_string()),
+                Just("xoauth2".to_string()),
+            ]),
+        )
+            .prop_map(|(login, method)| Sasl { login, method })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Tls {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            prop_oneof![Just("TLSv1.2"), Just("TLSv1.3")],
+            prop_oneof![
+                Just("TLS_AES_256_GCM_SHA384"),
+                Just("TLS_CHACHA20_POLY1305_SHA256"),
+                Just("ECDHE-RSA-AES128-GCM-SHA256"),
+            ],
+            option::of(prop_oneof![Just(128u16), Just(256u16)]),
+            option::of(domain().prop_map(|domain| format!("CN={}", domain))),
+            option::of(domain().prop_map(|domain| format!("CN={}", domain))),
+        )
+            .prop_map(|(version, cipher, bits, issuer, subject)| Tls {
+                version: version.to_string(),
+                cipher: cipher.to_string(),
+                bits,
+                issuer,
+                subject,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Server {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            option::of(domain()),
+            prop_oneof![Just(25u16), Just(465u16), Just(587u16)],
+            option::of(ip_address()),
+        )
+            .prop_map(|(name, port, ip)| Server { name, port, ip })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Context {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            any::<Stage>(),
+            any::<Client>(),
+            option::of(any::<Sasl>()),
+            option::of(any::<Tls>()),
+            any::<Server>(),
+            option::of("[0-9A-F]{16}".prop_map(|id| Queue { id })),
+        )
+            .prop_map(|(stage, client, sasl, tls, server, queue)| Context {
+                stage,
+                client,
+                sasl,
+                tls,
+                server,
+                queue,
+                protocol: Protocol { version: 1 },
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Address {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (email_address(), option::of(parameters()))
+            .prop_map(|(address, parameters)| Address {
+                address,
+                parameters,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Envelope {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (any::<Address>(), vec(any::<Address>(), 1..5))
+            .prop_map(|(from, to)| Envelope { from, to })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Message {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (vec(header(), 0..3), vec(header(), 0..8), any::<Contents>())
+            .prop_map(|(server_headers, headers, contents)| {
+                let mut message = Message {
+                    headers,
+                    server_headers,
+                    contents,
+                    size: 0,
+                };
+                message.size = message.to_rfc5322().len();
+                message
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Request {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            any::<Context>(),
+            option::of(any::<Envelope>()),
+            option::of(any::<Message>()),
+        )
+            .prop_map(|(context, envelope, message)| Request {
+                context,
+                envelope,
+                message,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Action {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            Just(Action::Accept),
+            Just(Action::Discard),
+            Just(Action::Reject),
+            Just(Action::Quarantine),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for SmtpResponse {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            option::of(200..600u16),
+            option::of("[245]\\.[0-7]\\.[0-9]{1,2}"),
+            option::of("[A-Z][a-z]{1,10}( [a-z]{1,10}){0,5}"),
+            any::<bool>(),
+        )
+            .prop_map(
+                |(status, enhanced_status, message, disconnect)| SmtpResponse {
+                    status,
+                    enhanced_status,
+                    message,
+                    disconnect,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for Response {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            any::<Action>(),
+            option::of(any::<SmtpResponse>()),
+            vec(any::<Modification>(), 0..5),
+        )
+            .prop_map(|(action, response, modifications)| Response {
+                action,
+                response,
+                modifications,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Modification {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        let parameters = || hash_map("[A-Z]{2,10}", option::of("[A-Za-z0-9+/.=-]{1,20}"), 0..3);
+        let header = || header().prop_map(|(name, value)| (name, value.trim().to_string()));
+        prop_oneof![
+            (email_address(), parameters())
+                .prop_map(|(value, parameters)| Modification::ChangeFrom { value, parameters }),
+            (email_address(), parameters())
+                .prop_map(|(value, parameters)| Modification::AddRecipient { value, parameters }),
+            email_address().prop_map(|value| Modification::DeleteRecipient { value }),
+            any::<Contents>().prop_map(|value| Modification::ReplaceContents { value }),
+            header().prop_map(|(name, value)| Modification::AddHeader { name, value }),
+            (1..10u32, header()).prop_map(|(index, (name, value))| Modification::InsertHeader {
+                index,
+                name,
+                value
+            }),
+            (1..10u32, header()).prop_map(|(index, (name, value))| Modification::ChangeHeader {
+                index,
+                name,
+                value
+            }),
+            (1..10u32, header())
+                .prop_map(|(index, (name, _))| Modification::DeleteHeader { index, name }),
+        ]
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    /// Serializes, deserializes and serializes again, comparing the JSON.
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Result<(), TestCaseError> {
+        let json = serde_json::to_value(value).unwrap();
+        let parsed: T = serde_json::from_value(json.clone())
+            .map_err(|err| TestCaseError::fail(format!("{}: {}", err, json)))?;
+        prop_assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_request_round_trip(request in any::<Request>()) {
+            round_trip(&request)?;
+        }
+
+        #[test]
+        fn test_response_round_trip(response in any::<Response>()) {
+            round_trip(&response)?;
+        }
+
+        #[test]
+        fn test_message_rfc5322_round_trip(message in any::<Message>()) {
+            let raw = message.to_rfc5322();
+            let parsed = Message::parse(&raw);
+            prop_assert_eq!(parsed.size, message.size);
+            prop_assert_eq!(parsed.headers.len(), message.headers.len() + message.server_headers.len());
+            prop_assert_eq!(parsed.to_rfc5322(), raw);
+        }
+    }
+}
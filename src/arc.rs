@@ -0,0 +1,582 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Authenticated Received Chain (RFC 8617) validation and sealing.
+//!
+//! Forwarding hooks read the existing chain from `Message.headers`, validate
+//! it and add a new ARC set as three `InsertHeader` modifications.
+
+use crate::dkim::{
+    body_hash, parse_tags, select_headers, signed_data, strip_signature, unix_time,
+    verify_signature, verify_tags, Canonicalization, DkimError, DkimKey, KeyResolver,
+};
+use crate::modifications::Modification;
+use crate::request::{Message, Request};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::fmt;
+
+pub const ARC_AUTHENTICATION_RESULTS: &str = "ARC-Authentication-Results";
+pub const ARC_MESSAGE_SIGNATURE: &str = "ARC-Message-Signature";
+pub const ARC_SEAL: &str = "ARC-Seal";
+
+/// Highest instance number allowed by RFC 8617, section 4.2.1.
+pub const MAX_INSTANCE: u32 = 50;
+
+#[derive(Debug)]
+pub enum ArcError {
+    /// The latest ARC-Seal already reports `cv=fail`, no further sets may be added.
+    ChainFailed,
+    TooManyInstances,
+    Dkim(DkimError),
+}
+
+impl fmt::Display for ArcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArcError::ChainFailed => f.write_str("ARC chain has already failed"),
+            ArcError::TooManyInstances => {
+                write!(f, "ARC chain exceeds {} instances", MAX_INSTANCE)
+            }
+            ArcError::Dkim(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ArcError {}
+
+impl From<DkimError> for ArcError {
+    fn from(err: DkimError) -> Self {
+        ArcError::Dkim(err)
+    }
+}
+
+/// Result of validating an ARC chain, used as the `cv=` tag of the next seal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainValidation {
+    None,
+    Pass,
+    Fail(String),
+}
+
+impl ChainValidation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChainValidation::None => "none",
+            ChainValidation::Pass => "pass",
+            ChainValidation::Fail(_) => "fail",
+        }
+    }
+}
+
+/// The three headers sharing one instance number.
+#[derive(Debug, Clone)]
+pub struct ArcSet {
+    pub instance: u32,
+    pub authentication_results: (String, String),
+    pub message_signature: (String, String),
+    pub seal: (String, String),
+}
+
+impl ArcSet {
+    fn seal_tag(&self, name: &str) -> Option<String> {
+        parse_tags(&self.seal.1)
+            .into_iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// The ARC sets found in a message, ordered by instance.
+#[derive(Debug, Clone, Default)]
+pub struct ArcChain {
+    sets: Vec<ArcSet>,
+    error: Option<String>,
+}
+
+impl ArcChain {
+    pub fn from_message(message: &Message) -> Self {
+        let mut found: Vec<(u32, usize, &(String, String))> = Vec::new();
+        let mut chain = ArcChain::default();
+
+        for header in &message.headers {
+            let kind = [ARC_AUTHENTICATION_RESULTS, ARC_MESSAGE_SIGNATURE, ARC_SEAL]
+                .iter()
+                .position(|name| header.0.eq_ignore_ascii_case(name));
+            let Some(kind) = kind else {
+                continue;
+            };
+            // ARC-Authentication-Results starts with `i=`, the signatures
+            // may list it anywhere among their tags.
+            let instance = if kind == 0 {
+                header
+                    .1
+                    .split(';')
+                    .next()
+                    .and_then(|tag| tag.split_once('='))
+                    .filter(|(name, _)| name.trim() == "i")
+                    .and_then(|(_, value)| value.trim().parse::<u32>().ok())
+            } else {
+                parse_tags(&header.1)
+                    .into_iter()
+                    .find(|(name, _)| name == "i")
+                    .and_then(|(_, value)| value.parse::<u32>().ok())
+            };
+            match instance {
+                Some(instance) if (1..=MAX_INSTANCE).contains(&instance) => {
+                    found.push((instance, kind, header))
+                }
+                _ => {
+                    chain.error = Some(format!("invalid instance tag in {}", header.0));
+                    return chain;
+                }
+            }
+        }
+
+        let highest = found.iter().map(|(instance, _, _)| *instance).max();
+        for instance in 1..=highest.unwrap_or(0) {
+            let mut headers: [Vec<&(String, String)>; 3] = Default::default();
+            for (_, kind, header) in found.iter().filter(|(i, _, _)| *i == instance) {
+                headers[*kind].push(header);
+            }
+            if headers.iter().any(|headers| headers.len() != 1) {
+                chain.error = Some(format!("incomplete or duplicate ARC set i={}", instance));
+                return chain;
+            }
+            chain.sets.push(ArcSet {
+                instance,
+                authentication_results: headers[0][0].clone(),
+                message_signature: headers[1][0].clone(),
+                seal: headers[2][0].clone(),
+            });
+        }
+
+        chain
+    }
+
+    pub fn sets(&self) -> &[ArcSet] {
+        &self.sets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty() && self.error.is_none()
+    }
+
+    /// The `cv=` value of the most recent ARC-Seal.
+    pub fn last_validation(&self) -> Option<String> {
+        self.sets.last().and_then(|set| set.seal_tag("cv"))
+    }
+
+    /// Validates the chain following RFC 8617, section 5.2.
+    pub fn validate(&self, message: &Message, resolver: &dyn KeyResolver) -> ChainValidation {
+        if let Some(error) = &self.error {
+            return ChainValidation::Fail(error.clone());
+        }
+        let Some(last) = self.sets.last() else {
+            return ChainValidation::None;
+        };
+
+        if self.last_validation().as_deref() == Some("fail") {
+            return ChainValidation::Fail(format!("ARC-Seal i={} reports cv=fail", last.instance));
+        }
+        for set in &self.sets {
+            let expected = if set.instance == 1 { "none" } else { "pass" };
+            if set.seal_tag("cv").as_deref() != Some(expected) {
+                return ChainValidation::Fail(format!(
+                    "ARC-Seal i={} does not have cv={}",
+                    set.instance, expected
+                ));
+            }
+        }
+
+        if let Err(reason) = verify_message_signature(message, last, resolver) {
+            return ChainValidation::Fail(reason);
+        }
+        for set in self.sets.iter().rev() {
+            if let Err(reason) = self.verify_seal(set, resolver) {
+                return ChainValidation::Fail(reason);
+            }
+        }
+
+        ChainValidation::Pass
+    }
+
+    fn verify_seal(&self, set: &ArcSet, resolver: &dyn KeyResolver) -> Result<(), String> {
+        let tags = parse_tags(&set.seal.1);
+        let mut data = seal_data(&self.sets[..set.instance as usize - 1]);
+        data.extend(seal_data_for(
+            &set.authentication_results,
+            &set.message_signature,
+            &set.seal.0,
+            &strip_signature(&set.seal.1),
+        ));
+        verify_tags(&tags, &data, resolver)
+            .map_err(|reason| format!("ARC-Seal i={}: {}", set.instance, reason))
+    }
+}
+
+fn verify_message_signature(
+    message: &Message,
+    set: &ArcSet,
+    resolver: &dyn KeyResolver,
+) -> Result<(), String> {
+    verify_signature(message, &set.message_signature, resolver)
+        .map_err(|err| format!("ARC-Message-Signature i={}: {}", set.instance, err))
+}
+
+fn seal_data_for(
+    authentication_results: &(String, String),
+    message_signature: &(String, String),
+    seal_name: &str,
+    seal_value: &str,
+) -> Vec<u8> {
+    signed_data(
+        Canonicalization::Relaxed,
+        &[authentication_results, message_signature],
+        seal_name,
+        seal_value,
+    )
+}
+
+fn seal_data(sets: &[ArcSet]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for set in sets {
+        data.extend(seal_data_for(
+            &set.authentication_results,
+            &set.message_signature,
+            &set.seal.0,
+            &set.seal.1,
+        ));
+        data.extend_from_slice(b"\r\n");
+    }
+    data
+}
+
+/// Adds a new ARC set to forwarded messages.
+#[derive(Debug, Clone)]
+pub struct ArcSealer {
+    authserv_id: String,
+    domain: String,
+    selector: String,
+    key: DkimKey,
+    headers: Vec<String>,
+}
+
+impl ArcSealer {
+    pub fn new(authserv_id: String, domain: String, selector: String, key: DkimKey) -> Self {
+        Self {
+            authserv_id,
+            domain,
+            selector,
+            key,
+            headers: crate::dkim::DEFAULT_SIGNED_HEADERS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+
+    /// Sets the headers covered by the ARC-Message-Signature.
+    pub fn with_headers(mut self, headers: Vec<String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Validates the chain of the request message and seals it.
+    ///
+    /// Returns no modifications for requests without a message.
+    pub fn seal_request(
+        &self,
+        request: &Request,
+        resolver: &dyn KeyResolver,
+        authentication_results: &str,
+    ) -> Result<Vec<Modification>, ArcError> {
+        let Some(message) = request.message.as_ref() else {
+            return Ok(Vec::new());
+        };
+        let chain = ArcChain::from_message(message);
+        let validation = chain.validate(message, resolver);
+        self.seal(message, &chain, &validation, authentication_results)
+    }
+
+    /// Produces the `ARC-Seal`, `ARC-Message-Signature` and
+    /// `ARC-Authentication-Results` insertions for the next instance.
+    pub fn seal(
+        &self,
+        message: &Message,
+        chain: &ArcChain,
+        validation: &ChainValidation,
+        authentication_results: &str,
+    ) -> Result<Vec<Modification>, ArcError> {
+        self.seal_at(
+            message,
+            chain,
+            validation,
+            authentication_results,
+            unix_time(),
+        )
+    }
+
+    /// Same as [`ArcSealer::seal`] with an explicit signing timestamp.
+    pub fn seal_at(
+        &self,
+        message: &Message,
+        chain: &ArcChain,
+        validation: &ChainValidation,
+        authentication_results: &str,
+        timestamp: u64,
+    ) -> Result<Vec<Modification>, ArcError> {
+        if chain.last_validation().as_deref() == Some("fail") {
+            return Err(ArcError::ChainFailed);
+        }
+        let instance = chain.sets().len() as u32 + 1;
+        if instance > MAX_INSTANCE {
+            return Err(ArcError::TooManyInstances);
+        }
+        let validation = match validation {
+            ChainValidation::None if instance > 1 => "fail",
+            validation => validation.as_str(),
+        };
+
+        let results = if authentication_results.trim().is_empty() {
+            "none"
+        } else {
+            authentication_results.trim()
+        };
+        let authentication_results = (
+            ARC_AUTHENTICATION_RESULTS.to_string(),
+            format!("i={}; {}; {}", instance, self.authserv_id, results),
+        );
+
+        let arc_headers = [ARC_AUTHENTICATION_RESULTS, ARC_MESSAGE_SIGNATURE, ARC_SEAL];
+        let names: Vec<String> = self
+            .headers
+            .iter()
+            .filter(|name| !arc_headers.iter().any(|arc| name.eq_ignore_ascii_case(arc)))
+            .cloned()
+            .collect();
+        let selected = select_headers(&message.headers, &names);
+        let selected_names: Vec<&str> = selected.iter().map(|(name, _)| name.as_str()).collect();
+        let mut signature = format!(
+            "i={}; a={}; c=relaxed/relaxed; d={}; s={}; t={}; h={}; bh={}; b=",
+            instance,
+            self.key.algorithm(),
+            self.domain,
+            self.selector,
+            timestamp,
+            selected_names.join(":"),
+            body_hash(Canonicalization::Relaxed, &message.contents)
+        );
+        let data = signed_data(
+            Canonicalization::Relaxed,
+            &selected,
+            ARC_MESSAGE_SIGNATURE,
+            &signature,
+        );
+        signature.push_str(&STANDARD.encode(self.key.sign(&data)?));
+        let message_signature = (ARC_MESSAGE_SIGNATURE.to_string(), signature);
+
+        let mut seal = format!(
+            "i={}; a={}; cv={}; d={}; s={}; t={}; b=",
+            instance,
+            self.key.algorithm(),
+            validation,
+            self.domain,
+            self.selector,
+            timestamp
+        );
+        let mut data = seal_data(chain.sets());
+        data.extend(seal_data_for(
+            &authentication_results,
+            &message_signature,
+            ARC_SEAL,
+            &seal,
+        ));
+        seal.push_str(&STANDARD.encode(self.key.sign(&data)?));
+
+        // Each insertion happens at the top, leaving ARC-Seal first.
+        Ok(vec![
+            Modification::insert_header(0, authentication_results.0, authentication_results.1),
+            Modification::insert_header(0, message_signature.0, message_signature.1),
+            Modification::insert_header(0, ARC_SEAL.to_string(), seal),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dkim::DkimPublicKey;
+    use ed25519_dalek::SigningKey;
+    use std::collections::HashMap;
+
+    fn message() -> Message {
+        Message {
+            headers: vec![
+                (
+                    "From".to_string(),
+                    "John Doe <john@example.com>".to_string(),
+                ),
+                ("To".to_string(), "list@example.org".to_string()),
+                ("Subject".to_string(), "Hello, World!".to_string()),
+            ],
+            server_headers: vec![],
+            contents: "Hello, World!\r\n".into(),
+            size: 100,
+        }
+    }
+
+    fn sealer(domain: &str, seed: u8) -> ArcSealer {
+        ArcSealer::new(
+            "mx.example.org".to_string(),
+            domain.to_string(),
+            "arc".to_string(),
+            DkimKey::Ed25519(Box::new(SigningKey::from_bytes(&[seed; 32]))),
+        )
+    }
+
+    fn resolver() -> HashMap<String, DkimPublicKey> {
+        let mut keys = HashMap::new();
+        for (domain, seed) in [("example.org", 1u8), ("example.net", 2u8)] {
+            let key = DkimKey::Ed25519(Box::new(SigningKey::from_bytes(&[seed; 32])));
+            keys.insert(format!("arc._domainkey.{}", domain), key.public_key());
+        }
+        keys
+    }
+
+    fn apply(message: &mut Message, modifications: Vec<Modification>) {
+        for modification in modifications {
+            match modification {
+                Modification::InsertHeader { index, name, value } => {
+                    message.headers.insert(index as usize, (name, value))
+                }
+                _ => panic!("Expected InsertHeader modification"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_chain() {
+        let message = message();
+        let chain = ArcChain::from_message(&message);
+        assert!(chain.is_empty());
+        assert_eq!(chain.validate(&message, &resolver()), ChainValidation::None);
+    }
+
+    #[test]
+    fn test_seal_and_validate_chain() {
+        let resolver = resolver();
+        let mut message = message();
+
+        let chain = ArcChain::from_message(&message);
+        let modifications = sealer("example.org", 1)
+            .seal_at(
+                &message,
+                &chain,
+                &ChainValidation::None,
+                "spf=pass",
+                1_700_000_000,
+            )
+            .unwrap();
+        assert_eq!(modifications.len(), 3);
+        apply(&mut message, modifications);
+        assert_eq!(message.headers[0].0, ARC_SEAL);
+        assert!(message.headers[0]
+            .1
+            .starts_with("i=1; a=ed25519-sha256; cv=none;"));
+        assert_eq!(message.headers[1].0, ARC_MESSAGE_SIGNATURE);
+        assert_eq!(message.headers[2].1, "i=1; mx.example.org; spf=pass");
+
+        let chain = ArcChain::from_message(&message);
+        assert_eq!(chain.sets().len(), 1);
+        assert_eq!(chain.validate(&message, &resolver), ChainValidation::Pass);
+
+        // A second hop adds instance 2 on top of a passing chain.
+        let validation = chain.validate(&message, &resolver);
+        let modifications = sealer("example.net", 2)
+            .seal(&message, &chain, &validation, "arc=pass")
+            .unwrap();
+        apply(&mut message, modifications);
+        let chain = ArcChain::from_message(&message);
+        assert_eq!(chain.sets().len(), 2);
+        assert_eq!(chain.last_validation().as_deref(), Some("pass"));
+        assert_eq!(chain.validate(&message, &resolver), ChainValidation::Pass);
+    }
+
+    #[test]
+    fn test_modified_body_fails() {
+        let resolver = resolver();
+        let mut message = message();
+        let chain = ArcChain::from_message(&message);
+        let modifications = sealer("example.org", 1)
+            .seal(&message, &chain, &ChainValidation::None, "")
+            .unwrap();
+        apply(&mut message, modifications);
+        message.contents = "Changed by a mailing list\r\n".into();
+
+        let chain = ArcChain::from_message(&message);
+        let validation = chain.validate(&message, &resolver);
+        assert!(
+            matches!(validation, ChainValidation::Fail(ref reason) if reason.contains("body hash"))
+        );
+
+        // The next hop records the failure, after which sealing stops.
+        let modifications = sealer("example.net", 2)
+            .seal(&message, &chain, &validation, "arc=fail")
+            .unwrap();
+        apply(&mut message, modifications);
+        let chain = ArcChain::from_message(&message);
+        assert_eq!(chain.last_validation().as_deref(), Some("fail"));
+        assert!(matches!(
+            sealer("example.org", 1).seal(&message, &chain, &ChainValidation::Pass, ""),
+            Err(ArcError::ChainFailed)
+        ));
+    }
+
+    #[test]
+    fn test_instance_tag_anywhere() {
+        let mut message = message();
+        for (name, value) in [
+            (ARC_SEAL, "a=rsa-sha256; i=1; cv=none; d=x; s=y; b="),
+            (
+                ARC_MESSAGE_SIGNATURE,
+                "a=rsa-sha256; d=x; s=y; i=1; h=from; bh=; b=",
+            ),
+            (ARC_AUTHENTICATION_RESULTS, "i=1; mx.example.org; spf=pass"),
+        ] {
+            message
+                .headers
+                .insert(0, (name.to_string(), value.to_string()));
+        }
+        let chain = ArcChain::from_message(&message);
+        assert_eq!(chain.sets().len(), 1);
+        assert_eq!(chain.sets()[0].instance, 1);
+
+        // The results header must still start with its instance.
+        message.headers[0].1 = "mx.example.org; i=1; spf=pass".to_string();
+        let chain = ArcChain::from_message(&message);
+        assert!(matches!(
+            chain.validate(&message, &resolver()),
+            ChainValidation::Fail(ref reason) if reason.contains("instance")
+        ));
+    }
+
+    #[test]
+    fn test_incomplete_set_fails() {
+        let mut message = message();
+        message.headers.insert(
+            0,
+            (
+                ARC_SEAL.to_string(),
+                "i=1; a=rsa-sha256; cv=none; d=x; s=y; b=".to_string(),
+            ),
+        );
+        let chain = ArcChain::from_message(&message);
+        assert!(matches!(
+            chain.validate(&message, &resolver()),
+            ChainValidation::Fail(_)
+        ));
+    }
+}
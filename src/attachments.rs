@@ -0,0 +1,741 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Attachment policies for messages at `Stage::Data`.
+//!
+//! Each leaf MIME part is checked for blocked file extensions, double
+//! extensions such as `invoice.pdf.exe`, blocked or mismatching content
+//! types detected from magic bytes, password-protected ZIP archives and
+//! size. ZIP and TAR archives can optionally be opened to check their
+//! members by name, size and encryption; the contents of compressed ZIP
+//! members are not inspected.
+//!
+//! Offending messages are either rejected or have the offending parts
+//! replaced by a short text notice.
+
+use crate::mime::{Body, Part};
+use crate::modifications::Modification;
+use crate::request::{Message, Request};
+use crate::response::{Action, Response, SmtpResponse};
+use std::fmt;
+
+/// Extensions of executables and scripts, blocked by default.
+pub const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "ade", "adp", "app", "bat", "chm", "cmd", "com", "cpl", "dll", "exe", "hta", "inf", "ins",
+    "isp", "jar", "js", "jse", "lib", "lnk", "msc", "msi", "msp", "mst", "pif", "ps1", "reg",
+    "scr", "sct", "shb", "shs", "sys", "vb", "vbe", "vbs", "vxd", "wsc", "wsf", "wsh",
+];
+
+/// Detected types of executables, blocked by default.
+pub const EXECUTABLE_TYPES: &[&str] = &["application/x-msdownload", "application/x-executable"];
+
+/// Returns the content type indicated by the leading bytes of `data`.
+pub fn sniff(data: &[u8]) -> Option<&'static str> {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"MZ", "application/x-msdownload"),
+        (b"\x7fELF", "application/x-executable"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"PK\x05\x06", "application/zip"),
+        (b"%PDF-", "application/pdf"),
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"Rar!\x1a\x07", "application/x-rar-compressed"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"\x1f\x8b", "application/gzip"),
+        (
+            b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1",
+            "application/x-ole-storage",
+        ),
+        (b"#!", "text/x-shellscript"),
+    ];
+    MAGIC
+        .iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|(_, content_type)| *content_type)
+        .or_else(|| (data.get(257..262) == Some(b"ustar")).then_some("application/x-tar"))
+}
+
+/// Whether a declared content type is plausible for a detected one.
+fn compatible(declared: &str, sniffed: &str) -> bool {
+    if declared == sniffed || declared == "application/octet-stream" {
+        return true;
+    }
+    match sniffed {
+        "application/zip" => {
+            declared == "application/x-zip-compressed"
+                || declared == "application/java-archive"
+                || declared == "application/epub+zip"
+                || declared.starts_with("application/vnd.openxmlformats-")
+                || declared.starts_with("application/vnd.oasis.opendocument.")
+                || declared.starts_with("application/vnd.ms-")
+        }
+        "application/x-ole-storage" => {
+            declared == "application/msword" || declared.starts_with("application/vnd.ms-")
+        }
+        "application/gzip" => declared == "application/x-gzip",
+        "image/jpeg" => declared == "image/jpg" || declared == "image/pjpeg",
+        "application/x-msdownload" => {
+            declared == "application/x-msdos-program" || declared == "application/x-dosexec"
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    BlockedExtension(String),
+    DoubleExtension,
+    BlockedType(String),
+    TypeMismatch { declared: String, sniffed: String },
+    Encrypted,
+    TooLarge(usize),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::BlockedExtension(extension) => write!(f, "blocked file type .{}", extension),
+            Reason::DoubleExtension => write!(f, "double file extension"),
+            Reason::BlockedType(content_type) => write!(f, "blocked content type {}", content_type),
+            Reason::TypeMismatch { declared, sniffed } => {
+                write!(f, "declared as {} but contains {}", declared, sniffed)
+            }
+            Reason::Encrypted => write!(f, "password-protected archive"),
+            Reason::TooLarge(size) => write!(f, "attachment too large ({} bytes)", size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Position of the part among the leaf parts, see `Part::leaves`.
+    pub part: usize,
+    pub filename: Option<String>,
+    /// The archive member the violation was found in.
+    pub member: Option<String>,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.filename, &self.member) {
+            (Some(filename), Some(member)) => {
+                write!(f, "{} ({}): {}", filename, member, self.reason)
+            }
+            (Some(name), None) | (None, Some(name)) => write!(f, "{}: {}", name, self.reason),
+            (None, None) => write!(f, "part {}: {}", self.part + 1, self.reason),
+        }
+    }
+}
+
+/// What happens to messages with violations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolicyAction {
+    #[default]
+    Reject,
+    /// Replaces the offending parts with a notice. Messages that are not
+    /// multipart or whose body is not UTF-8 are rejected instead.
+    Strip,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttachmentPolicy {
+    blocked_extensions: Vec<String>,
+    block_double_extensions: bool,
+    blocked_types: Vec<String>,
+    check_type_mismatch: bool,
+    block_encrypted: bool,
+    max_size: Option<usize>,
+    scan_archives: bool,
+    action: PolicyAction,
+    notice: String,
+}
+
+impl Default for AttachmentPolicy {
+    fn default() -> Self {
+        Self {
+            blocked_extensions: EXECUTABLE_EXTENSIONS
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            block_double_extensions: true,
+            blocked_types: EXECUTABLE_TYPES.iter().map(|t| t.to_string()).collect(),
+            check_type_mismatch: true,
+            block_encrypted: true,
+            max_size: None,
+            scan_archives: false,
+            action: PolicyAction::default(),
+            notice: "The attachment {filename} was removed: {reason}.".to_string(),
+        }
+    }
+}
+
+impl AttachmentPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the blocked file extensions, without the leading dot
+    /// ([`EXECUTABLE_EXTENSIONS`] by default).
+    pub fn with_blocked_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.blocked_extensions = extensions
+            .into_iter()
+            .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Blocks names like `invoice.pdf.exe` whose last extension is an
+    /// executable one (enabled by default).
+    pub fn with_double_extensions(mut self, block: bool) -> Self {
+        self.block_double_extensions = block;
+        self
+    }
+
+    /// Sets the content types blocked when detected from the magic bytes
+    /// ([`EXECUTABLE_TYPES`] by default).
+    pub fn with_blocked_types(mut self, content_types: Vec<String>) -> Self {
+        self.blocked_types = content_types;
+        self
+    }
+
+    /// Blocks parts whose declared type does not match the detected one
+    /// (enabled by default).
+    pub fn with_type_mismatch(mut self, check: bool) -> Self {
+        self.check_type_mismatch = check;
+        self
+    }
+
+    /// Blocks password-protected ZIP archives (enabled by default).
+    pub fn with_encrypted_archives(mut self, block: bool) -> Self {
+        self.block_encrypted = block;
+        self
+    }
+
+    /// Blocks attachments and archive members larger than `size` bytes
+    /// after decoding.
+    pub fn with_max_size(mut self, size: usize) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Checks the members of ZIP and TAR attachments as well.
+    pub fn with_archive_scanning(mut self, scan: bool) -> Self {
+        self.scan_archives = scan;
+        self
+    }
+
+    pub fn with_action(mut self, action: PolicyAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Sets the text replacing stripped parts; `{filename}` and `{reason}`
+    /// are substituted.
+    pub fn with_notice(mut self, notice: String) -> Self {
+        self.notice = notice;
+        self
+    }
+
+    /// Lists all violations in the message.
+    pub fn inspect(&self, message: &Message) -> Vec<Violation> {
+        let root = Part::from_message(message);
+        let mut violations = Vec::new();
+        for (pos, part) in root.leaves().into_iter().enumerate() {
+            let filename = part.filename();
+            let data = part.decoded_body();
+            let mut reasons = Vec::new();
+
+            if let Some(filename) = &filename {
+                self.check_name(filename, &mut reasons);
+            }
+            let sniffed = sniff(&data);
+            if let Some(sniffed) = sniffed {
+                if self.blocked_types.iter().any(|blocked| blocked == sniffed) {
+                    reasons.push(Reason::BlockedType(sniffed.to_string()));
+                } else if self.check_type_mismatch
+                    && (part.is_attachment() || part.header("Content-Type").is_some())
+                {
+                    let declared = part.content_type();
+                    if !declared.starts_with("text/") && !compatible(&declared, sniffed) {
+                        reasons.push(Reason::TypeMismatch {
+                            declared,
+                            sniffed: sniffed.to_string(),
+                        });
+                    }
+                }
+            }
+            if self.max_size.is_some_and(|max| data.len() > max) && part.is_attachment() {
+                reasons.push(Reason::TooLarge(data.len()));
+            }
+            violations.extend(reasons.into_iter().map(|reason| Violation {
+                part: pos,
+                filename: filename.clone(),
+                member: None,
+                reason,
+            }));
+
+            let members = match sniffed {
+                Some("application/zip") => zip_members(&data),
+                Some("application/x-tar") if self.scan_archives => tar_members(&data),
+                _ => Vec::new(),
+            };
+            for member in members {
+                let mut reasons = Vec::new();
+                if member.encrypted && self.block_encrypted {
+                    reasons.push(Reason::Encrypted);
+                }
+                if self.scan_archives {
+                    self.check_name(&member.name, &mut reasons);
+                    if let Some(sniffed) = member.data.and_then(sniff) {
+                        if self.blocked_types.iter().any(|blocked| blocked == sniffed) {
+                            reasons.push(Reason::BlockedType(sniffed.to_string()));
+                        }
+                    }
+                    if self.max_size.is_some_and(|max| member.size > max) {
+                        reasons.push(Reason::TooLarge(member.size));
+                    }
+                }
+                violations.extend(reasons.into_iter().map(|reason| Violation {
+                    part: pos,
+                    filename: filename.clone(),
+                    member: Some(member.name.clone()),
+                    reason,
+                }));
+            }
+        }
+        // An encrypted archive is reported once, not for every member.
+        violations.dedup_by(|a, b| {
+            a.part == b.part && a.reason == Reason::Encrypted && b.reason == Reason::Encrypted
+        });
+        violations
+    }
+
+    fn check_name(&self, name: &str, reasons: &mut Vec<Reason>) {
+        let name = name.trim_end_matches([' ', '.']).to_ascii_lowercase();
+        let base = name.rsplit(['/', '\\']).next().unwrap_or(&name);
+        let mut extensions = base.split('.').skip(1).map(str::trim).collect::<Vec<_>>();
+        let Some(last) = extensions.pop() else {
+            return;
+        };
+        if self
+            .blocked_extensions
+            .iter()
+            .any(|blocked| blocked == last)
+        {
+            reasons.push(Reason::BlockedExtension(last.to_string()));
+        }
+        if self.block_double_extensions
+            && !extensions.is_empty()
+            && EXECUTABLE_EXTENSIONS.contains(&last)
+        {
+            reasons.push(Reason::DoubleExtension);
+        }
+    }
+
+    /// Applies the policy to the message of the request, returning `None`
+    /// if there is nothing to object to.
+    pub fn apply(&self, request: &Request) -> Option<Response> {
+        self.apply_message(request.message.as_ref()?)
+    }
+
+    pub fn apply_message(&self, message: &Message) -> Option<Response> {
+        let violations = self.inspect(message);
+        let first = violations.first()?;
+        let mut root = Part::from_message(message);
+        if self.action == PolicyAction::Strip
+            && message.contents.is_utf8()
+            && matches!(root.body, Body::Multipart { .. })
+        {
+            let mut pos = 0;
+            self.strip(&mut root, &violations, &mut pos);
+            return Some(
+                Response::accept()
+                    .with_modifications(vec![Modification::replace_contents(root.body_string())]),
+            );
+        }
+        Some(Response {
+            action: Action::Reject,
+            response: Some(SmtpResponse {
+                status: Some(554),
+                enhanced_status: Some("5.7.1".to_string()),
+                message: Some(format!("Attachment not accepted: {}", first)),
+                disconnect: false,
+            }),
+            modifications: Vec::new(),
+        })
+    }
+
+    fn strip(&self, part: &mut Part, violations: &[Violation], pos: &mut usize) {
+        match &mut part.body {
+            Body::Multipart { parts, .. } => {
+                for part in parts {
+                    self.strip(part, violations, pos);
+                }
+            }
+            Body::Single(_) => {
+                if let Some(violation) = violations.iter().find(|v| v.part == *pos) {
+                    let filename = part.filename().unwrap_or_else(|| "attachment".to_string());
+                    let notice = self
+                        .notice
+                        .replace("{filename}", &filename)
+                        .replace("{reason}", &violation.reason.to_string());
+                    *part = Part {
+                        headers: vec![
+                            (
+                                "Content-Type".to_string(),
+                                "text/plain; charset=utf-8".to_string(),
+                            ),
+                            ("Content-Transfer-Encoding".to_string(), "8bit".to_string()),
+                        ],
+                        body: Body::Single(format!("{}\r\n", notice)),
+                    };
+                }
+                *pos += 1;
+            }
+        }
+    }
+}
+
+struct Member<'a> {
+    name: String,
+    size: usize,
+    encrypted: bool,
+    /// The member data when it is stored uncompressed.
+    data: Option<&'a [u8]>,
+}
+
+fn le16(data: &[u8], pos: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?) as usize)
+}
+
+fn le32(data: &[u8], pos: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize)
+}
+
+/// Reads the central directory of a ZIP archive.
+fn zip_members(data: &[u8]) -> Vec<Member<'_>> {
+    let search_from = data.len().saturating_sub(22 + 65535);
+    let Some(end) = (search_from..data.len().saturating_sub(21))
+        .rev()
+        .find(|pos| data[*pos..].starts_with(b"PK\x05\x06"))
+    else {
+        return Vec::new();
+    };
+    let (Some(count), Some(mut pos)) = (le16(data, end + 10), le32(data, end + 16)) else {
+        return Vec::new();
+    };
+
+    let mut members = Vec::new();
+    for _ in 0..count {
+        if !data
+            .get(pos..)
+            .is_some_and(|entry| entry.starts_with(b"PK\x01\x02"))
+        {
+            break;
+        }
+        let (Some(flags), Some(method), Some(compressed), Some(size)) = (
+            le16(data, pos + 8),
+            le16(data, pos + 10),
+            le32(data, pos + 20),
+            le32(data, pos + 24),
+        ) else {
+            break;
+        };
+        let (Some(name_len), Some(extra_len), Some(comment_len), Some(local)) = (
+            le16(data, pos + 28),
+            le16(data, pos + 30),
+            le16(data, pos + 32),
+            le32(data, pos + 42),
+        ) else {
+            break;
+        };
+        let Some(name) = data.get(pos + 46..pos + 46 + name_len) else {
+            break;
+        };
+        let stored = (method == 0)
+            .then(|| {
+                let start = local + 30 + le16(data, local + 26)? + le16(data, local + 28)?;
+                data.get(start..start + compressed)
+            })
+            .flatten();
+        members.push(Member {
+            name: String::from_utf8_lossy(name).into_owned(),
+            size,
+            encrypted: flags & 1 == 1,
+            data: stored.filter(|_| flags & 1 == 0),
+        });
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    members
+}
+
+/// Reads the headers of an uncompressed TAR archive.
+fn tar_members(data: &[u8]) -> Vec<Member<'_>> {
+    let field = |block: &[u8], range: std::ops::Range<usize>| {
+        let field = &block[range];
+        let end = field
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..end]).trim().to_string()
+    };
+    let mut members = Vec::new();
+    let mut pos = 0;
+    while let Some(block) = data.get(pos..pos + 512) {
+        if block.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        let Ok(size) = usize::from_str_radix(&field(block, 124..136), 8) else {
+            break;
+        };
+        let prefix = field(block, 345..500);
+        let name = match field(block, 0..100) {
+            name if prefix.is_empty() => name,
+            name => format!("{}/{}", prefix, name),
+        };
+        // Only regular files, typeflag '0' or NUL.
+        if matches!(block[156], b'0' | 0) {
+            members.push(Member {
+                name,
+                size,
+                encrypted: false,
+                data: data.get(pos + 512..pos + 512 + size),
+            });
+        }
+        pos += 512 + size.div_ceil(512) * 512;
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    /// A ZIP archive with stored entries `(name, data, encrypted)`.
+    fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for (name, data, encrypted) in entries {
+            let offset = out.len() as u32;
+            let flags: u16 = if *encrypted { 1 } else { 0 };
+            let mut header = Vec::new();
+            header.extend_from_slice(&flags.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes()); // stored
+            header.extend_from_slice(&[0; 8]); // time, date, crc
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+
+            out.extend_from_slice(b"PK\x03\x04\x14\x00");
+            out.extend_from_slice(&header);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(data);
+
+            central.extend_from_slice(b"PK\x01\x02\x14\x00\x14\x00");
+            central.extend_from_slice(&header);
+            central.extend_from_slice(&[0; 10]); // comment, disk, attributes
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = out.len() as u32;
+        out.extend_from_slice(&central);
+        out.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        out.extend_from_slice(&central_offset.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+
+    fn tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, data) in entries {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            let size = format!("{:011o}\0", data.len());
+            header[124..136].copy_from_slice(size.as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            out.extend_from_slice(&header);
+            out.extend_from_slice(data);
+            out.resize(out.len().div_ceil(512) * 512, 0);
+        }
+        out.resize(out.len() + 1024, 0);
+        out
+    }
+
+    fn message(attachments: &[(&str, &str, &[u8])]) -> Message {
+        let mut contents = "--b\r\nContent-Type: text/plain\r\n\r\nSee attached.\r\n".to_string();
+        for (filename, content_type, data) in attachments {
+            contents.push_str(&format!(
+                "--b\r\nContent-Type: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\n\
+                 Content-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+                content_type,
+                filename,
+                STANDARD.encode(data)
+            ));
+        }
+        contents.push_str("--b--\r\n");
+        Message {
+            headers: vec![(
+                "Content-Type".to_string(),
+                " multipart/mixed; boundary=\"b\"\r\n".to_string(),
+            )],
+            server_headers: Vec::new(),
+            size: contents.len(),
+            contents: contents.into(),
+        }
+    }
+
+    fn reasons(policy: &AttachmentPolicy, message: &Message) -> Vec<Reason> {
+        policy
+            .inspect(message)
+            .into_iter()
+            .map(|violation| violation.reason)
+            .collect()
+    }
+
+    #[test]
+    fn test_names_and_types() {
+        let policy = AttachmentPolicy::new();
+        let pdf: &[u8] = b"%PDF-1.7 ...";
+        assert!(reasons(&policy, &message(&[("report.pdf", "application/pdf", pdf)])).is_empty());
+        assert_eq!(
+            reasons(
+                &policy,
+                &message(&[("setup.EXE ", "application/octet-stream", b"data")])
+            ),
+            [Reason::BlockedExtension("exe".to_string())]
+        );
+        assert_eq!(
+            reasons(
+                &policy,
+                &message(&[("invoice.pdf.scr", "application/pdf", pdf)])
+            ),
+            [
+                Reason::BlockedExtension("scr".to_string()),
+                Reason::DoubleExtension,
+            ]
+        );
+        assert_eq!(
+            reasons(
+                &policy,
+                &message(&[("photo.jpg", "image/jpeg", b"MZ\x90\x00")])
+            ),
+            [Reason::BlockedType("application/x-msdownload".to_string())]
+        );
+        assert_eq!(
+            reasons(&policy, &message(&[("photo.jpg", "image/jpeg", pdf)])),
+            [Reason::TypeMismatch {
+                declared: "image/jpeg".to_string(),
+                sniffed: "application/pdf".to_string(),
+            }]
+        );
+        let docx = zip(&[("word/document.xml", b"<xml/>", false)]);
+        let docx_type = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+        assert!(reasons(&policy, &message(&[("letter.docx", docx_type, &docx)])).is_empty());
+
+        let sized = AttachmentPolicy::new().with_max_size(5);
+        assert_eq!(
+            reasons(&sized, &message(&[("report.pdf", "application/pdf", pdf)])),
+            [Reason::TooLarge(pdf.len())]
+        );
+    }
+
+    #[test]
+    fn test_archives() {
+        let encrypted = zip(&[("a.txt", b"x", true), ("b.txt", b"y", true)]);
+        let message_with =
+            |data: &[u8], name: &str, content_type: &str| message(&[(name, content_type, data)]);
+        assert_eq!(
+            reasons(
+                &AttachmentPolicy::new(),
+                &message_with(&encrypted, "secret.zip", "application/zip")
+            ),
+            [Reason::Encrypted]
+        );
+
+        let nested = zip(&[
+            ("readme.txt", b"hi", false),
+            ("tool.exe", b"MZ\x90\x00", false),
+        ]);
+        let message = message_with(&nested, "files.zip", "application/zip");
+        assert!(reasons(&AttachmentPolicy::new(), &message).is_empty());
+        let scanning = AttachmentPolicy::new().with_archive_scanning(true);
+        let violations = scanning.inspect(&message);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].member.as_deref(), Some("tool.exe"));
+        assert_eq!(
+            violations[0].reason,
+            Reason::BlockedExtension("exe".to_string())
+        );
+        assert_eq!(
+            violations[1].reason,
+            Reason::BlockedType("application/x-msdownload".to_string())
+        );
+
+        let archive = tar(&[
+            ("docs/notes.txt", b"notes"),
+            ("docs/run.sh.js", b"alert(1)"),
+        ]);
+        let violations = scanning.inspect(&message_with(&archive, "docs.tar", "application/x-tar"));
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| (v.member.as_deref().unwrap(), v.reason.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("docs/run.sh.js", Reason::BlockedExtension("js".to_string())),
+                ("docs/run.sh.js", Reason::DoubleExtension),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_responses() {
+        let message = message(&[
+            ("report.pdf", "application/pdf", b"%PDF-1.7"),
+            ("setup.exe", "application/octet-stream", b"MZ\x90\x00"),
+        ]);
+        let response = AttachmentPolicy::new().apply_message(&message).unwrap();
+        assert_eq!(response.action, Action::Reject);
+        assert_eq!(
+            response.response.unwrap().message.as_deref(),
+            Some("Attachment not accepted: setup.exe: blocked file type .exe")
+        );
+
+        let response = AttachmentPolicy::new()
+            .with_action(PolicyAction::Strip)
+            .apply_message(&message)
+            .unwrap();
+        assert_eq!(response.action, Action::Accept);
+        let Modification::ReplaceContents { value } = &response.modifications[0] else {
+            panic!("expected ReplaceContents");
+        };
+        let stripped = Part::from_message(&Message {
+            contents: value.clone(),
+            ..message.clone()
+        });
+        let leaves = stripped.leaves();
+        assert_eq!(leaves.len(), 3);
+        assert_eq!(leaves[1].filename().as_deref(), Some("report.pdf"));
+        assert_eq!(
+            leaves[2].decoded_text(),
+            "The attachment setup.exe was removed: blocked file type .exe.\r\n"
+        );
+
+        let clean = self::message(&[("report.pdf", "application/pdf", b"%PDF-1.7")]);
+        assert!(AttachmentPolicy::new().apply_message(&clean).is_none());
+    }
+}
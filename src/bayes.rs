@@ -0,0 +1,424 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! A token-based naive Bayes spam classifier.
+//!
+//! Messages are split into tokens from selected headers and the decoded
+//! text parts. Token counts are kept in a [`TokenStore`]; scoring combines
+//! the per-token probabilities with Robinson's chi-square method, giving a
+//! spam probability between 0 and 1.
+//!
+//! The `bayes-train` binary fills a [`MemoryTokenStore`] file from ham and
+//! spam maildirs or recorded request JSONL files.
+
+use crate::mime::{decode_encoded_words, header, Part};
+use crate::modifications::Modification;
+use crate::request::{Message, Request, Stage};
+use crate::response::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Headers whose words are used as tokens, prefixed with the header name.
+const TOKEN_HEADERS: &[&str] = &[
+    "Subject",
+    "From",
+    "Reply-To",
+    "To",
+    "Content-Type",
+    "X-Mailer",
+    "User-Agent",
+];
+
+const MIN_WORD: usize = 3;
+const MAX_WORD: usize = 40;
+
+/// How often a token was seen in spam and ham messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenCounts {
+    pub spam: u64,
+    pub ham: u64,
+}
+
+/// Storage for token counts and the number of trained messages.
+pub trait TokenStore {
+    fn counts(&self, token: &str) -> io::Result<TokenCounts>;
+    /// The number of trained spam and ham messages.
+    fn totals(&self) -> io::Result<TokenCounts>;
+    /// Counts one message with the given tokens.
+    fn learn(&self, tokens: &[String], spam: bool) -> io::Result<()>;
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Corpus {
+    messages: TokenCounts,
+    tokens: HashMap<String, TokenCounts>,
+}
+
+/// Keeps all counts in memory, optionally loaded from and saved to a JSON
+/// file.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    corpus: Mutex<Corpus>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a store saved with [`MemoryTokenStore::save`], starting empty
+    /// if the file does not exist.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let corpus = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Corpus::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            corpus: Mutex::new(corpus),
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = serde_json::to_vec(&*self.lock())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let path = path.as_ref();
+        let mut temporary = path.to_path_buf().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, data)?;
+        std::fs::rename(&temporary, path)
+    }
+
+    /// The number of distinct tokens.
+    pub fn len(&self) -> usize {
+        self.lock().tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Corpus> {
+        self.corpus.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn counts(&self, token: &str) -> io::Result<TokenCounts> {
+        Ok(self.lock().tokens.get(token).copied().unwrap_or_default())
+    }
+
+    fn totals(&self) -> io::Result<TokenCounts> {
+        Ok(self.lock().messages)
+    }
+
+    fn learn(&self, tokens: &[String], spam: bool) -> io::Result<()> {
+        let mut corpus = self.lock();
+        let bump = |counts: &mut TokenCounts| match spam {
+            true => counts.spam += 1,
+            false => counts.ham += 1,
+        };
+        bump(&mut corpus.messages);
+        for token in tokens {
+            bump(corpus.tokens.entry(token.clone()).or_default());
+        }
+        Ok(())
+    }
+}
+
+/// Splits a message into its distinct tokens.
+pub fn tokenize(message: &Message) -> Vec<String> {
+    let mut tokens = BTreeSet::new();
+    for name in TOKEN_HEADERS {
+        if let Some(value) = header(&message.headers, name) {
+            let prefix = name.to_ascii_lowercase();
+            words(&decode_encoded_words(value), &prefix, &mut tokens);
+        }
+    }
+    for part in Part::from_message(message).leaves() {
+        let content_type = part.content_type();
+        if content_type == "text/html" {
+            words(&strip_tags(&part.decoded_text()), "", &mut tokens);
+        } else if content_type.starts_with("text/") {
+            words(&part.decoded_text(), "", &mut tokens);
+        }
+    }
+    tokens.into_iter().collect()
+}
+
+fn words(text: &str, prefix: &str, tokens: &mut BTreeSet<String>) {
+    for word in text.split_whitespace() {
+        let word = word.trim_matches(|ch: char| !ch.is_alphanumeric() && ch != '$');
+        let lower = word.to_lowercase();
+        if let Some(url) = lower
+            .strip_prefix("http://")
+            .or_else(|| lower.strip_prefix("https://"))
+        {
+            let host = url.split(['/', '?', '#', ':']).next().unwrap_or_default();
+            tokens.insert(format!("url:{}", host));
+            continue;
+        }
+        let len = lower.chars().count();
+        let token = if len > MAX_WORD {
+            // Long words are mostly encoded data; keep only their shape.
+            format!(
+                "skip:{}:{}",
+                lower.chars().next().unwrap_or_default(),
+                len / 10 * 10
+            )
+        } else if len >= MIN_WORD {
+            lower
+        } else {
+            continue;
+        };
+        tokens.insert(match prefix {
+            "" => token,
+            prefix => format!("{}:{}", prefix, token),
+        });
+    }
+}
+
+/// Replaces HTML tags with spaces.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for ch in html.chars() {
+        match ch {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            ch if !in_tag => text.push(ch),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Scores and trains messages against a token store.
+#[derive(Debug)]
+pub struct BayesClassifier<S: TokenStore> {
+    store: S,
+    threshold: f64,
+    min_learned: u64,
+    max_tokens: usize,
+}
+
+impl<S: TokenStore> BayesClassifier<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            threshold: 0.9,
+            min_learned: 20,
+            max_tokens: 150,
+        }
+    }
+
+    /// Sets the probability from which messages count as spam (0.9 by
+    /// default).
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets how many spam and how many ham messages must have been trained
+    /// before messages are scored (20 each by default).
+    pub fn with_min_learned(mut self, min_learned: u64) -> Self {
+        self.min_learned = min_learned;
+        self
+    }
+
+    /// Sets how many of the most significant tokens are combined (150 by
+    /// default).
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens.max(1);
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn learn(&self, message: &Message, spam: bool) -> io::Result<()> {
+        self.store.learn(&tokenize(message), spam)
+    }
+
+    /// Trains a raw RFC 5322 message.
+    pub fn learn_raw(&self, raw: impl AsRef<[u8]>, spam: bool) -> io::Result<()> {
+        self.learn(&Message::parse(raw), spam)
+    }
+
+    /// The spam probability of the message, or `None` while too few
+    /// messages have been trained.
+    pub fn classify(&self, message: &Message) -> io::Result<Option<f64>> {
+        let totals = self.store.totals()?;
+        if totals.spam < self.min_learned.max(1) || totals.ham < self.min_learned.max(1) {
+            return Ok(None);
+        }
+
+        let mut probabilities = Vec::new();
+        for token in tokenize(message) {
+            let counts = self.store.counts(&token)?;
+            let seen = (counts.spam + counts.ham) as f64;
+            if seen == 0.0 {
+                continue;
+            }
+            let spam_ratio = counts.spam as f64 / totals.spam as f64;
+            let ham_ratio = counts.ham as f64 / totals.ham as f64;
+            let probability = spam_ratio / (spam_ratio + ham_ratio);
+            // Robinson's adjustment towards 0.5 for rarely seen tokens.
+            let adjusted = (0.5 + seen * probability) / (1.0 + seen);
+            if (adjusted - 0.5).abs() >= 0.1 {
+                probabilities.push(adjusted.clamp(0.01, 0.99));
+            }
+        }
+        if probabilities.is_empty() {
+            return Ok(Some(0.5));
+        }
+        probabilities.sort_by(|a, b| (b - 0.5).abs().total_cmp(&(a - 0.5).abs()));
+        probabilities.truncate(self.max_tokens);
+
+        let n = probabilities.len();
+        let ham_evidence = inverse_chi_square(
+            -2.0 * probabilities.iter().map(|p| p.ln()).sum::<f64>(),
+            2 * n,
+        );
+        let spam_evidence = inverse_chi_square(
+            -2.0 * probabilities.iter().map(|p| (1.0 - p).ln()).sum::<f64>(),
+            2 * n,
+        );
+        Ok(Some((1.0 + ham_evidence - spam_evidence) / 2.0))
+    }
+
+    /// The `X-Spam-Score` and `X-Spam-Status` headers for a probability.
+    pub fn headers(&self, probability: f64) -> Vec<Modification> {
+        let spam = probability >= self.threshold;
+        vec![
+            Modification::add_header("X-Spam-Score".to_string(), format!("{:.2}", probability)),
+            Modification::add_header(
+                "X-Spam-Status".to_string(),
+                format!(
+                    "{}, score={:.2} required={:.2} tests=BAYES",
+                    if spam { "Yes" } else { "No" },
+                    probability,
+                    self.threshold
+                ),
+            ),
+        ]
+    }
+
+    /// Scores the message at `Stage::Data`, accepting it with the spam
+    /// headers. Returns `None` at other stages and while untrained.
+    pub fn check(&self, request: &Request) -> io::Result<Option<Response>> {
+        let message = match (&request.message, request.context.stage) {
+            (Some(message), Stage::Data) => message,
+            _ => return Ok(None),
+        };
+        Ok(self
+            .classify(message)?
+            .map(|probability| Response::accept().with_modifications(self.headers(probability))))
+    }
+}
+
+/// The probability that a chi-square distributed value with `freedom`
+/// (even) degrees of freedom is at least `chi`.
+fn inverse_chi_square(chi: f64, freedom: usize) -> f64 {
+    let m = chi / 2.0;
+    let mut term = (-m).exp();
+    let mut sum = term;
+    for i in 1..freedom / 2 {
+        term *= m / i as f64;
+        sum += term;
+    }
+    sum.min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPAM: &[&str] = &[
+        "Subject: Cheap watches\r\n\r\nBuy cheap replica watches now! Visit https://cheap.example.net/offer\r\n",
+        "Subject: You won\r\n\r\nClaim your prize money now, cheap offer inside\r\n",
+        "Subject: Cheap pills\r\nContent-Type: text/html\r\n\r\n<p>Buy <b>cheap</b> pills, limited offer!</p>\r\n",
+    ];
+    const HAM: &[&str] = &[
+        "Subject: Meeting notes\r\n\r\nHere are the notes from the project meeting yesterday.\r\n",
+        "Subject: Lunch\r\n\r\nShall we meet for lunch after the project review?\r\n",
+        "Subject: Re: Project review\r\n\r\nThe review went well, notes attached tomorrow.\r\n",
+    ];
+
+    fn trained() -> BayesClassifier<MemoryTokenStore> {
+        let classifier = BayesClassifier::new(MemoryTokenStore::new()).with_min_learned(3);
+        for raw in SPAM {
+            classifier.learn_raw(raw, true).unwrap();
+        }
+        for raw in HAM {
+            classifier.learn_raw(raw, false).unwrap();
+        }
+        classifier
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize(&Message::parse(
+            "Subject: =?UTF-8?Q?Gro=C3=9Fe?= offer\r\nContent-Type: multipart/alternative; boundary=b\r\n\r\n\
+             --b\r\nContent-Type: text/html\r\n\r\n<a href=\"x\">Visit https://Spam.example.com/buy now!</a>\r\n\
+             --b\r\nContent-Type: image/png\r\n\r\nnot text\r\n--b--\r\n",
+        ));
+        assert!(tokens.contains(&"subject:große".to_string()));
+        assert!(tokens.contains(&"subject:offer".to_string()));
+        assert!(tokens.contains(&"url:spam.example.com".to_string()));
+        assert!(tokens.contains(&"visit".to_string()));
+        assert!(tokens.contains(&"now".to_string()));
+        assert!(!tokens.contains(&"href".to_string()));
+        assert!(!tokens.contains(&"text".to_string()));
+    }
+
+    #[test]
+    fn test_classify() {
+        let untrained = BayesClassifier::new(MemoryTokenStore::new());
+        assert_eq!(untrained.classify(&Message::parse(SPAM[0])).unwrap(), None);
+
+        let classifier = trained();
+        let spam =
+            Message::parse("Subject: Cheap offer\r\n\r\nCheap watches and pills, buy now!\r\n");
+        let ham =
+            Message::parse("Subject: Project meeting\r\n\r\nNotes from the review meeting.\r\n");
+        assert!(classifier.classify(&spam).unwrap().unwrap() > 0.9);
+        assert!(classifier.classify(&ham).unwrap().unwrap() < 0.1);
+
+        let headers = classifier.headers(0.95);
+        match &headers[1] {
+            Modification::AddHeader { name, value } => {
+                assert_eq!(name, "X-Spam-Status");
+                assert_eq!(value, "Yes, score=0.95 required=0.90 tests=BAYES");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_store_persistence() {
+        let path = std::env::temp_dir().join(format!("bayes-{}.json", std::process::id()));
+        let classifier = trained();
+        classifier.store().save(&path).unwrap();
+
+        let loaded = MemoryTokenStore::load(&path).unwrap();
+        assert_eq!(loaded.len(), classifier.store().len());
+        assert_eq!(loaded.totals().unwrap(), TokenCounts { spam: 3, ham: 3 });
+        assert_eq!(
+            loaded.counts("cheap").unwrap(),
+            TokenCounts { spam: 3, ham: 0 }
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
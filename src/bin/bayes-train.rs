@@ -0,0 +1,141 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Trains the Bayes token database from ham and spam corpora.
+//!
+//! ```text
+//! bayes-train <database.json> [--spam <source>]... [--ham <source>]...
+//! ```
+//!
+//! A source is a maildir (its `cur` and `new` folders are read), a plain
+//! directory of message files, a single message file, or a `.jsonl` file
+//! of recorded hook requests, one JSON `Request` per line.
+
+use stalwart_mta_hook_types::bayes::{BayesClassifier, MemoryTokenStore, TokenStore};
+use stalwart_mta_hook_types::Request;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: bayes-train <database.json> [--spam <source>]... [--ham <source>]...";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(database) = args.next().filter(|arg| !arg.starts_with('-')) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let mut sources = Vec::new();
+    while let Some(arg) = args.next() {
+        let spam = match arg.as_str() {
+            "--spam" => true,
+            "--ham" => false,
+            _ => {
+                eprintln!("unexpected argument {}\n{}", arg, USAGE);
+                return ExitCode::FAILURE;
+            }
+        };
+        let Some(source) = args.next() else {
+            eprintln!("{} requires a source\n{}", arg, USAGE);
+            return ExitCode::FAILURE;
+        };
+        sources.push((PathBuf::from(source), spam));
+    }
+
+    match train(Path::new(&database), &sources) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("training failed: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn train(database: &Path, sources: &[(PathBuf, bool)]) -> io::Result<()> {
+    let classifier = BayesClassifier::new(MemoryTokenStore::load(database)?);
+    for (source, spam) in sources {
+        let learned = learn_source(&classifier, source, *spam)?;
+        println!(
+            "{}: learned {} {} messages",
+            source.display(),
+            learned,
+            if *spam { "spam" } else { "ham" }
+        );
+    }
+    let totals = classifier.store().totals()?;
+    println!(
+        "{}: {} spam, {} ham, {} tokens",
+        database.display(),
+        totals.spam,
+        totals.ham,
+        classifier.store().len()
+    );
+    classifier.store().save(database)
+}
+
+fn learn_source(
+    classifier: &BayesClassifier<MemoryTokenStore>,
+    source: &Path,
+    spam: bool,
+) -> io::Result<usize> {
+    if source.is_dir() {
+        let maildir = ["cur", "new"].map(|folder| source.join(folder));
+        let folders: Vec<PathBuf> = if maildir.iter().any(|folder| folder.is_dir()) {
+            maildir
+                .into_iter()
+                .filter(|folder| folder.is_dir())
+                .collect()
+        } else {
+            vec![source.to_path_buf()]
+        };
+        let mut learned = 0;
+        for folder in folders {
+            for entry in std::fs::read_dir(folder)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    learn_file(classifier, &path, spam)?;
+                    learned += 1;
+                }
+            }
+        }
+        Ok(learned)
+    } else if source
+        .extension()
+        .is_some_and(|extension| extension == "jsonl")
+    {
+        let file = io::BufReader::new(std::fs::File::open(source)?);
+        let mut learned = 0;
+        for (number, line) in file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request: Request = serde_json::from_str(&line).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", source.display(), number + 1, err),
+                )
+            })?;
+            if let Some(message) = &request.message {
+                classifier.learn(message, spam)?;
+                learned += 1;
+            }
+        }
+        Ok(learned)
+    } else {
+        learn_file(classifier, source, spam)?;
+        Ok(1)
+    }
+}
+
+fn learn_file(
+    classifier: &BayesClassifier<MemoryTokenStore>,
+    path: &Path,
+    spam: bool,
+) -> io::Result<()> {
+    classifier.learn_raw(std::fs::read(path)?, spam)
+}
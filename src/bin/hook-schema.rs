@@ -0,0 +1,41 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Prints the JSON Schema of requests or responses, or an OpenAPI
+//! document for a hook endpoint.
+//!
+//! ```text
+//! hook-schema request|response|openapi [<path>]
+//! ```
+
+use stalwart_mta_hook_types::schema;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: hook-schema request|response|openapi [<path>]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let document = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["request"] => schema::request_schema().to_value(),
+        ["response"] => schema::response_schema().to_value(),
+        ["openapi"] => schema::openapi("/"),
+        ["openapi", path] => schema::openapi(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match serde_json::to_string_pretty(&document) {
+        Ok(json) => {
+            println!("{}", json);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("cannot serialize schema: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
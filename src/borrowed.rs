@@ -0,0 +1,417 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Requests borrowing from the input buffer.
+//!
+//! [`RequestRef`] mirrors [`Request`] for JSON given as a slice or string.
+//! Addresses and header names point into the JSON when they contain no
+//! escapes. Header values and the body, which carry most of the data and
+//! always contain escaped line ends, are not decoded at all: they are kept
+//! as [`RawStr`] and [`RawContents`], slices of the JSON text that are
+//! decoded when accessed, and [`RequestRef::into_owned`] decodes them into a
+//! [`Request`]. A hook that only looks at the envelope or a few headers
+//! never copies the body. The small connection context is parsed into the
+//! owned [`Context`]. Run `cargo bench` to compare owned and borrowed
+//! parsing.
+//!
+//! ```
+//! use stalwart_mta_hook_types::borrowed::RequestRef;
+//! use stalwart_mta_hook_types::Request;
+//! use std::borrow::Cow;
+//!
+//! let json = r#"{"context":{"stage":"data","client":{"ip":"192.0.2.1",
+//!     "port":49152,"activeConnections":1},"server":{"port":25},
+//!     "protocol":{"version":1}},"envelope":{"from":{"address":"john@example.com"},
+//!     "to":[{"address":"bill@example.org"}]},"message":{"headers":[["Subject",
+//!     " Hello\r\n"]],"contents":"Hi Bill\r\n","size":29}}"#;
+//! let request: RequestRef = serde_json::from_str(json).unwrap();
+//! let envelope = request.envelope.as_ref().unwrap();
+//! assert!(matches!(envelope.from.address, Cow::Borrowed("john@example.com")));
+//! let message = request.message.as_ref().unwrap();
+//! assert_eq!(message.headers[0].1.as_json(), r#"" Hello\r\n""#);
+//! assert_eq!(message.headers[0].1.decode().unwrap(), " Hello\r\n");
+//!
+//! let owned: Request = request.into_owned().unwrap();
+//! assert_eq!(owned.message.unwrap().contents, "Hi Bill\r\n");
+//! ```
+
+use crate::contents::Contents;
+use crate::request::{deserialize_string_or_int_map, Address, Context, Envelope, Message, Request};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A header name and raw value.
+pub type HeaderRef<'a> = (Cow<'a, str>, RawStr<'a>);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequestRef<'a> {
+    pub context: Context,
+    #[serde(borrow, default)]
+    pub envelope: Option<EnvelopeRef<'a>>,
+    #[serde(borrow, default)]
+    pub message: Option<MessageRef<'a>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnvelopeRef<'a> {
+    #[serde(borrow)]
+    pub from: AddressRef<'a>,
+    #[serde(borrow)]
+    pub to: Vec<AddressRef<'a>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddressRef<'a> {
+    #[serde(borrow)]
+    pub address: Cow<'a, str>,
+    #[serde(deserialize_with = "deserialize_string_or_int_map")]
+    #[serde(default)]
+    pub parameters: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageRef<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_headers")]
+    pub headers: Vec<HeaderRef<'a>>,
+    #[serde(rename = "serverHeaders")]
+    #[serde(borrow, default, deserialize_with = "deserialize_headers")]
+    pub server_headers: Vec<HeaderRef<'a>>,
+    #[serde(borrow)]
+    pub contents: RawContents<'a>,
+    pub size: usize,
+}
+
+/// A JSON string as written, with its escapes, decoded when accessed.
+#[derive(Debug, Clone, Copy)]
+pub struct RawStr<'a>(&'a RawValue);
+
+impl<'a> RawStr<'a> {
+    /// The JSON text of the string, including quotes and escapes.
+    pub fn as_json(&self) -> &'a str {
+        self.0.get()
+    }
+
+    /// The decoded string, borrowed if it contains no escapes. Fails on
+    /// escapes of unpaired surrogates, which are not checked when parsing.
+    pub fn decode(&self) -> Result<Cow<'a, str>, serde_json::Error> {
+        serde_json::from_str::<CowStr<'a>>(self.0.get()).map(|value| value.0)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawStr<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = <&'a RawValue>::deserialize(deserializer)?;
+        if !raw.get().starts_with('"') {
+            return Err(de::Error::custom("expected a string"));
+        }
+        Ok(RawStr(raw))
+    }
+}
+
+/// Message contents as written in the JSON, decoded when accessed.
+#[derive(Debug, Clone, Copy)]
+pub struct RawContents<'a>(&'a RawValue);
+
+impl<'a> RawContents<'a> {
+    /// The JSON text of the contents.
+    pub fn as_json(&self) -> &'a str {
+        self.0.get()
+    }
+
+    /// The decoded contents, read as [`Contents`] does and borrowed if they
+    /// contain no escapes.
+    pub fn decode(&self) -> Result<Cow<'a, [u8]>, serde_json::Error> {
+        let mut deserializer = serde_json::Deserializer::from_str(self.0.get());
+        deserialize_contents(&mut deserializer)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawContents<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = <&'a RawValue>::deserialize(deserializer)?;
+        if !raw.get().starts_with(['"', '[']) {
+            return Err(de::Error::custom(
+                "expected a string or a sequence of bytes",
+            ));
+        }
+        Ok(RawContents(raw))
+    }
+}
+
+impl RequestRef<'_> {
+    /// Decodes the request into an owned [`Request`].
+    pub fn into_owned(self) -> Result<Request, serde_json::Error> {
+        Ok(Request {
+            context: self.context,
+            envelope: self.envelope.map(EnvelopeRef::into_owned),
+            message: self.message.map(MessageRef::into_owned).transpose()?,
+        })
+    }
+}
+
+impl EnvelopeRef<'_> {
+    pub fn into_owned(self) -> Envelope {
+        Envelope {
+            from: self.from.into_owned(),
+            to: self.to.into_iter().map(AddressRef::into_owned).collect(),
+        }
+    }
+}
+
+impl AddressRef<'_> {
+    pub fn into_owned(self) -> Address {
+        Address {
+            address: self.address.into_owned(),
+            parameters: self.parameters,
+        }
+    }
+}
+
+impl MessageRef<'_> {
+    /// Decodes the headers and contents into an owned [`Message`].
+    pub fn into_owned(self) -> Result<Message, serde_json::Error> {
+        let owned = |headers: Vec<HeaderRef>| {
+            headers
+                .into_iter()
+                .map(|(name, value)| Ok((name.into_owned(), value.decode()?.into_owned())))
+                .collect::<Result<Vec<_>, serde_json::Error>>()
+        };
+        Ok(Message {
+            headers: owned(self.headers)?,
+            server_headers: owned(self.server_headers)?,
+            contents: Contents::from(self.contents.decode()?.into_owned()),
+            size: self.size,
+        })
+    }
+}
+
+impl TryFrom<RequestRef<'_>> for Request {
+    type Error = serde_json::Error;
+
+    fn try_from(request: RequestRef<'_>) -> Result<Self, Self::Error> {
+        request.into_owned()
+    }
+}
+
+impl From<EnvelopeRef<'_>> for Envelope {
+    fn from(envelope: EnvelopeRef<'_>) -> Self {
+        envelope.into_owned()
+    }
+}
+
+impl From<AddressRef<'_>> for Address {
+    fn from(address: AddressRef<'_>) -> Self {
+        address.into_owned()
+    }
+}
+
+impl TryFrom<MessageRef<'_>> for Message {
+    type Error = serde_json::Error;
+
+    fn try_from(message: MessageRef<'_>) -> Result<Self, Self::Error> {
+        message.into_owned()
+    }
+}
+
+/// A string that borrows from the input where possible, also inside
+/// sequences, where serde's `Cow` implementation always copies.
+struct CowStr<'a>(Cow<'a, str>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for CowStr<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CowStrVisitor;
+
+        impl<'de> Visitor<'de> for CowStrVisitor {
+            type Value = CowStr<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a string")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<Self::Value, E> {
+                Ok(CowStr(Cow::Borrowed(value)))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(CowStr(Cow::Owned(value.to_string())))
+            }
+
+            fn visit_string<E: de::Error>(self, value: String) -> Result<Self::Value, E> {
+                Ok(CowStr(Cow::Owned(value)))
+            }
+        }
+
+        deserializer.deserialize_str(CowStrVisitor)
+    }
+}
+
+fn deserialize_headers<'de: 'a, 'a, D>(deserializer: D) -> Result<Vec<HeaderRef<'a>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let headers: Vec<(CowStr<'a>, RawStr<'a>)> = Vec::deserialize(deserializer)?;
+    Ok(headers
+        .into_iter()
+        .map(|(name, value)| (name.0, value))
+        .collect())
+}
+
+/// Reads contents as [`Contents`] does, borrowing unescaped strings and
+/// byte strings.
+fn deserialize_contents<'de, D>(deserializer: D) -> Result<Cow<'de, [u8]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct ContentsVisitor;
+
+    impl<'de> Visitor<'de> for ContentsVisitor {
+        type Value = Cow<'de, [u8]>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a string or a sequence of bytes")
+        }
+
+        fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<Self::Value, E> {
+            Ok(Cow::Borrowed(value.as_bytes()))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value.as_bytes().to_vec()))
+        }
+
+        fn visit_string<E: de::Error>(self, value: String) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value.into_bytes()))
+        }
+
+        fn visit_borrowed_bytes<E: de::Error>(self, value: &'de [u8]) -> Result<Self::Value, E> {
+            Ok(Cow::Borrowed(value))
+        }
+
+        fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value.to_vec()))
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element::<u8>()? {
+                bytes.push(byte);
+            }
+            Ok(Cow::Owned(bytes))
+        }
+    }
+
+    deserializer.deserialize_any(ContentsVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Stage;
+
+    #[test]
+    fn test_borrowed_request() {
+        let request = Request::builder()
+            .stage(Stage::Data)
+            .from("john@example.com")
+            .from_param("SIZE", "1024")
+            .to("bill@example.org")
+            .raw_message("Subject: Hello\r\n\r\nHello Bill,\r\nsee you tomorrow.\r\n")
+            .build();
+        let json = serde_json::to_string(&request).unwrap();
+        let borrowed: RequestRef = serde_json::from_str(&json).unwrap();
+
+        let envelope = borrowed.envelope.as_ref().unwrap();
+        assert!(matches!(envelope.from.address, Cow::Borrowed(_)));
+        assert_eq!(envelope.from.parameters.as_ref().unwrap()["SIZE"], "1024");
+        let message = borrowed.message.as_ref().unwrap();
+        assert!(matches!(message.headers[0].0, Cow::Borrowed("Subject")));
+
+        // Header values and the body stay slices of the escaped JSON.
+        let within = |raw: &str| json.as_bytes().as_ptr_range().contains(&raw.as_ptr());
+        assert_eq!(message.headers[0].1.as_json(), r#"" Hello\r\n""#);
+        assert!(within(message.headers[0].1.as_json()));
+        assert_eq!(
+            message.contents.as_json(),
+            r#""Hello Bill,\r\nsee you tomorrow.\r\n""#
+        );
+        assert!(within(message.contents.as_json()));
+        assert_eq!(
+            message.contents.decode().unwrap().as_ref(),
+            b"Hello Bill,\r\nsee you tomorrow.\r\n"
+        );
+
+        let owned = borrowed.into_owned().unwrap();
+        assert_eq!(serde_json::to_string(&owned).unwrap(), json);
+    }
+
+    #[test]
+    fn test_borrowed_binary_contents() {
+        let request = Request::builder()
+            .raw_message(b"Subject: Hi\r\n\r\ncaf\xe9\r\n")
+            .build();
+        let json = serde_json::to_vec(&request).unwrap();
+        let borrowed: RequestRef = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            borrowed
+                .message
+                .as_ref()
+                .unwrap()
+                .contents
+                .decode()
+                .unwrap()
+                .as_ref(),
+            "caf\u{fffd}\r\n".as_bytes()
+        );
+        assert!(borrowed.envelope.is_none());
+
+        // Arrays of byte values are still accepted.
+        let mut value = serde_json::to_value(&request).unwrap();
+        value["message"]["contents"] = serde_json::json!(b"caf\xe9\r\n");
+        let json = serde_json::to_vec(&value).unwrap();
+        let borrowed: RequestRef = serde_json::from_slice(&json).unwrap();
+        let owned = Request::try_from(borrowed).unwrap();
+        assert_eq!(owned.message.unwrap().contents, b"caf\xe9\r\n"[..]);
+    }
+
+    #[test]
+    fn test_borrowed_invalid_message() {
+        let mut value =
+            serde_json::to_value(Request::builder().raw_message("A: b\r\n\r\n").build()).unwrap();
+
+        // Values of the wrong type are rejected when parsing.
+        value["message"]["headers"][0][1] = serde_json::json!(42);
+        let json = serde_json::to_string(&value).unwrap();
+        assert!(serde_json::from_str::<RequestRef>(&json).is_err());
+        value["message"]["headers"][0][1] = serde_json::json!(" b\r\n");
+        value["message"]["contents"] = serde_json::json!({"text": "body"});
+        let json = serde_json::to_string(&value).unwrap();
+        assert!(serde_json::from_str::<RequestRef>(&json).is_err());
+
+        // Unpaired surrogates are only found when decoding.
+        value["message"]["contents"] = serde_json::json!("body");
+        let json = serde_json::to_string(&value)
+            .unwrap()
+            .replace("body", "\\ud800");
+        let borrowed: RequestRef = serde_json::from_str(&json).unwrap();
+        assert!(borrowed
+            .message
+            .as_ref()
+            .unwrap()
+            .contents
+            .decode()
+            .is_err());
+        assert!(borrowed.into_owned().is_err());
+        assert!(serde_json::from_str::<Request>(&json).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! A builder for requests, mainly for tests.
+//!
+//! The defaults describe a plain SMTP connection from `192.0.2.1` to port
+//! 25 of `mx.example.org` at `Stage::Connect`, with protocol version 1 and
+//! neither envelope nor message.
+//!
+//! ```
+//! use stalwart_mta_hook_types::{Request, Stage};
+//!
+//! let request = Request::builder()
+//!     .stage(Stage::Rcpt)
+//!     .from("john@example.com")
+//!     .to("bill@example.org")
+//!     .tls_13()
+//!     .authenticated("john")
+//!     .build();
+//! assert_eq!(request.envelope.unwrap().to[0].address, "bill@example.org");
+//! ```
+
+use crate::request::{
+    Address, Client, Context, Envelope, Message, Protocol, Queue, Request, Sasl, Server, Stage, Tls,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct RequestBuilder {
+    request: Request,
+}
+
+impl Default for RequestBuilder {
+    fn default() -> Self {
+        Self {
+            request: Request {
+                context: Context {
+                    stage: Stage::Connect,
+                    client: Client {
+                        ip: "192.0.2.1".to_string(),
+                        port: 49152,
+                        ptr: None,
+                        helo: None,
+                        active_connections: 1,
+                    },
+                    sasl: None,
+                    tls: None,
+                    server: Server {
+                        name: Some("mx.example.org".to_string()),
+                        port: 25,
+                        ip: Some("198.51.100.1".to_string()),
+                    },
+                    queue: None,
+                    protocol: Protocol { version: 1 },
+                },
+                envelope: None,
+                message: None,
+            },
+        }
+    }
+}
+
+impl Request {
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder::default()
+    }
+}
+
+impl RequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stage(mut self, stage: Stage) -> Self {
+        self.request.context.stage = stage;
+        self
+    }
+
+    pub fn client_ip(mut self, ip: impl Into<String>) -> Self {
+        self.request.context.client.ip = ip.into();
+        self
+    }
+
+    pub fn client_port(mut self, port: u16) -> Self {
+        self.request.context.client.port = port;
+        self
+    }
+
+    pub fn ptr(mut self, ptr: impl Into<String>) -> Self {
+        self.request.context.client.ptr = Some(ptr.into());
+        self
+    }
+
+    pub fn helo(mut self, helo: impl Into<String>) -> Self {
+        self.request.context.client.helo = Some(helo.into());
+        self
+    }
+
+    pub fn server_name(mut self, name: impl Into<String>) -> Self {
+        self.request.context.server.name = Some(name.into());
+        self
+    }
+
+    pub fn server_ip(mut self, ip: impl Into<String>) -> Self {
+        self.request.context.server.ip = Some(ip.into());
+        self
+    }
+
+    pub fn server_port(mut self, port: u16) -> Self {
+        self.request.context.server.port = port;
+        self
+    }
+
+    pub fn tls(mut self, tls: Tls) -> Self {
+        self.request.context.tls = Some(tls);
+        self
+    }
+
+    /// A TLS 1.3 connection with `TLS_AES_256_GCM_SHA384`.
+    pub fn tls_13(self) -> Self {
+        self.tls(Tls {
+            version: "TLSv1.3".to_string(),
+            cipher: "TLS_AES_256_GCM_SHA384".to_string(),
+            bits: Some(256),
+            issuer: None,
+            subject: None,
+        })
+    }
+
+    /// Authenticated as `login` with SASL `PLAIN`.
+    pub fn authenticated(mut self, login: impl Into<String>) -> Self {
+        self.request.context.sasl = Some(Sasl {
+            login: login.into(),
+            method: Some("plain".to_string()),
+        });
+        self
+    }
+
+    pub fn queue_id(mut self, id: impl Into<String>) -> Self {
+        self.request.context.queue = Some(Queue { id: id.into() });
+        self
+    }
+
+    pub fn protocol_version(mut self, version: u32) -> Self {
+        self.request.context.protocol.version = version;
+        self
+    }
+
+    fn envelope(&mut self) -> &mut Envelope {
+        self.request.envelope.get_or_insert_with(|| Envelope {
+            from: Address {
+                address: String::new(),
+                parameters: None,
+            },
+            to: Vec::new(),
+        })
+    }
+
+    /// Sets the envelope sender, `""` for the null sender.
+    pub fn from(mut self, address: impl Into<String>) -> Self {
+        self.envelope().from.address = address.into();
+        self
+    }
+
+    /// Adds a `MAIL FROM` parameter such as `SIZE`.
+    pub fn from_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.envelope()
+            .from
+            .parameters
+            .get_or_insert_with(HashMap::new)
+            .insert(name.into(), value.into());
+        self
+    }
+
+    /// Adds a recipient.
+    pub fn to(mut self, address: impl Into<String>) -> Self {
+        self.envelope().to.push(Address {
+            address: address.into(),
+            parameters: None,
+        });
+        self
+    }
+
+    pub fn message(mut self, message: Message) -> Self {
+        self.request.message = Some(message);
+        self
+    }
+
+    /// Sets the message from a raw RFC 5322 message.
+    pub fn raw_message(self, raw: impl AsRef<[u8]>) -> Self {
+        self.message(Message::parse(raw))
+    }
+
+    /// Sets the message from an `.eml` file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be read, as befits a test fixture.
+    pub fn message_from_eml(self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let message = Message::from_file(path)
+            .unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err));
+        self.message(message)
+    }
+
+    pub fn build(self) -> Request {
+        self.request
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let request = Request::builder().build();
+        assert_eq!(request.context.stage, Stage::Connect);
+        assert_eq!(request.context.client.ip, "192.0.2.1");
+        assert!(request.envelope.is_none() && request.message.is_none());
+
+        let request = Request::builder()
+            .stage(Stage::Data)
+            .client_ip("203.0.113.7")
+            .helo("client.example.com")
+            .from("")
+            .from_param("SIZE", "1024")
+            .to("bill@example.org")
+            .to("jane@example.org")
+            .tls_13()
+            .authenticated("john")
+            .queue_id("ABC123")
+            .raw_message("Subject: Hi\r\n\r\nHello\r\n")
+            .build();
+        let envelope = request.envelope.as_ref().unwrap();
+        assert_eq!(envelope.from.address, "");
+        assert_eq!(envelope.from.parameters.as_ref().unwrap()["SIZE"], "1024");
+        assert_eq!(envelope.to.len(), 2);
+        assert_eq!(request.context.tls.as_ref().unwrap().version, "TLSv1.3");
+        assert_eq!(request.context.sasl.as_ref().unwrap().login, "john");
+        assert_eq!(request.message.as_ref().unwrap().contents, "Hello\r\n");
+
+        // The built request survives the JSON round trip.
+        let json = serde_json::to_string(&request).unwrap();
+        let parsed: Request = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.context.queue.unwrap().id, "ABC123");
+
+        let path = std::env::temp_dir().join(format!("builder-{}.eml", std::process::id()));
+        std::fs::write(&path, "From: john@example.com\r\n\r\nBody\r\n").unwrap();
+        let request = Request::builder().message_from_eml(&path).build();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(request.message.unwrap().headers[0].0, "From");
+    }
+}
@@ -0,0 +1,131 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! CBOR encoding of requests and responses, e.g. for archiving them for
+//! replay.
+//!
+//! Field names and coercion rules are those of the JSON form: parameters
+//! may be strings, numbers or booleans, and bodies that are not UTF-8 are
+//! written as byte strings.
+//!
+//! ```
+//! use stalwart_mta_hook_types::{cbor, Request, Stage};
+//!
+//! let request = Request::builder().stage(Stage::Rcpt).to("bill@example.org").build();
+//! let data = cbor::to_vec(&request).unwrap();
+//! let archived: Request = cbor::from_slice(&data).unwrap();
+//! assert_eq!(archived.envelope.unwrap().to[0].address, "bill@example.org");
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum CborError {
+    Encode(ciborium::ser::Error<io::Error>),
+    Decode(ciborium::de::Error<io::Error>),
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CborError::Encode(err) => write!(f, "cannot encode CBOR: {}", err),
+            CborError::Decode(err) => write!(f, "cannot decode CBOR: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CborError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CborError::Encode(err) => Some(err),
+            CborError::Decode(err) => Some(err),
+        }
+    }
+}
+
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, CborError> {
+    let mut data = Vec::new();
+    to_writer(value, &mut data)?;
+    Ok(data)
+}
+
+pub fn to_writer<T: Serialize>(value: &T, writer: impl io::Write) -> Result<(), CborError> {
+    ciborium::into_writer(value, writer).map_err(CborError::Encode)
+}
+
+pub fn from_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T, CborError> {
+    from_reader(data)
+}
+
+pub fn from_reader<T: DeserializeOwned>(reader: impl io::Read) -> Result<T, CborError> {
+    ciborium::from_reader(reader).map_err(CborError::Decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modifications::Modification;
+    use crate::request::{Request, Stage};
+    use crate::response::Response;
+    use serde_json::json;
+
+    #[test]
+    fn test_cbor_round_trip() {
+        let request = Request::builder()
+            .stage(Stage::Data)
+            .from("john@example.com")
+            .from_param("SIZE", "1024")
+            .to("bill@example.org")
+            .raw_message(b"Subject: Hi\r\n\r\ncaf\xe9\r\n")
+            .build();
+        let data = to_vec(&request).unwrap();
+        let decoded: Request = from_slice(&data).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&request).unwrap()
+        );
+
+        let response = Response::accept().with_modifications(vec![
+            Modification::replace_contents(b"caf\xe9\r\n".to_vec()),
+            Modification::add_header("X-Spam".to_string(), "no".to_string()),
+        ]);
+        let decoded: Response = from_slice(&to_vec(&response).unwrap()).unwrap();
+        match &decoded.modifications[0] {
+            Modification::ReplaceContents { value } => assert_eq!(value, &b"caf\xe9\r\n"[..]),
+            _ => panic!("expected ReplaceContents"),
+        }
+    }
+
+    #[test]
+    fn test_cbor_coercion() {
+        // Written by another tool: numeric parameters and an uppercase stage.
+        let value = json!({
+            "context": {
+                "stage": "RCPT",
+                "client": {"ip": "192.0.2.1", "port": 49152, "activeConnections": 1},
+                "server": {"port": 25},
+                "protocol": {"version": 1},
+            },
+            "envelope": {
+                "from": {"address": "john@example.com", "parameters": {"SIZE": 1024}},
+                "to": [{"address": "bill@example.org", "parameters": null}],
+            },
+        });
+        let request: Request = from_slice(&to_vec(&value).unwrap()).unwrap();
+        assert_eq!(request.context.stage, Stage::Rcpt);
+        let envelope = request.envelope.unwrap();
+        assert_eq!(envelope.from.parameters.unwrap()["SIZE"], "1024");
+        assert!(envelope.to[0].parameters.is_none());
+
+        assert!(matches!(
+            from_slice::<Request>(b"\xa0"),
+            Err(CborError::Decode(_))
+        ));
+    }
+}
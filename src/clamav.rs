@@ -0,0 +1,540 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Virus scanning of messages with ClamAV's `clamd`.
+//!
+//! Messages are streamed to `clamd` with the `INSTREAM` command over TCP
+//! or a Unix socket, either as a whole or as individual decoded MIME
+//! parts, and the verdict is turned into a hook [`Response`].
+//!
+//! ```no_run
+//! use stalwart_mta_hook_types::clamav::{Clamd, VirusScanner};
+//! use stalwart_mta_hook_types::{Action, Request, Stage};
+//!
+//! let scanner = VirusScanner::new(Clamd::new("unix:/run/clamav/clamd.ctl".parse().unwrap()))
+//!     .with_infected_action(Action::Reject)
+//!     .with_fail_open(true);
+//! let request = Request::builder()
+//!     .stage(Stage::Data)
+//!     .from("john@example.com")
+//!     .to("bill@example.org")
+//!     .raw_message("Subject: Invoice\r\n\r\nPlease find it attached.\r\n")
+//!     .build();
+//! let response = scanner.scan(&request);
+//! ```
+
+use crate::mime::Part;
+use crate::modifications::Modification;
+use crate::net::connect_tcp;
+use crate::request::{Message, Request};
+use crate::response::{Action, Response, SmtpResponse};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum ClamavError {
+    Io(io::Error),
+    Timeout,
+    /// `clamd` answered with something that is not a scan result.
+    Protocol(String),
+}
+
+impl fmt::Display for ClamavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClamavError::Io(err) => write!(f, "clamd connection failed: {}", err),
+            ClamavError::Timeout => write!(f, "clamd timed out"),
+            ClamavError::Protocol(reply) => write!(f, "unexpected clamd reply: {}", reply),
+        }
+    }
+}
+
+impl std::error::Error for ClamavError {}
+
+impl From<io::Error> for ClamavError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ClamavError::Timeout,
+            _ => ClamavError::Io(err),
+        }
+    }
+}
+
+/// Where `clamd` listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClamdAddress {
+    /// `host:port`
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for ClamdAddress {
+    type Err = String;
+
+    /// Parses `unix:/path`, an absolute socket path or `host:port`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let path = value
+            .strip_prefix("unix:")
+            .or_else(|| value.starts_with('/').then_some(value));
+        match path {
+            #[cfg(unix)]
+            Some(path) => Ok(ClamdAddress::Unix(PathBuf::from(path))),
+            #[cfg(not(unix))]
+            Some(_) => Err(format!("unix sockets are not supported: {}", value)),
+            None if value.contains(':') => Ok(ClamdAddress::Tcp(value.to_string())),
+            None => Err(format!("invalid clamd address {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanResult {
+    Clean,
+    /// A signature matched, with its name.
+    Infected(String),
+    /// `clamd` could not scan the data, e.g. because of its size limit.
+    Error(String),
+}
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+/// A `clamd` client.
+#[derive(Debug, Clone)]
+pub struct Clamd {
+    address: ClamdAddress,
+    timeout: Duration,
+    chunk_size: usize,
+}
+
+impl Clamd {
+    pub fn new(address: ClamdAddress) -> Self {
+        Self {
+            address,
+            timeout: Duration::from_secs(30),
+            chunk_size: 64 * 1024,
+        }
+    }
+
+    /// Sets the timeout for connecting and each read or write (30 seconds
+    /// by default).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the size of the chunks data is streamed in (64 KiB by default).
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    fn connect(&self) -> Result<Box<dyn Stream>, ClamavError> {
+        match &self.address {
+            ClamdAddress::Tcp(address) => Ok(Box::new(connect_tcp(address, self.timeout)?)),
+            #[cfg(unix)]
+            ClamdAddress::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+
+    fn command(&self, command: &[u8], data: Option<&[u8]>) -> Result<String, ClamavError> {
+        let mut stream = self.connect()?;
+        stream.write_all(command)?;
+        if let Some(data) = data {
+            for chunk in data.chunks(self.chunk_size) {
+                stream.write_all(&(chunk.len() as u32).to_be_bytes())?;
+                stream.write_all(chunk)?;
+            }
+            stream.write_all(&[0; 4])?;
+        }
+        stream.flush()?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        let reply = String::from_utf8_lossy(&reply);
+        Ok(reply.trim_end_matches(['\0', '\n']).to_string())
+    }
+
+    /// Checks that `clamd` is reachable.
+    pub fn ping(&self) -> Result<(), ClamavError> {
+        match self.command(b"zPING\0", None)?.as_str() {
+            "PONG" => Ok(()),
+            reply => Err(ClamavError::Protocol(reply.to_string())),
+        }
+    }
+
+    /// Streams `data` to `clamd` and returns its verdict.
+    pub fn scan(&self, data: &[u8]) -> Result<ScanResult, ClamavError> {
+        let reply = self.command(b"zINSTREAM\0", Some(data))?;
+        let result = reply.strip_prefix("stream: ").unwrap_or(&reply);
+        if result == "OK" {
+            Ok(ScanResult::Clean)
+        } else if let Some(signature) = result.strip_suffix(" FOUND") {
+            Ok(ScanResult::Infected(signature.to_string()))
+        } else if let Some(error) = result.strip_suffix(" ERROR") {
+            Ok(ScanResult::Error(error.to_string()))
+        } else {
+            Err(ClamavError::Protocol(reply))
+        }
+    }
+}
+
+/// What is sent to `clamd`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScanMode {
+    /// The complete message with its headers.
+    #[default]
+    Message,
+    /// Each leaf MIME part separately, with its transfer encoding removed.
+    Parts,
+}
+
+/// Turns `clamd` verdicts into hook responses.
+#[derive(Debug, Clone)]
+pub struct VirusScanner {
+    clamd: Clamd,
+    mode: ScanMode,
+    infected_action: Action,
+    fail_open: bool,
+    scanner_name: String,
+}
+
+impl VirusScanner {
+    pub fn new(clamd: Clamd) -> Self {
+        Self {
+            clamd,
+            mode: ScanMode::default(),
+            infected_action: Action::Reject,
+            fail_open: false,
+            scanner_name: "ClamAV".to_string(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: ScanMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets what happens to infected messages, `Reject` (the default),
+    /// `Quarantine` or `Discard`. Stalwart does not implement `Quarantine`
+    /// yet; use `quarantine::Quarantine` to keep infected messages.
+    pub fn with_infected_action(mut self, action: Action) -> Self {
+        self.infected_action = action;
+        self
+    }
+
+    /// Accepts messages that could not be scanned instead of deferring
+    /// them (fail-closed by default).
+    pub fn with_fail_open(mut self, fail_open: bool) -> Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// Sets the scanner named in the `X-Virus-Scanned` header.
+    pub fn with_scanner_name(mut self, name: String) -> Self {
+        self.scanner_name = name;
+        self
+    }
+
+    /// Scans the message of the request; requests without a message are
+    /// accepted unchanged.
+    pub fn scan(&self, request: &Request) -> Response {
+        match &request.message {
+            Some(message) => self.scan_message(message),
+            None => Response::accept(),
+        }
+    }
+
+    pub fn scan_message(&self, message: &Message) -> Response {
+        match self.verdict(message) {
+            Ok(ScanResult::Clean) => self.accept("Clean".to_string()),
+            Ok(ScanResult::Infected(signature)) => self.infected(signature),
+            Ok(ScanResult::Error(error)) => self.failed(error),
+            Err(err) => self.failed(err.to_string()),
+        }
+    }
+
+    fn verdict(&self, message: &Message) -> Result<ScanResult, ClamavError> {
+        match self.mode {
+            ScanMode::Message => {
+                let mut head = String::with_capacity(1024);
+                for (name, value) in &message.headers {
+                    crate::mime::write_header(&mut head, name, value);
+                }
+                head.push_str("\r\n");
+                let mut data = head.into_bytes();
+                data.extend_from_slice(&message.contents);
+                self.clamd.scan(&data)
+            }
+            ScanMode::Parts => {
+                let root = Part::from_message(message);
+                let mut error = None;
+                for part in root.leaves() {
+                    let body = part.decoded_body();
+                    if body.is_empty() {
+                        continue;
+                    }
+                    match self.clamd.scan(&body)? {
+                        ScanResult::Clean => {}
+                        ScanResult::Infected(signature) => {
+                            return Ok(ScanResult::Infected(signature))
+                        }
+                        ScanResult::Error(err) => error = Some(err),
+                    }
+                }
+                Ok(error.map_or(ScanResult::Clean, ScanResult::Error))
+            }
+        }
+    }
+
+    fn headers(&self, status: String) -> Vec<Modification> {
+        vec![
+            Modification::add_header("X-Virus-Scanned".to_string(), self.scanner_name.clone()),
+            Modification::add_header("X-Virus-Status".to_string(), status),
+        ]
+    }
+
+    fn accept(&self, status: String) -> Response {
+        Response::accept().with_modifications(self.headers(status))
+    }
+
+    fn infected(&self, signature: String) -> Response {
+        match self.infected_action {
+            Action::Accept => self.accept(format!("Infected ({})", signature)),
+            Action::Reject => Response {
+                action: Action::Reject,
+                response: Some(SmtpResponse {
+                    status: Some(554),
+                    enhanced_status: Some("5.7.1".to_string()),
+                    message: Some(format!("Message contains a virus ({})", signature)),
+                    disconnect: false,
+                }),
+                modifications: Vec::new(),
+            },
+            action => Response {
+                action,
+                response: None,
+                modifications: self.headers(format!("Infected ({})", signature)),
+            },
+        }
+    }
+
+    fn failed(&self, error: String) -> Response {
+        if self.fail_open {
+            return Response::accept().with_modifications(vec![Modification::add_header(
+                "X-Virus-Status".to_string(),
+                format!("Unscanned ({})", error),
+            )]);
+        }
+        Response {
+            action: Action::Reject,
+            response: Some(SmtpResponse {
+                status: Some(451),
+                enhanced_status: Some("4.7.1".to_string()),
+                message: Some("Virus scan failed, try again later".to_string()),
+                disconnect: false,
+            }),
+            modifications: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const EICAR: &str = "X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+    /// Serves a single connection, answering like clamd.
+    fn fake_clamd(mut stream: impl Read + Write) {
+        let mut command = Vec::new();
+        let mut byte = [0u8];
+        while stream.read_exact(&mut byte).is_ok() && byte[0] != 0 {
+            command.push(byte[0]);
+        }
+        let reply = match command.as_slice() {
+            b"zPING" => "PONG".to_string(),
+            b"zINSTREAM" => {
+                let mut data = Vec::new();
+                loop {
+                    let mut len = [0u8; 4];
+                    stream.read_exact(&mut len).unwrap();
+                    let len = u32::from_be_bytes(len) as usize;
+                    if len == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0; len];
+                    stream.read_exact(&mut chunk).unwrap();
+                    data.extend(chunk);
+                }
+                if data
+                    .windows(EICAR.len())
+                    .any(|window| window == EICAR.as_bytes())
+                {
+                    "stream: Eicar-Signature FOUND".to_string()
+                } else if data.len() > 4096 {
+                    "INSTREAM size limit exceeded. ERROR".to_string()
+                } else {
+                    "stream: OK".to_string()
+                }
+            }
+            _ => "UNKNOWN COMMAND".to_string(),
+        };
+        stream.write_all(reply.as_bytes()).unwrap();
+        stream.write_all(b"\0").unwrap();
+    }
+
+    fn tcp_clamd(connections: usize) -> Clamd {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                fake_clamd(stream.unwrap());
+            }
+        });
+        Clamd::new(ClamdAddress::Tcp(address)).with_chunk_size(7)
+    }
+
+    fn message(contents: &str) -> Message {
+        Message {
+            headers: vec![
+                ("From".to_string(), " john@example.com\r\n".to_string()),
+                (
+                    "Content-Type".to_string(),
+                    " multipart/mixed; boundary=b\r\n".to_string(),
+                ),
+            ],
+            server_headers: Vec::new(),
+            contents: contents.into(),
+            size: contents.len(),
+        }
+    }
+
+    fn status(response: &Response) -> Option<&str> {
+        response
+            .modifications
+            .iter()
+            .find_map(|modification| match modification {
+                Modification::AddHeader { name, value } if name == "X-Virus-Status" => {
+                    Some(value.as_str())
+                }
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            "127.0.0.1:3310".parse::<ClamdAddress>(),
+            Ok(ClamdAddress::Tcp("127.0.0.1:3310".to_string()))
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            "unix:/run/clamd.ctl".parse::<ClamdAddress>(),
+            Ok(ClamdAddress::Unix(PathBuf::from("/run/clamd.ctl")))
+        );
+        assert!("clamd".parse::<ClamdAddress>().is_err());
+    }
+
+    #[test]
+    fn test_scan_over_tcp() {
+        let clamd = tcp_clamd(4);
+        clamd.ping().unwrap();
+        assert_eq!(clamd.scan(b"harmless").unwrap(), ScanResult::Clean);
+        assert_eq!(
+            clamd.scan(EICAR.as_bytes()).unwrap(),
+            ScanResult::Infected("Eicar-Signature".to_string())
+        );
+        assert!(matches!(
+            clamd.scan(&[b'a'; 5000]).unwrap(),
+            ScanResult::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_scanner_responses() {
+        let clean = message("--b\r\n\r\nHello\r\n--b--\r\n");
+        let response = VirusScanner::new(tcp_clamd(1)).scan_message(&clean);
+        assert_eq!(response.action, Action::Accept);
+        assert_eq!(status(&response), Some("Clean"));
+
+        // The EICAR string is only visible once the part is decoded.
+        let encoded = crate::mime::decode_base64("WDVPIVAlQEFQWzRcUFpYNTQoUF4pN0NDKTd9JEVJQ0FSLVNUQU5EQVJELUFOVElWSVJVUy1URVNULUZJTEUhJEgrSCo=").unwrap();
+        assert_eq!(encoded, EICAR.as_bytes());
+        let infected = message(
+            "--b\r\n\r\nHello\r\n--b\r\nContent-Transfer-Encoding: base64\r\n\r\n\
+             WDVPIVAlQEFQWzRcUFpYNTQoUF4pN0NDKTd9JEVJQ0FSLVNUQU5E\r\n\
+             QVJELUFOVElWSVJVUy1URVNULUZJTEUhJEgrSCo=\r\n--b--\r\n",
+        );
+        let response = VirusScanner::new(tcp_clamd(1)).scan_message(&infected);
+        assert_eq!(response.action, Action::Accept);
+
+        let response = VirusScanner::new(tcp_clamd(2))
+            .with_mode(ScanMode::Parts)
+            .scan_message(&infected);
+        assert_eq!(response.action, Action::Reject);
+        assert_eq!(response.response.unwrap().status, Some(554));
+
+        let response = VirusScanner::new(tcp_clamd(2))
+            .with_mode(ScanMode::Parts)
+            .with_infected_action(Action::Quarantine)
+            .scan_message(&infected);
+        assert_eq!(response.action, Action::Quarantine);
+        assert_eq!(status(&response), Some("Infected (Eicar-Signature)"));
+    }
+
+    #[test]
+    fn test_failure_modes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let silent = thread::spawn(move || {
+            let streams: Vec<_> = listener.incoming().take(2).collect();
+            thread::sleep(Duration::from_millis(500));
+            drop(streams);
+        });
+        let clamd = Clamd::new(ClamdAddress::Tcp(address)).with_timeout(Duration::from_millis(100));
+        assert!(matches!(clamd.scan(b"data"), Err(ClamavError::Timeout)));
+
+        let message = message("--b\r\n\r\nHello\r\n--b--\r\n");
+        let response = VirusScanner::new(clamd.clone()).scan_message(&message);
+        assert_eq!(response.action, Action::Reject);
+        assert_eq!(response.response.unwrap().status, Some(451));
+
+        let unreachable = Clamd::new(ClamdAddress::Tcp("127.0.0.1:1".to_string()));
+        let response = VirusScanner::new(unreachable)
+            .with_fail_open(true)
+            .scan_message(&message);
+        assert_eq!(response.action, Action::Accept);
+        assert!(status(&response).unwrap().starts_with("Unscanned"));
+        silent.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("clamd-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || fake_clamd(listener.accept().unwrap().0));
+
+        let clamd = Clamd::new(format!("unix:{}", path.display()).parse().unwrap());
+        assert_eq!(clamd.scan(b"harmless").unwrap(), ScanResult::Clean);
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
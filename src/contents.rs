@@ -0,0 +1,222 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Binary-safe message contents.
+//!
+//! With `8BITMIME` and `BINARYMIME` a message body may hold bytes that are
+//! not UTF-8, such as text in a legacy charset. [`Contents`] holds them
+//! exactly, but Stalwart sends and expects the body as a JSON string, so
+//! human-readable formats such as JSON always get a string and the JSON path
+//! is lossy: bytes that are not UTF-8 are replaced by U+FFFD, and such a
+//! body does not survive a JSON parse, modify and serialize cycle. Binary
+//! formats such as CBOR and MessagePack write it as a byte string, and
+//! [`Message::to_rfc5322`](crate::Message::to_rfc5322) writes the raw
+//! message; both keep the bytes exact. Strings, byte strings and arrays of
+//! byte values are all accepted when deserializing.
+//!
+//! ```
+//! use stalwart_mta_hook_types::Contents;
+//!
+//! let latin1 = Contents::from(b"Gr\xfc\xdfe\r\n".to_vec());
+//! assert_eq!(latin1.as_str(), None);
+//! let json = serde_json::to_string(&latin1).unwrap();
+//! assert_eq!(json, "\"Gr\u{fffd}\u{fffd}e\\r\\n\"");
+//!
+//! let utf8 = Contents::from("Grüße\r\n");
+//! assert_eq!(serde_json::to_string(&utf8).unwrap(), r#""Grüße\r\n""#);
+//! ```
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Deref;
+
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Contents(Vec<u8>);
+
+impl Contents {
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Self(bytes.into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// The contents as text, or `None` if they are not UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    /// The contents as text, with invalid bytes replaced by `U+FFFD`.
+    pub fn to_str_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        self.as_str().is_some()
+    }
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Contents {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Contents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_str() {
+            Some(text) => fmt::Debug::fmt(text, f),
+            None => write!(f, "b\"{}\"", self.0.escape_ascii()),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Contents {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for Contents {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl From<String> for Contents {
+    fn from(text: String) -> Self {
+        Self(text.into_bytes())
+    }
+}
+
+impl From<&str> for Contents {
+    fn from(text: &str) -> Self {
+        Self(text.as_bytes().to_vec())
+    }
+}
+
+impl From<Contents> for Vec<u8> {
+    fn from(contents: Contents) -> Self {
+        contents.0
+    }
+}
+
+impl PartialEq<str> for Contents {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Contents {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<String> for Contents {
+    fn eq(&self, other: &String) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<[u8]> for Contents {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == other
+    }
+}
+
+impl Serialize for Contents {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.as_str() {
+            Some(text) => serializer.serialize_str(text),
+            None if serializer.is_human_readable() => {
+                serializer.serialize_str(&self.to_str_lossy())
+            }
+            None => serializer.serialize_bytes(&self.0),
+        }
+    }
+}
+
+struct ContentsVisitor;
+
+impl<'de> Visitor<'de> for ContentsVisitor {
+    type Value = Contents;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string or a sequence of bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Contents, E> {
+        Ok(Contents::from(value))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Contents, E> {
+        Ok(Contents::from(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Contents, E> {
+        Ok(Contents::from(value))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Contents, E> {
+        Ok(Contents::from(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Contents, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        Ok(Contents(bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for Contents {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ContentsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contents_serde() {
+        let utf8 = Contents::from("Hello\r\n");
+        assert_eq!(serde_json::to_string(&utf8).unwrap(), r#""Hello\r\n""#);
+        assert_eq!(utf8, "Hello\r\n");
+
+        let latin1 = Contents::from(b"caf\xe9\r\n".to_vec());
+        assert_eq!(
+            serde_json::to_string(&latin1).unwrap(),
+            "\"caf\u{fffd}\\r\\n\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Contents>("[99,97,102,233,13,10]").unwrap(),
+            latin1
+        );
+        assert_eq!(latin1.to_str_lossy(), "caf\u{fffd}\r\n");
+        assert_eq!(format!("{:?}", latin1), r#"b"caf\xe9\r\n""#);
+        assert!(serde_json::from_str::<Contents>("[256]").is_err());
+        assert!(serde_json::from_str::<Contents>("42").is_err());
+    }
+}
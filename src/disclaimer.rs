@@ -0,0 +1,522 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Disclaimer and signature injection for outgoing messages.
+//!
+//! A disclaimer is chosen by the authenticated login, then by the sender
+//! domain, then the default, and appended to the message text: to every
+//! text alternative of a `multipart/alternative` and to the first text
+//! body of other multiparts, never to attachments. Signed or encrypted
+//! messages are left alone, as are messages already containing the
+//! disclaimer, e.g. quoted in a reply, and messages whose body is not
+//! UTF-8, which could not be rewritten unchanged.
+//!
+//! ```
+//! use stalwart_mta_hook_types::disclaimer::{Disclaimer, DisclaimerPolicy};
+//! use stalwart_mta_hook_types::{Modification, Request, Response, Stage};
+//!
+//! let request = Request::builder()
+//!     .stage(Stage::Data)
+//!     .authenticated("john")
+//!     .from("john@example.org")
+//!     .to("bill@example.com")
+//!     .raw_message("Subject: Hello\r\nContent-Type: text/plain\r\n\r\nHi Bill\r\n")
+//!     .build();
+//! let policy = DisclaimerPolicy::new()
+//!     .with_default(Disclaimer::new("Example Ltd, registered in Example".to_string()))
+//!     .with_domain(
+//!         "example.org".to_string(),
+//!         Disclaimer::new("Sent by {sender} on behalf of {domain}".to_string()),
+//!     );
+//! let response = policy.apply(&request).unwrap_or_else(Response::accept);
+//! assert!(response.modifications.iter().any(|modification| matches!(
+//!     modification,
+//!     Modification::ReplaceContents { value }
+//!         if value.to_str_lossy().contains("Sent by john@example.org on behalf of example.org")
+//! )));
+//! ```
+
+use crate::mime::{encode_base64, encode_quoted_printable, header, parse_params, Body, Part};
+use crate::modifications::Modification;
+use crate::request::{Message, Request};
+use crate::response::Response;
+use std::collections::HashMap;
+
+/// Content types of signed or encrypted messages, which must not change.
+const PROTECTED_TYPES: &[&str] = &[
+    "multipart/signed",
+    "multipart/encrypted",
+    "application/pkcs7-mime",
+    "application/x-pkcs7-mime",
+];
+
+/// A disclaimer template; `{sender}`, `{domain}` and `{login}` are
+/// replaced by the envelope sender, its domain and the SASL login.
+#[derive(Debug, Clone)]
+pub struct Disclaimer {
+    text: String,
+    html: Option<String>,
+}
+
+impl Disclaimer {
+    pub fn new(text: String) -> Self {
+        Self { text, html: None }
+    }
+
+    /// Sets the HTML version; by default the escaped text is used.
+    pub fn with_html(mut self, html: String) -> Self {
+        self.html = Some(html);
+        self
+    }
+
+    fn render(&self, request: &Request) -> Rendered {
+        let sender = request
+            .envelope
+            .as_ref()
+            .map(|envelope| envelope.from.address.as_str())
+            .unwrap_or_default();
+        let domain = sender
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_ascii_lowercase())
+            .unwrap_or_default();
+        let login = request
+            .context
+            .sasl
+            .as_ref()
+            .map(|sasl| sasl.login.as_str())
+            .unwrap_or_default();
+        let fill = |template: &str, escape: fn(&str) -> String| {
+            template
+                .replace("{sender}", &escape(sender))
+                .replace("{domain}", &escape(&domain))
+                .replace("{login}", &escape(login))
+        };
+
+        let text = fill(&self.text, str::to_string);
+        let html = match &self.html {
+            Some(html) => fill(html, escape_html),
+            None => format!(
+                "<div>{}</div>",
+                escape_html(&text)
+                    .lines()
+                    .collect::<Vec<_>>()
+                    .join("<br>\r\n")
+            ),
+        };
+        Rendered { text, html }
+    }
+}
+
+struct Rendered {
+    text: String,
+    html: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DisclaimerPolicy {
+    default: Option<Disclaimer>,
+    domains: HashMap<String, Disclaimer>,
+    logins: HashMap<String, Disclaimer>,
+    marker: Option<String>,
+}
+
+impl DisclaimerPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_default(mut self, disclaimer: Disclaimer) -> Self {
+        self.default = Some(disclaimer);
+        self
+    }
+
+    /// Uses `disclaimer` for envelope senders in `domain`.
+    pub fn with_domain(mut self, domain: String, disclaimer: Disclaimer) -> Self {
+        self.domains.insert(domain.to_ascii_lowercase(), disclaimer);
+        self
+    }
+
+    /// Uses `disclaimer` for the SASL `login`, taking precedence over the
+    /// sender domain.
+    pub fn with_login(mut self, login: String, disclaimer: Disclaimer) -> Self {
+        self.logins.insert(login, disclaimer);
+        self
+    }
+
+    /// Sets the text whose presence means the disclaimer was already added.
+    /// Defaults to the first non-empty line of the rendered disclaimer.
+    pub fn with_marker(mut self, marker: String) -> Self {
+        self.marker = Some(marker);
+        self
+    }
+
+    /// The disclaimer for the sender of the request, if any.
+    pub fn select(&self, request: &Request) -> Option<&Disclaimer> {
+        let login = request.context.sasl.as_ref().map(|sasl| &sasl.login);
+        let domain = request.envelope.as_ref().and_then(|envelope| {
+            envelope
+                .from
+                .address
+                .rsplit_once('@')
+                .map(|(_, domain)| domain.to_ascii_lowercase())
+        });
+        login
+            .and_then(|login| self.logins.get(login))
+            .or_else(|| domain.and_then(|domain| self.domains.get(&domain)))
+            .or(self.default.as_ref())
+    }
+
+    /// Adds the disclaimer to the message of the request, returning `None`
+    /// if there is no disclaimer for the sender or the message is left
+    /// unchanged.
+    pub fn apply(&self, request: &Request) -> Option<Response> {
+        let message = request.message.as_ref()?;
+        let rendered = self.select(request)?.render(request);
+        let modifications = self.inject(message, &rendered)?;
+        Some(Response::accept().with_modifications(modifications))
+    }
+
+    fn inject(&self, message: &Message, rendered: &Rendered) -> Option<Vec<Modification>> {
+        message.contents.as_str()?;
+        let mut root = Part::from_message(message);
+        if is_protected(&root) {
+            return None;
+        }
+        let marker = match &self.marker {
+            Some(marker) => marker.clone(),
+            None => rendered
+                .text
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())?
+                .to_string(),
+        };
+        let escaped_marker = escape_html(&marker);
+        let present = root.leaves().iter().any(|part| {
+            part.content_type().starts_with("text/") && {
+                let text = part.decoded_text();
+                text.contains(&marker) || text.contains(&escaped_marker)
+            }
+        });
+        if present || !inject_part(&mut root, rendered) {
+            return None;
+        }
+
+        let mut modifications = vec![Modification::replace_contents(root.body_string())];
+        // A single-part message may need new top-level headers.
+        for name in ["Content-Type", "Content-Transfer-Encoding"] {
+            let (before, after) = (header(&message.headers, name), root.header(name));
+            match (before, after) {
+                (Some(before), Some(after)) if before != after => modifications.push(
+                    Modification::change_header(1, name.to_string(), after.to_string()),
+                ),
+                (None, Some(after)) => modifications.push(Modification::add_header(
+                    name.to_string(),
+                    after.to_string(),
+                )),
+                _ => {}
+            }
+        }
+        Some(modifications)
+    }
+}
+
+fn is_protected(part: &Part) -> bool {
+    PROTECTED_TYPES.contains(&part.content_type().as_str())
+        || match &part.body {
+            Body::Multipart { parts, .. } => parts.iter().any(is_protected),
+            Body::Single(_) => {
+                part.content_type() == "text/plain"
+                    && part
+                        .decoded_text()
+                        .contains("-----BEGIN PGP SIGNED MESSAGE-----")
+            }
+        }
+}
+
+/// Adds the disclaimer below `part`, returning whether anything changed.
+fn inject_part(part: &mut Part, rendered: &Rendered) -> bool {
+    let content_type = part.content_type();
+    let attachment = part.is_attachment();
+    match &mut part.body {
+        Body::Multipart { parts, .. } if content_type == "multipart/alternative" => {
+            // Every alternative gets the disclaimer, so no short-circuiting.
+            let mut changed = false;
+            for part in parts {
+                changed |= inject_part(part, rendered);
+            }
+            changed
+        }
+        Body::Multipart { parts, .. } => parts.iter_mut().any(|part| inject_part(part, rendered)),
+        Body::Single(_) if attachment => false,
+        Body::Single(_) => match content_type.as_str() {
+            "text/plain" => rewrite(part, |text| append_text(text, &rendered.text)),
+            "text/html" => rewrite(part, |html| insert_html(html, &rendered.html)),
+            _ => false,
+        },
+    }
+}
+
+fn append_text(text: &str, disclaimer: &str) -> String {
+    let mut out = text.to_string();
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push_str("\r\n");
+    }
+    out.push_str("\r\n");
+    for line in disclaimer.lines() {
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn insert_html(html: &str, disclaimer: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    match lower.rfind("</body>").or_else(|| lower.rfind("</html>")) {
+        Some(pos) => format!("{}{}\r\n{}", &html[..pos], disclaimer, &html[pos..]),
+        None => format!("{}\r\n{}\r\n", html, disclaimer),
+    }
+}
+
+/// Replaces the text of a leaf part, keeping its charset and transfer
+/// encoding where they can represent the new text.
+fn rewrite(part: &mut Part, edit: impl FnOnce(&str) -> String) -> bool {
+    let charset = part
+        .content_type_param("charset")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let latin1 = matches!(
+        charset.as_str(),
+        "iso-8859-1" | "latin1" | "iso8859-1" | "windows-1252" | "cp1252"
+    );
+    if !latin1 && !matches!(charset.as_str(), "" | "us-ascii" | "utf-8" | "utf8") {
+        return false;
+    }
+
+    let text = edit(&part.decoded_text());
+    let bytes: Vec<u8> = if latin1 && text.chars().all(|ch| (ch as u32) < 0x100) {
+        text.chars().map(|ch| ch as u8).collect()
+    } else {
+        if !text.is_ascii() && !charset.starts_with("utf") {
+            set_charset(part, "utf-8");
+        }
+        text.into_bytes()
+    };
+
+    let mut encoding = part.transfer_encoding();
+    let body = match encoding.as_str() {
+        "base64" => encode_base64(&bytes),
+        "quoted-printable" => encode_quoted_printable(&bytes),
+        _ => match String::from_utf8(bytes.clone()) {
+            Ok(body) if body.is_ascii() || encoding != "7bit" => body,
+            _ => {
+                encoding = "quoted-printable".to_string();
+                encode_quoted_printable(&bytes)
+            }
+        },
+    };
+    if encoding != part.transfer_encoding() {
+        set_header(part, "Content-Transfer-Encoding", encoding);
+    }
+    part.body = Body::Single(body);
+    true
+}
+
+fn set_charset(part: &mut Part, charset: &str) {
+    let (media_type, params) = parse_params(part.header("Content-Type").unwrap_or("text/plain"));
+    let mut value = media_type;
+    for (name, param) in params.iter().filter(|(name, _)| name != "charset") {
+        value.push_str(&format!(
+            "; {}=\"{}\"",
+            name,
+            param.replace(['"', '\\'], "")
+        ));
+    }
+    value.push_str(&format!("; charset={}", charset));
+    set_header(part, "Content-Type", value);
+}
+
+fn set_header(part: &mut Part, name: &str, value: String) {
+    match part
+        .headers
+        .iter_mut()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+    {
+        Some((_, existing)) => *existing = value,
+        None => part.headers.push((name.to_string(), value)),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Stage;
+    use serde_json::json;
+
+    fn request(
+        login: Option<&str>,
+        from: &str,
+        headers: &[(&str, &str)],
+        contents: &str,
+    ) -> Request {
+        let mut raw = String::new();
+        for (name, value) in headers {
+            raw.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        raw.push_str("\r\n");
+        raw.push_str(contents);
+        let mut builder = Request::builder()
+            .stage(Stage::Data)
+            .server_port(587)
+            .from(from)
+            .raw_message(raw);
+        if let Some(login) = login {
+            builder = builder.authenticated(login);
+        }
+        builder.build()
+    }
+
+    fn policy() -> DisclaimerPolicy {
+        DisclaimerPolicy::new()
+            .with_default(Disclaimer::new("Example Ltd".to_string()))
+            .with_domain(
+                "example.org".to_string(),
+                Disclaimer::new("Sent by {sender} for {domain}".to_string())
+                    .with_html("<p>Sent by <b>{sender}</b></p>".to_string()),
+            )
+            .with_login(
+                "jane".to_string(),
+                Disclaimer::new("Jane Müller, Geschäftsführerin".to_string()),
+            )
+    }
+
+    fn contents(response: &Response) -> &str {
+        match &response.modifications[0] {
+            Modification::ReplaceContents { value } => value.as_str().unwrap(),
+            _ => panic!("expected ReplaceContents"),
+        }
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let request = request(None, "joe@example.com", &[("Subject", "Hi")], "Hello\r\n");
+        let response = policy().apply(&request).unwrap();
+        assert_eq!(response.modifications.len(), 1);
+        assert_eq!(contents(&response), "Hello\r\n\r\nExample Ltd\r\n");
+
+        // Non-ASCII text in a 7bit message changes the top-level headers.
+        let request = self::request(
+            Some("jane"),
+            "jane@example.org",
+            &[("Content-Type", "text/plain; format=flowed")],
+            "Hello",
+        );
+        let response = policy().apply(&request).unwrap();
+        assert_eq!(
+            contents(&response),
+            "Hello\r\n\r\nJane M=C3=BCller, Gesch=C3=A4ftsf=C3=BChrerin\r\n"
+        );
+        let headers: Vec<_> = response.modifications[1..]
+            .iter()
+            .map(|modification| serde_json::to_value(modification).unwrap())
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                json!({"type": "changeHeader", "index": 1, "name": "Content-Type",
+                       "value": "text/plain; format=\"flowed\"; charset=utf-8"}),
+                json!({"type": "addHeader", "name": "Content-Transfer-Encoding",
+                       "value": "quoted-printable"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multipart() {
+        let body = "--mixed\r\n\
+            Content-Type: multipart/alternative; boundary=\"alt\"\r\n\r\n\
+            --alt\r\nContent-Type: text/plain; charset=utf-8\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\r\nGr=C3=BC=C3=9Fe\r\n\
+            --alt\r\nContent-Type: text/html; charset=utf-8\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\nPGh0bWw+PGJvZHk+SGk8L2JvZHk+PC9odG1sPg==\r\n\
+            --alt--\r\n\r\n\
+            --mixed\r\nContent-Type: text/plain; name=\"notes.txt\"\r\n\r\nnotes\r\n\
+            --mixed--\r\n";
+        let request = request(
+            None,
+            "joe@Example.org",
+            &[("Content-Type", "multipart/mixed; boundary=\"mixed\"")],
+            body,
+        );
+        let response = policy().apply(&request).unwrap();
+        assert_eq!(response.modifications.len(), 1);
+        let message = Message {
+            contents: contents(&response).into(),
+            ..request.message.clone().unwrap()
+        };
+        let root = Part::from_message(&message);
+        let leaves = root.leaves();
+        assert_eq!(
+            leaves[0].decoded_text(),
+            "Grüße\r\n\r\nSent by joe@Example.org for example.org\r\n"
+        );
+        assert_eq!(
+            leaves[1].decoded_text(),
+            "<html><body>Hi<p>Sent by <b>joe@Example.org</b></p>\r\n</body></html>"
+        );
+        assert_eq!(leaves[2].decoded_text(), "notes");
+
+        // Applying it again finds the disclaimer and changes nothing.
+        let mut again = request.clone();
+        again.message = Some(message);
+        assert!(policy().apply(&again).is_none());
+    }
+
+    #[test]
+    fn test_untouched() {
+        let signed = request(
+            None,
+            "joe@example.com",
+            &[(
+                "Content-Type",
+                "multipart/signed; protocol=\"application/pgp-signature\"; boundary=\"s\"",
+            )],
+            "--s\r\nContent-Type: text/plain\r\n\r\nHello\r\n--s\r\n\
+             Content-Type: application/pgp-signature\r\n\r\nsig\r\n--s--\r\n",
+        );
+        assert!(policy().apply(&signed).is_none());
+
+        let reply = request(
+            None,
+            "joe@example.com",
+            &[],
+            "Thanks!\r\n\r\n> Hello\r\n>\r\n> Example Ltd\r\n",
+        );
+        assert!(policy().apply(&reply).is_none());
+
+        let unconfigured = DisclaimerPolicy::new()
+            .with_domain("example.org".to_string(), Disclaimer::new("x".to_string()));
+        assert!(unconfigured
+            .apply(&request(None, "joe@example.com", &[], "Hello"))
+            .is_none());
+
+        let mut latin1 = request(
+            None,
+            "joe@example.com",
+            &[("Content-Type", "text/plain; charset=iso-8859-1")],
+            "",
+        );
+        latin1.message.as_mut().unwrap().contents = b"Gr\xfc\xdfe\r\n".to_vec().into();
+        assert!(policy().apply(&latin1).is_none());
+    }
+}
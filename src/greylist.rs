@@ -0,0 +1,446 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Greylisting at `Stage::Rcpt`.
+//!
+//! Delivery attempts are tracked per triplet of client network, envelope
+//! sender and recipient. The first attempt is deferred with a 451 4.7.1
+//! response; a retry after the initial delay but within the retry window
+//! passes, and the triplet is accepted from then on. Networks with enough
+//! passed triplets are whitelisted entirely.
+
+use crate::net::IpNetwork;
+use crate::request::{Request, Stage};
+use crate::response::{Action, Response, SmtpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+#[cfg(feature = "json")]
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// State kept for a triplet or a whitelisted network, times in seconds
+/// since the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub passes: u32,
+}
+
+/// Storage for greylisting state.
+pub trait Store {
+    fn get(&self, key: &str) -> io::Result<Option<Entry>>;
+    fn put(&self, key: &str, entry: Entry) -> io::Result<()>;
+    /// Removes all entries for which `expired` returns true, returning how
+    /// many were removed.
+    fn purge(&self, expired: &dyn Fn(&Entry) -> bool) -> io::Result<usize>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> io::Result<Option<Entry>> {
+        Ok(lock(&self.entries).get(key).copied())
+    }
+
+    fn put(&self, key: &str, entry: Entry) -> io::Result<()> {
+        lock(&self.entries).insert(key.to_string(), entry);
+        Ok(())
+    }
+
+    fn purge(&self, expired: &dyn Fn(&Entry) -> bool) -> io::Result<usize> {
+        let mut entries = lock(&self.entries);
+        let before = entries.len();
+        entries.retain(|_, entry| !expired(entry));
+        Ok(before - entries.len())
+    }
+}
+
+/// Keeps the state in memory and persists it as a JSON file after every
+/// change, replacing the file atomically.
+///
+/// Suited to low and moderate traffic; busy servers should implement
+/// [`Store`] on top of a database.
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[cfg(feature = "json")]
+impl FileStore {
+    /// Opens the store, starting empty if the file does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    fn save(&self, entries: &HashMap<String, Entry>) -> io::Result<()> {
+        let data = serde_json::to_vec(entries)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, data)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
+
+#[cfg(feature = "json")]
+impl Store for FileStore {
+    fn get(&self, key: &str) -> io::Result<Option<Entry>> {
+        Ok(lock(&self.entries).get(key).copied())
+    }
+
+    fn put(&self, key: &str, entry: Entry) -> io::Result<()> {
+        let mut entries = lock(&self.entries);
+        entries.insert(key.to_string(), entry);
+        self.save(&entries)
+    }
+
+    fn purge(&self, expired: &dyn Fn(&Entry) -> bool) -> io::Result<usize> {
+        let mut entries = lock(&self.entries);
+        let before = entries.len();
+        entries.retain(|_, entry| !expired(entry));
+        let removed = before - entries.len();
+        if removed > 0 {
+            self.save(&entries)?;
+        }
+        Ok(removed)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[derive(Debug, Clone)]
+pub struct GreylistConfig {
+    /// How long a new triplet is deferred.
+    pub initial_delay: Duration,
+    /// How long after the initial delay a retry is accepted; later retries
+    /// start over.
+    pub retry_window: Duration,
+    /// How long passed triplets and whitelisted networks are remembered
+    /// without traffic.
+    pub lifetime: Duration,
+    /// Number of passed triplets after which the whole client network is
+    /// whitelisted, `0` disables auto-whitelisting.
+    pub auto_whitelist_after: u32,
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+    /// Whether authenticated clients bypass greylisting.
+    pub skip_authenticated: bool,
+}
+
+impl Default for GreylistConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(5 * 60),
+            retry_window: Duration::from_secs(4 * 60 * 60),
+            lifetime: Duration::from_secs(36 * 24 * 60 * 60),
+            auto_whitelist_after: 5,
+            ipv4_prefix: 24,
+            ipv6_prefix: 64,
+            skip_authenticated: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Greylist<S: Store> {
+    config: GreylistConfig,
+    store: S,
+}
+
+impl<S: Store> Greylist<S> {
+    pub fn new(config: GreylistConfig, store: S) -> Self {
+        Self { config, store }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the deferral response if the recipient is greylisted.
+    pub fn check(&self, request: &Request) -> io::Result<Option<Response>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.check_at(request, now)
+    }
+
+    /// Same as [`Greylist::check`] at an explicit time in seconds since
+    /// the Unix epoch.
+    pub fn check_at(&self, request: &Request, now: u64) -> io::Result<Option<Response>> {
+        let context = &request.context;
+        if context.stage != Stage::Rcpt
+            || (self.config.skip_authenticated && context.sasl.is_some())
+        {
+            return Ok(None);
+        }
+        let (Some(ip), Some(envelope)) = (context.client.ip_addr(), request.envelope.as_ref())
+        else {
+            return Ok(None);
+        };
+        let Some(recipient) = envelope.to.last() else {
+            return Ok(None);
+        };
+
+        let network = IpNetwork::containing(ip, self.config.ipv4_prefix, self.config.ipv6_prefix);
+        let network_key = format!("network|{}", network);
+        let lifetime = self.config.lifetime.as_secs();
+
+        if self.config.auto_whitelist_after > 0 {
+            if let Some(mut entry) = self.store.get(&network_key)? {
+                if entry.passes >= self.config.auto_whitelist_after
+                    && entry.last_seen + lifetime >= now
+                {
+                    entry.last_seen = now;
+                    self.store.put(&network_key, entry)?;
+                    return Ok(None);
+                }
+            }
+        }
+
+        let triplet_key = format!(
+            "triplet|{}|{}|{}",
+            network,
+            envelope.from.address.to_lowercase(),
+            recipient.address.to_lowercase()
+        );
+        let entry = self.store.get(&triplet_key)?;
+        let delay = self.config.initial_delay.as_secs();
+        let window = self.config.retry_window.as_secs();
+
+        match entry {
+            Some(mut entry) if entry.passes > 0 && entry.last_seen + lifetime >= now => {
+                entry.last_seen = now;
+                entry.passes = entry.passes.saturating_add(1);
+                self.store.put(&triplet_key, entry)?;
+                Ok(None)
+            }
+            Some(mut entry)
+                if entry.passes == 0
+                    && now >= entry.first_seen + delay
+                    && now <= entry.first_seen + delay + window =>
+            {
+                entry.last_seen = now;
+                entry.passes = 1;
+                self.store.put(&triplet_key, entry)?;
+                self.record_pass(&network_key, now)?;
+                Ok(None)
+            }
+            Some(entry) if entry.passes == 0 && now < entry.first_seen + delay => {
+                let entry = Entry {
+                    last_seen: now,
+                    ..entry
+                };
+                self.store.put(&triplet_key, entry)?;
+                Ok(Some(deferral()))
+            }
+            _ => {
+                let entry = Entry {
+                    first_seen: now,
+                    last_seen: now,
+                    passes: 0,
+                };
+                self.store.put(&triplet_key, entry)?;
+                Ok(Some(deferral()))
+            }
+        }
+    }
+
+    fn record_pass(&self, network_key: &str, now: u64) -> io::Result<()> {
+        if self.config.auto_whitelist_after == 0 {
+            return Ok(());
+        }
+        let entry = match self.store.get(network_key)? {
+            Some(entry) => Entry {
+                last_seen: now,
+                passes: entry.passes.saturating_add(1),
+                ..entry
+            },
+            None => Entry {
+                first_seen: now,
+                last_seen: now,
+                passes: 1,
+            },
+        };
+        self.store.put(network_key, entry)
+    }
+
+    /// Removes expired triplets and whitelist entries.
+    pub fn purge_at(&self, now: u64) -> io::Result<usize> {
+        let pending = (self.config.initial_delay + self.config.retry_window).as_secs();
+        let lifetime = self.config.lifetime.as_secs();
+        self.store.purge(&|entry| {
+            if entry.passes == 0 {
+                entry.first_seen + pending < now
+            } else {
+                entry.last_seen + lifetime < now
+            }
+        })
+    }
+}
+
+fn deferral() -> Response {
+    Response {
+        action: Action::Reject,
+        response: Some(SmtpResponse {
+            status: Some(451),
+            enhanced_status: Some("4.7.1".to_string()),
+            message: Some("Greylisted, please try again later".to_string()),
+            disconnect: false,
+        }),
+        modifications: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60;
+
+    fn request(ip: &str, from: &str, to: &str) -> Request {
+        Request::builder()
+            .stage(Stage::Rcpt)
+            .client_ip(ip)
+            .from(from)
+            .to(to)
+            .build()
+    }
+
+    fn is_deferred(response: Option<Response>) -> bool {
+        match response {
+            Some(response) => {
+                let smtp = response.response.unwrap();
+                assert_eq!(smtp.status, Some(451));
+                assert_eq!(smtp.enhanced_status.as_deref(), Some("4.7.1"));
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[test]
+    fn test_defer_then_pass() {
+        let greylist = Greylist::new(GreylistConfig::default(), MemoryStore::new());
+        let request = request("192.0.2.10", "john@example.com", "bill@foobar.com");
+        let start = 1_700_000_000;
+
+        assert!(is_deferred(greylist.check_at(&request, start).unwrap()));
+        assert!(is_deferred(
+            greylist.check_at(&request, start + MINUTE).unwrap()
+        ));
+        assert!(!is_deferred(
+            greylist.check_at(&request, start + 6 * MINUTE).unwrap()
+        ));
+        // Retries from another host in the same network pass as well.
+        let sibling = self::request("192.0.2.11", "john@example.com", "bill@foobar.com");
+        assert!(!is_deferred(
+            greylist.check_at(&sibling, start + 7 * MINUTE).unwrap()
+        ));
+
+        let other = self::request("192.0.2.10", "john@example.com", "jane@foobar.com");
+        assert!(is_deferred(greylist.check_at(&other, start).unwrap()));
+
+        let mut data = request.clone();
+        data.context.stage = Stage::Data;
+        let fresh = Greylist::new(GreylistConfig::default(), MemoryStore::new());
+        assert!(fresh.check_at(&data, start).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_late_retry_starts_over() {
+        let greylist = Greylist::new(GreylistConfig::default(), MemoryStore::new());
+        let request = request("192.0.2.10", "john@example.com", "bill@foobar.com");
+        let start = 1_700_000_000;
+
+        assert!(is_deferred(greylist.check_at(&request, start).unwrap()));
+        let late = start + 5 * 60 * MINUTE;
+        assert!(is_deferred(greylist.check_at(&request, late).unwrap()));
+        assert!(!is_deferred(
+            greylist.check_at(&request, late + 5 * MINUTE).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_auto_whitelist() {
+        let config = GreylistConfig {
+            auto_whitelist_after: 2,
+            ..Default::default()
+        };
+        let greylist = Greylist::new(config, MemoryStore::new());
+        let start = 1_700_000_000;
+
+        for recipient in ["a@foobar.com", "b@foobar.com"] {
+            let request = request("2001:db8::1", "john@example.com", recipient);
+            assert!(is_deferred(greylist.check_at(&request, start).unwrap()));
+            assert!(!is_deferred(
+                greylist.check_at(&request, start + 10 * MINUTE).unwrap()
+            ));
+        }
+
+        let new_triplet = request("2001:db8::2", "jane@example.com", "c@foobar.com");
+        assert!(!is_deferred(
+            greylist
+                .check_at(&new_triplet, start + 11 * MINUTE)
+                .unwrap()
+        ));
+
+        let other_network = request("2001:db9::2", "jane@example.com", "c@foobar.com");
+        assert!(is_deferred(
+            greylist
+                .check_at(&other_network, start + 11 * MINUTE)
+                .unwrap()
+        ));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_file_store_persists() {
+        let path = std::env::temp_dir().join(format!("greylist-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let request = request("192.0.2.10", "john@example.com", "bill@foobar.com");
+        let start = 1_700_000_000;
+
+        let greylist = Greylist::new(GreylistConfig::default(), FileStore::open(&path).unwrap());
+        assert!(is_deferred(greylist.check_at(&request, start).unwrap()));
+        drop(greylist);
+
+        let greylist = Greylist::new(GreylistConfig::default(), FileStore::open(&path).unwrap());
+        assert!(!is_deferred(
+            greylist.check_at(&request, start + 10 * MINUTE).unwrap()
+        ));
+        assert_eq!(greylist.purge_at(start + 10 * MINUTE).unwrap(), 0);
+        assert_eq!(greylist.purge_at(start + 40 * 24 * 60 * MINUTE).unwrap(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,413 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Fail2ban-style banning of clients based on earlier hook verdicts.
+//!
+//! Every response sent for a client is recorded with [`Jail::record`].
+//! Once a client collects too many failures within a rule's time frame it
+//! is banned, and [`Jail::check`] rejects its connections at
+//! `Stage::Connect` until the ban expires. Each repeated ban lasts longer
+//! than the previous one.
+//!
+//! ```
+//! use std::time::Duration;
+//! use stalwart_mta_hook_types::jail::{Jail, JailRule};
+//! use stalwart_mta_hook_types::Stage;
+//!
+//! let jail = Jail::new()
+//!     .with_rule(JailRule::new(vec![Stage::Auth], 5, Duration::from_secs(600)))
+//!     .with_rule(JailRule::new(vec![Stage::Rcpt, Stage::Data], 20, Duration::from_secs(3600)))
+//!     .with_ban_time(Duration::from_secs(600), Duration::from_secs(7 * 86400));
+//! ```
+
+use crate::net::IpNetwork;
+use crate::request::{Request, Stage};
+use crate::response::{Action, Response, SmtpResponse};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Bans a client after `max_failures` failures at the given stages within
+/// `find_time`.
+#[derive(Debug, Clone)]
+pub struct JailRule {
+    stages: Vec<Stage>,
+    actions: Vec<Action>,
+    max_failures: usize,
+    find_time: Duration,
+}
+
+impl JailRule {
+    pub fn new(stages: Vec<Stage>, max_failures: usize, find_time: Duration) -> Self {
+        Self {
+            stages,
+            actions: vec![Action::Reject],
+            max_failures,
+            find_time,
+        }
+    }
+
+    /// Sets the actions counted as failures (only `Reject` by default).
+    pub fn with_actions(mut self, actions: Vec<Action>) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    fn matches(&self, stage: Stage, response: &Response) -> bool {
+        (self.stages.is_empty() || self.stages.contains(&stage))
+            && self.actions.contains(&response.action)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inmate {
+    failures: HashMap<usize, VecDeque<Instant>>,
+    bans: u32,
+    banned_until: Option<BanEnd>,
+}
+
+/// When a ban ends; bans too long to represent never do.
+#[derive(Debug, Clone, Copy)]
+enum BanEnd {
+    At(Instant),
+    Never,
+}
+
+#[derive(Debug)]
+pub struct Jail {
+    rules: Vec<JailRule>,
+    ban_time: Duration,
+    max_ban_time: Duration,
+    ban_factor: u32,
+    reset_after: Duration,
+    ignore: Vec<IpNetwork>,
+    response: SmtpResponse,
+    inmates: Mutex<HashMap<IpAddr, Inmate>>,
+}
+
+impl Default for Jail {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            ban_time: Duration::from_secs(10 * 60),
+            max_ban_time: Duration::from_secs(7 * 24 * 60 * 60),
+            ban_factor: 2,
+            reset_after: Duration::from_secs(24 * 60 * 60),
+            ignore: Vec::new(),
+            response: SmtpResponse {
+                status: Some(421),
+                enhanced_status: Some("4.7.0".to_string()),
+                message: Some("Too many errors, try again later".to_string()),
+                disconnect: true,
+            },
+            inmates: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Jail {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, rule: JailRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Sets the duration of the first ban and the upper bound for
+    /// escalated bans (10 minutes and 7 days by default). Bans too long to
+    /// end, such as `Duration::MAX`, last until [`Jail::unban`].
+    pub fn with_ban_time(mut self, ban_time: Duration, max_ban_time: Duration) -> Self {
+        self.ban_time = ban_time;
+        self.max_ban_time = max_ban_time;
+        self
+    }
+
+    /// Sets the factor each repeated ban is multiplied by (2 by default).
+    pub fn with_ban_factor(mut self, factor: u32) -> Self {
+        self.ban_factor = factor.max(1);
+        self
+    }
+
+    /// Sets how long after its last ban a client starts over with the
+    /// initial ban time (1 day by default).
+    pub fn with_reset_after(mut self, reset_after: Duration) -> Self {
+        self.reset_after = reset_after;
+        self
+    }
+
+    /// Never bans clients from the given network.
+    pub fn with_ignore(mut self, network: IpNetwork) -> Self {
+        self.ignore.push(network);
+        self
+    }
+
+    /// Sets the disconnecting reject sent to banned clients
+    /// (421 4.7.0 by default).
+    pub fn with_response(mut self, status: u16, enhanced_status: String, message: String) -> Self {
+        self.response = SmtpResponse {
+            status: Some(status),
+            enhanced_status: Some(enhanced_status),
+            message: Some(message),
+            disconnect: true,
+        };
+        self
+    }
+
+    /// Rejects the connection if the client is banned.
+    pub fn check(&self, request: &Request) -> Option<Response> {
+        self.check_at(request, Instant::now())
+    }
+
+    /// Same as [`Jail::check`] at an explicit point in time.
+    pub fn check_at(&self, request: &Request, now: Instant) -> Option<Response> {
+        if request.context.stage != Stage::Connect {
+            return None;
+        }
+        let ip = request.context.client.ip_addr()?.to_canonical();
+        self.banned_for_at(&ip, now).map(|_| Response {
+            action: Action::Reject,
+            response: Some(self.response.clone()),
+            modifications: Vec::new(),
+        })
+    }
+
+    /// Records the response given to a request, banning the client if it
+    /// exceeded a rule. Returns the ban duration if a ban was started.
+    pub fn record(&self, request: &Request, response: &Response) -> Option<Duration> {
+        self.record_at(request, response, Instant::now())
+    }
+
+    pub fn record_at(
+        &self,
+        request: &Request,
+        response: &Response,
+        now: Instant,
+    ) -> Option<Duration> {
+        let stage = request.context.stage;
+        let ip = request.context.client.ip_addr()?.to_canonical();
+        if self.ignore.iter().any(|network| network.contains(&ip)) {
+            return None;
+        }
+        let matching: Vec<usize> = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matches(stage, response))
+            .map(|(pos, _)| pos)
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+
+        let mut inmates = self.lock();
+        let inmate = inmates.entry(ip).or_default();
+        let mut exceeded = false;
+        for pos in matching {
+            let rule = &self.rules[pos];
+            let failures = inmate.failures.entry(pos).or_default();
+            while failures
+                .front()
+                .is_some_and(|time| now.saturating_duration_since(*time) > rule.find_time)
+            {
+                failures.pop_front();
+            }
+            failures.push_back(now);
+            exceeded |= failures.len() >= rule.max_failures;
+        }
+        if !exceeded {
+            return None;
+        }
+
+        if inmate.banned_until.is_some_and(|until| match until {
+            BanEnd::At(until) => now.saturating_duration_since(until) > self.reset_after,
+            BanEnd::Never => false,
+        }) {
+            inmate.bans = 0;
+        }
+        let duration = self
+            .ban_time
+            .saturating_mul(self.ban_factor.saturating_pow(inmate.bans))
+            .min(self.max_ban_time);
+        inmate.bans = inmate.bans.saturating_add(1);
+        inmate.banned_until = Some(now.checked_add(duration).map_or(BanEnd::Never, BanEnd::At));
+        inmate.failures.clear();
+        Some(duration)
+    }
+
+    /// The remaining ban time of `ip`, if it is banned, or `Duration::MAX`
+    /// for a ban without end.
+    pub fn banned_for(&self, ip: &IpAddr) -> Option<Duration> {
+        self.banned_for_at(ip, Instant::now())
+    }
+
+    pub fn banned_for_at(&self, ip: &IpAddr, now: Instant) -> Option<Duration> {
+        self.lock()
+            .get(&ip.to_canonical())
+            .and_then(|inmate| inmate.banned_until)
+            .and_then(|until| match until {
+                BanEnd::At(until) => (until > now).then(|| until - now),
+                BanEnd::Never => Some(Duration::MAX),
+            })
+    }
+
+    /// Lifts the ban of `ip` and forgets its history.
+    pub fn unban(&self, ip: &IpAddr) -> bool {
+        self.lock().remove(&ip.to_canonical()).is_some()
+    }
+
+    /// Forgets clients without recent failures or bans.
+    pub fn purge(&self) {
+        self.purge_at(Instant::now())
+    }
+
+    pub fn purge_at(&self, now: Instant) {
+        let find_time = self
+            .rules
+            .iter()
+            .map(|rule| rule.find_time)
+            .max()
+            .unwrap_or_default();
+        self.lock().retain(|_, inmate| {
+            let failing = inmate
+                .failures
+                .values()
+                .filter_map(|failures| failures.back())
+                .any(|time| now.saturating_duration_since(*time) <= find_time);
+            let remembered = inmate.banned_until.is_some_and(|until| match until {
+                BanEnd::At(until) => now.saturating_duration_since(until) <= self.reset_after,
+                BanEnd::Never => true,
+            });
+            failing || remembered
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, Inmate>> {
+        self.inmates.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(stage: Stage, ip: &str) -> Request {
+        Request::builder()
+            .stage(stage)
+            .client_ip(ip)
+            .client_port(4000)
+            .build()
+    }
+
+    fn reject() -> Response {
+        Response::reject(550, "User unknown".to_string())
+    }
+
+    #[test]
+    fn test_ban_and_escalation() {
+        let minute = Duration::from_secs(60);
+        let jail = Jail::new()
+            .with_rule(JailRule::new(vec![Stage::Rcpt], 3, 10 * minute))
+            .with_ban_time(10 * minute, 25 * minute);
+        let rcpt = request(Stage::Rcpt, "192.0.2.1");
+        let connect = request(Stage::Connect, "192.0.2.1");
+        let start = Instant::now();
+
+        assert_eq!(jail.record_at(&rcpt, &reject(), start), None);
+        assert_eq!(jail.record_at(&rcpt, &Response::accept(), start), None);
+        assert_eq!(
+            jail.record_at(&request(Stage::Data, "192.0.2.1"), &reject(), start),
+            None
+        );
+        assert_eq!(jail.record_at(&rcpt, &reject(), start), None);
+        assert!(jail.check_at(&connect, start).is_none());
+        assert_eq!(jail.record_at(&rcpt, &reject(), start), Some(10 * minute));
+
+        let response = jail.check_at(&connect, start + minute).unwrap();
+        let smtp = response.response.unwrap();
+        assert_eq!(response.action, Action::Reject);
+        assert_eq!(smtp.status, Some(421));
+        assert!(smtp.disconnect);
+        assert!(jail
+            .check_at(&request(Stage::Connect, "192.0.2.2"), start)
+            .is_none());
+        assert!(jail.check_at(&rcpt, start + minute).is_none());
+
+        let later = start + 11 * minute;
+        assert!(jail.check_at(&connect, later).is_none());
+        for _ in 0..3 {
+            jail.record_at(&rcpt, &reject(), later);
+        }
+        assert_eq!(
+            jail.banned_for_at(&"192.0.2.1".parse().unwrap(), later),
+            Some(20 * minute)
+        );
+        let later = later + 21 * minute;
+        for _ in 0..2 {
+            jail.record_at(&rcpt, &reject(), later);
+        }
+        assert_eq!(jail.record_at(&rcpt, &reject(), later), Some(25 * minute));
+
+        assert!(jail.unban(&"::ffff:192.0.2.1".parse().unwrap()));
+        assert!(jail.check_at(&connect, later).is_none());
+    }
+
+    #[test]
+    fn test_ban_forever() {
+        let jail = Jail::new()
+            .with_rule(JailRule::new(vec![Stage::Rcpt], 1, Duration::from_secs(60)))
+            .with_ban_time(Duration::MAX, Duration::MAX);
+        let start = Instant::now();
+        let rcpt = request(Stage::Rcpt, "192.0.2.1");
+        assert_eq!(jail.record_at(&rcpt, &reject(), start), Some(Duration::MAX));
+
+        let much_later = start + Duration::from_secs(100 * 365 * 86400);
+        let connect = request(Stage::Connect, "192.0.2.1");
+        assert!(jail.check_at(&connect, much_later).is_some());
+        assert_eq!(
+            jail.banned_for_at(&"192.0.2.1".parse().unwrap(), much_later),
+            Some(Duration::MAX)
+        );
+        jail.purge_at(much_later);
+        assert_eq!(jail.len(), 1);
+    }
+
+    #[test]
+    fn test_failures_expire_and_ignore() {
+        let minute = Duration::from_secs(60);
+        let jail = Jail::new()
+            .with_rule(JailRule::new(vec![Stage::Auth], 2, minute))
+            .with_ignore("10.0.0.0/8".parse().unwrap());
+        let start = Instant::now();
+        let auth = request(Stage::Auth, "2001:db8::1");
+
+        assert_eq!(jail.record_at(&auth, &reject(), start), None);
+        assert_eq!(jail.record_at(&auth, &reject(), start + 2 * minute), None);
+        assert!(jail
+            .record_at(&auth, &reject(), start + 2 * minute)
+            .is_some());
+
+        let trusted = request(Stage::Auth, "10.1.2.3");
+        for _ in 0..5 {
+            assert_eq!(jail.record_at(&trusted, &reject(), start), None);
+        }
+
+        jail.purge_at(start + 2 * minute);
+        assert_eq!(jail.len(), 1);
+        jail.purge_at(start + Duration::from_secs(2 * 24 * 60 * 60));
+        assert!(jail.is_empty());
+    }
+}
@@ -5,10 +5,60 @@
  * SPDX-License-Identifier: MIT OR Apache-2.0
  */
 
-pub mod modifications;
-pub mod request;
-pub mod response;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+#[cfg(feature = "arc")]
+pub mod arc;
+#[cfg(feature = "attachments")]
+pub mod attachments;
+#[cfg(feature = "bayes")]
+pub mod bayes;
+#[cfg(feature = "json")]
+pub mod borrowed;
+pub mod builder;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "clamav")]
+pub mod clamav;
+pub mod contents;
+#[cfg(feature = "disclaimer")]
+pub mod disclaimer;
+#[cfg(feature = "dkim")]
+pub mod dkim;
+pub mod greylist;
+pub mod jail;
+#[cfg(feature = "json")]
+pub mod limits;
+#[cfg(feature = "mime")]
+pub mod mime;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+pub mod net;
+#[cfg(feature = "json")]
+pub mod protocol;
+#[cfg(feature = "json")]
+pub mod quarantine;
+pub mod ratelimit;
+#[cfg(feature = "rules")]
+pub mod rules;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod session;
+#[cfg(feature = "spam")]
+pub mod spam;
+#[cfg(feature = "srs")]
+pub mod srs;
+#[cfg(feature = "subject")]
+pub mod subject;
+pub mod v1;
 
+// The modules of the latest protocol version.
+pub use v1::{modifications, request, response};
+
+pub use builder::RequestBuilder;
+pub use contents::Contents;
 pub use modifications::*;
 pub use request::*;
 pub use response::*;
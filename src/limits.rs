@@ -0,0 +1,534 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Parsing requests from untrusted input with size limits.
+//!
+//! [`Limits::check`] scans the JSON once without copying its values and
+//! stops at the first value over a limit, before any header, recipient or
+//! body is allocated; [`Limits::parse`] then deserializes the request. Syntax errors
+//! are left to `serde_json`, which reports them as usual.
+//!
+//! ```
+//! use stalwart_mta_hook_types::limits::{LimitError, Limits};
+//!
+//! let limits = Limits::new().with_max_recipients(1);
+//! let json = br#"{"context":{"stage":"rcpt","client":{"ip":"192.0.2.1",
+//!     "port":49152,"activeConnections":1},"server":{"port":25}},
+//!     "envelope":{"from":{"address":""},
+//!     "to":[{"address":"a@example.org"},{"address":"b@example.org"}]}}"#;
+//! assert!(matches!(
+//!     limits.parse(json),
+//!     Err(LimitError::TooManyRecipients { limit: 1 })
+//! ));
+//! ```
+
+use crate::request::Request;
+use std::fmt;
+
+/// Limits on the size of a request; the defaults are generous for real
+/// traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    max_body_size: usize,
+    max_headers: usize,
+    max_header_length: usize,
+    max_recipients: usize,
+    max_parameters: usize,
+    max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_body_size: 64 << 20,
+            max_headers: 1000,
+            max_header_length: 64 << 10,
+            max_recipients: 1000,
+            max_parameters: 32,
+            max_depth: 16,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LimitError {
+    /// The input is not a valid request.
+    Json(serde_json::Error),
+    /// The decoded message body exceeds the limit in bytes.
+    BodyTooLarge {
+        limit: usize,
+    },
+    /// The message has more headers and server headers than allowed.
+    TooManyHeaders {
+        limit: usize,
+    },
+    /// A header name or value is longer than allowed in bytes.
+    HeaderTooLong {
+        limit: usize,
+    },
+    TooManyRecipients {
+        limit: usize,
+    },
+    /// An address has more ESMTP parameters than allowed.
+    TooManyParameters {
+        limit: usize,
+    },
+    /// Objects and arrays are nested deeper than allowed.
+    TooDeep {
+        limit: usize,
+    },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::Json(err) => write!(f, "invalid hook request: {}", err),
+            LimitError::BodyTooLarge { limit } => {
+                write!(f, "message body exceeds {} bytes", limit)
+            }
+            LimitError::TooManyHeaders { limit } => {
+                write!(f, "message has more than {} headers", limit)
+            }
+            LimitError::HeaderTooLong { limit } => {
+                write!(f, "header exceeds {} bytes", limit)
+            }
+            LimitError::TooManyRecipients { limit } => {
+                write!(f, "envelope has more than {} recipients", limit)
+            }
+            LimitError::TooManyParameters { limit } => {
+                write!(f, "address has more than {} parameters", limit)
+            }
+            LimitError::TooDeep { limit } => {
+                write!(f, "request is nested deeper than {} levels", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LimitError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for LimitError {
+    fn from(err: serde_json::Error) -> Self {
+        LimitError::Json(err)
+    }
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum decoded body size in bytes (64 MiB by default).
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Sets the maximum number of headers and server headers together
+    /// (1000 by default).
+    pub fn with_max_headers(mut self, max_headers: usize) -> Self {
+        self.max_headers = max_headers;
+        self
+    }
+
+    /// Sets the maximum length of a header name or value in bytes (64 KiB
+    /// by default).
+    pub fn with_max_header_length(mut self, max_header_length: usize) -> Self {
+        self.max_header_length = max_header_length;
+        self
+    }
+
+    /// Sets the maximum number of recipients (1000 by default).
+    pub fn with_max_recipients(mut self, max_recipients: usize) -> Self {
+        self.max_recipients = max_recipients;
+        self
+    }
+
+    /// Sets the maximum number of ESMTP parameters per address (32 by
+    /// default).
+    pub fn with_max_parameters(mut self, max_parameters: usize) -> Self {
+        self.max_parameters = max_parameters;
+        self
+    }
+
+    /// Sets the maximum nesting of objects and arrays (16 by default; a
+    /// valid request needs 4).
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Checks a JSON request against the limits without parsing it.
+    pub fn check(&self, json: &[u8]) -> Result<(), LimitError> {
+        let mut scanner = Scanner {
+            limits: self,
+            input: json,
+            pos: 0,
+            headers: 0,
+        };
+        match scanner.value(Path::Root, 0) {
+            Ok(()) | Err(Stop::Syntax) => Ok(()),
+            Err(Stop::Limit(err)) => Err(err),
+        }
+    }
+
+    /// Checks and parses a JSON request.
+    pub fn parse(&self, json: &[u8]) -> Result<Request, LimitError> {
+        self.check(json)?;
+        Ok(serde_json::from_slice(json)?)
+    }
+}
+
+/// Why the scan ended early.
+enum Stop {
+    Limit(LimitError),
+    /// Invalid JSON, reported by `serde_json` instead.
+    Syntax,
+}
+
+/// The places in a request that limits apply to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Path {
+    Root,
+    Envelope,
+    Recipients,
+    Address,
+    Parameters,
+    Message,
+    Headers,
+    Header,
+    HeaderField,
+    Contents,
+    Other,
+}
+
+impl Path {
+    fn key(self, key: &[u8]) -> Path {
+        match (self, key) {
+            (Path::Root, b"envelope") => Path::Envelope,
+            (Path::Root, b"message") => Path::Message,
+            (Path::Envelope, b"from") => Path::Address,
+            (Path::Envelope, b"to") => Path::Recipients,
+            (Path::Address, b"parameters") => Path::Parameters,
+            (Path::Message, b"headers" | b"serverHeaders") => Path::Headers,
+            (Path::Message, b"contents") => Path::Contents,
+            _ => Path::Other,
+        }
+    }
+
+    fn item(self) -> Path {
+        match self {
+            Path::Recipients => Path::Address,
+            Path::Headers => Path::Header,
+            Path::Header => Path::HeaderField,
+            _ => Path::Other,
+        }
+    }
+}
+
+struct Scanner<'a> {
+    limits: &'a Limits,
+    input: &'a [u8],
+    pos: usize,
+    headers: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(&byte) = self.input.get(self.pos) {
+            if !matches!(byte, b' ' | b'\t' | b'\r' | b'\n') {
+                return Some(byte);
+            }
+            self.pos += 1;
+        }
+        None
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Stop> {
+        if self.peek() != Some(byte) {
+            return Err(Stop::Syntax);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self, path: Path, depth: usize) -> Result<(), Stop> {
+        match self.peek().ok_or(Stop::Syntax)? {
+            b'{' => self.object(path, depth + 1),
+            b'[' => self.array(path, depth + 1),
+            b'"' => {
+                let length = self.string()?;
+                let limit = match path {
+                    Path::Contents => self.limits.max_body_size,
+                    Path::HeaderField => self.limits.max_header_length,
+                    _ => return Ok(()),
+                };
+                if length > limit {
+                    return Err(Stop::Limit(match path {
+                        Path::Contents => LimitError::BodyTooLarge { limit },
+                        _ => LimitError::HeaderTooLong { limit },
+                    }));
+                }
+                Ok(())
+            }
+            _ => {
+                // Numbers and literals; serde_json validates them.
+                let rest = &self.input[self.pos..];
+                let len = rest
+                    .iter()
+                    .position(|&byte| {
+                        matches!(byte, b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n')
+                    })
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(Stop::Syntax);
+                }
+                self.pos += len;
+                Ok(())
+            }
+        }
+    }
+
+    fn object(&mut self, path: Path, depth: usize) -> Result<(), Stop> {
+        self.depth(depth)?;
+        self.pos += 1;
+        let mut count = 0;
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(Stop::Syntax);
+            }
+            let start = self.pos;
+            self.string()?;
+            let raw = &self.input[start..self.pos];
+            // Escaped keys name the same fields once decoded.
+            let decoded: String;
+            let key = if raw.contains(&b'\\') {
+                decoded = serde_json::from_slice(raw).map_err(|_| Stop::Syntax)?;
+                decoded.as_bytes()
+            } else {
+                &raw[1..raw.len() - 1]
+            };
+            count += 1;
+            if path == Path::Parameters && count > self.limits.max_parameters {
+                return Err(Stop::Limit(LimitError::TooManyParameters {
+                    limit: self.limits.max_parameters,
+                }));
+            }
+            self.expect(b':')?;
+            self.value(path.key(key), depth)?;
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => return Err(Stop::Syntax),
+            }
+        }
+    }
+
+    fn array(&mut self, path: Path, depth: usize) -> Result<(), Stop> {
+        self.depth(depth)?;
+        self.pos += 1;
+        let mut count = 0;
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            count += 1;
+            match path {
+                Path::Recipients if count > self.limits.max_recipients => {
+                    return Err(Stop::Limit(LimitError::TooManyRecipients {
+                        limit: self.limits.max_recipients,
+                    }));
+                }
+                Path::Headers => {
+                    self.headers += 1;
+                    if self.headers > self.limits.max_headers {
+                        return Err(Stop::Limit(LimitError::TooManyHeaders {
+                            limit: self.limits.max_headers,
+                        }));
+                    }
+                }
+                // Contents that are not UTF-8 come as byte values.
+                Path::Contents if count > self.limits.max_body_size => {
+                    return Err(Stop::Limit(LimitError::BodyTooLarge {
+                        limit: self.limits.max_body_size,
+                    }));
+                }
+                _ => {}
+            }
+            self.value(path.item(), depth)?;
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => return Err(Stop::Syntax),
+            }
+        }
+    }
+
+    fn depth(&self, depth: usize) -> Result<(), Stop> {
+        if depth > self.limits.max_depth {
+            return Err(Stop::Limit(LimitError::TooDeep {
+                limit: self.limits.max_depth,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Skips a string starting at the opening quote and returns its
+    /// decoded length in bytes.
+    fn string(&mut self) -> Result<usize, Stop> {
+        self.pos += 1;
+        let mut length = 0;
+        loop {
+            match *self.input.get(self.pos).ok_or(Stop::Syntax)? {
+                b'"' => {
+                    self.pos += 1;
+                    return Ok(length);
+                }
+                b'\\' => match *self.input.get(self.pos + 1).ok_or(Stop::Syntax)? {
+                    b'u' => {
+                        let hex = self
+                            .input
+                            .get(self.pos + 2..self.pos + 6)
+                            .and_then(|hex| std::str::from_utf8(hex).ok())
+                            .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                            .ok_or(Stop::Syntax)?;
+                        // A surrogate pair encodes one four-byte character.
+                        length += match hex {
+                            0..=0x7f => 1,
+                            0x80..=0x7ff => 2,
+                            0xd800..=0xdbff => 4,
+                            0xdc00..=0xdfff => 0,
+                            _ => 3,
+                        };
+                        self.pos += 6;
+                    }
+                    _ => {
+                        length += 1;
+                        self.pos += 2;
+                    }
+                },
+                _ => {
+                    length += 1;
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Stage;
+
+    fn json(request: &Request) -> Vec<u8> {
+        serde_json::to_vec(request).unwrap()
+    }
+
+    #[test]
+    fn test_limits() {
+        let request = Request::builder()
+            .stage(Stage::Data)
+            .from("john@example.com")
+            .from_param("SIZE", "1024")
+            .from_param("BODY", "8BITMIME")
+            .to("bill@example.org")
+            .to("jane@example.org")
+            .raw_message("Subject: Gr\u{fc}\u{df}e\r\nTo: bill@example.org\r\n\r\n\u{1f600}\r\n")
+            .build();
+        let input = json(&request);
+        assert!(Limits::new().parse(&input).is_ok());
+
+        // The body is six bytes: the emoji and CRLF.
+        let body = Limits::new().with_max_body_size(6);
+        assert!(body.check(&input).is_ok());
+        assert!(matches!(
+            body.with_max_body_size(5).check(&input),
+            Err(LimitError::BodyTooLarge { limit: 5 })
+        ));
+        assert!(matches!(
+            Limits::new().with_max_headers(1).check(&input),
+            Err(LimitError::TooManyHeaders { limit: 1 })
+        ));
+        // The longest header value is " bill@example.org\r\n".
+        assert!(Limits::new()
+            .with_max_header_length(19)
+            .check(&input)
+            .is_ok());
+        assert!(matches!(
+            Limits::new().with_max_header_length(18).check(&input),
+            Err(LimitError::HeaderTooLong { limit: 18 })
+        ));
+        assert!(matches!(
+            Limits::new().with_max_recipients(1).check(&input),
+            Err(LimitError::TooManyRecipients { limit: 1 })
+        ));
+        assert!(matches!(
+            Limits::new().with_max_parameters(1).check(&input),
+            Err(LimitError::TooManyParameters { limit: 1 })
+        ));
+        assert!(Limits::new().with_max_depth(4).check(&input).is_ok());
+        assert!(matches!(
+            Limits::new().with_max_depth(3).check(&input),
+            Err(LimitError::TooDeep { limit: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_hostile_input() {
+        // Deep nesting is refused before serde_json's recursion limit.
+        let deep = format!(r#"{{"context":{}}}"#, "[".repeat(100_000));
+        assert!(matches!(
+            Limits::new().parse(deep.as_bytes()),
+            Err(LimitError::TooDeep { limit: 16 })
+        ));
+
+        let mut headers = r#"{"message":{"headers":["#.to_string();
+        headers.push_str(&vec![r#"["X","y"]"#; 2000].join(","));
+        assert!(matches!(
+            Limits::new().check(headers.as_bytes()),
+            Err(LimitError::TooManyHeaders { limit: 1000 })
+        ));
+
+        let escaped = br#"{"envel\u006fpe":{"to":[{"address":"a"},{"address":"b"}]}}"#;
+        assert!(matches!(
+            Limits::new().with_max_recipients(1).check(escaped),
+            Err(LimitError::TooManyRecipients { limit: 1 })
+        ));
+
+        // Binary contents count byte values.
+        let binary = br#"{"message":{"contents":[1,2,3,4]}}"#;
+        assert!(matches!(
+            Limits::new().with_max_body_size(3).check(binary),
+            Err(LimitError::BodyTooLarge { limit: 3 })
+        ));
+
+        // Syntax errors come from serde_json.
+        assert!(Limits::new().check(b"{\"context\":").is_ok());
+        assert!(matches!(
+            Limits::new().parse(b"{\"context\":"),
+            Err(LimitError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,703 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Minimal MIME parsing and serialization of message bodies.
+//!
+//! The top-level headers of a hook request are in `Message::headers` and
+//! the body in `Message::contents`; [`Part::from_message`] combines both
+//! into a tree of parts. Transfer-encoded bodies are kept as they are and
+//! only decoded on request, so an unchanged tree serializes back to an
+//! equivalent body.
+
+use crate::request::Message;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+
+/// Multipart nesting beyond this depth is treated as an opaque body.
+pub const MAX_DEPTH: usize = 32;
+
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    /// A leaf body, still transfer-encoded.
+    Single(String),
+    Multipart {
+        boundary: String,
+        /// Text before the first delimiter, `None` if the body starts with
+        /// the delimiter.
+        preamble: Option<String>,
+        parts: Vec<Part>,
+        /// Everything after the closing delimiter, including its line end.
+        epilogue: String,
+    },
+}
+
+impl Part {
+    /// Parses a part consisting of headers, an empty line and the body.
+    pub fn parse(raw: &str) -> Self {
+        Self::parse_nested(raw, 0)
+    }
+
+    fn parse_nested(raw: &str, depth: usize) -> Self {
+        let (headers, body) = split_header_body(raw);
+        Self::with_body(parse_headers(headers), body, depth)
+    }
+
+    /// Builds the tree for the headers and body of a hook message. A body
+    /// that is not UTF-8 is decoded lossily.
+    pub fn from_message(message: &Message) -> Self {
+        Self::with_body(message.headers.clone(), &message.contents.to_str_lossy(), 0)
+    }
+
+    fn with_body(headers: Vec<(String, String)>, body: &str, depth: usize) -> Self {
+        let mut part = Self {
+            headers,
+            body: Body::Single(String::new()),
+        };
+        part.body = match part.content_type_param("boundary") {
+            Some(boundary) if part.is_multipart() && depth < MAX_DEPTH => {
+                split_multipart(body, &boundary, depth)
+                    .unwrap_or_else(|| Body::Single(body.to_string()))
+            }
+            _ => Body::Single(body.to_string()),
+        };
+        part
+    }
+
+    /// The first value of the header `name`, without surrounding whitespace.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// The lowercased media type, `text/plain` if none is given.
+    pub fn content_type(&self) -> String {
+        self.header("Content-Type")
+            .map(|value| parse_params(value).0)
+            .filter(|value| value.contains('/'))
+            .unwrap_or_else(|| "text/plain".to_string())
+    }
+
+    pub fn content_type_param(&self, name: &str) -> Option<String> {
+        param(&parse_params(self.header("Content-Type")?).1, name)
+    }
+
+    pub fn transfer_encoding(&self) -> String {
+        self.header("Content-Transfer-Encoding")
+            .map(|value| value.to_ascii_lowercase())
+            .unwrap_or_else(|| "7bit".to_string())
+    }
+
+    pub fn is_multipart(&self) -> bool {
+        self.content_type().starts_with("multipart/")
+    }
+
+    /// Whether the part is marked as attachment or carries a file name.
+    pub fn is_attachment(&self) -> bool {
+        self.header("Content-Disposition")
+            .is_some_and(|value| parse_params(value).0 == "attachment")
+            || self.filename().is_some()
+    }
+
+    /// The file name from `Content-Disposition` or the `name` parameter of
+    /// `Content-Type`, with RFC 2047/2231 encodings decoded.
+    pub fn filename(&self) -> Option<String> {
+        self.header("Content-Disposition")
+            .and_then(|value| param(&parse_params(value).1, "filename"))
+            .or_else(|| self.content_type_param("name"))
+            .map(|name| decode_encoded_words(&name))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// The body with its transfer encoding removed, empty for multiparts.
+    pub fn decoded_body(&self) -> Vec<u8> {
+        match &self.body {
+            Body::Single(body) => match self.transfer_encoding().as_str() {
+                "base64" => decode_base64(body).unwrap_or_else(|| body.as_bytes().to_vec()),
+                "quoted-printable" => decode_quoted_printable(body),
+                _ => body.as_bytes().to_vec(),
+            },
+            Body::Multipart { .. } => Vec::new(),
+        }
+    }
+
+    /// The decoded body as text, honouring the `charset` parameter.
+    pub fn decoded_text(&self) -> String {
+        let charset = self.content_type_param("charset").unwrap_or_default();
+        decode_charset(&charset, &self.decoded_body())
+    }
+
+    /// All leaf parts in depth-first order.
+    pub fn leaves(&self) -> Vec<&Part> {
+        let mut leaves = Vec::new();
+        self.collect_leaves(&mut leaves);
+        leaves
+    }
+
+    fn collect_leaves<'a>(&'a self, leaves: &mut Vec<&'a Part>) {
+        match &self.body {
+            Body::Single(_) => leaves.push(self),
+            Body::Multipart { parts, .. } => {
+                parts.iter().for_each(|part| part.collect_leaves(leaves))
+            }
+        }
+    }
+
+    /// Serializes headers and body.
+    pub fn to_mime_string(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in &self.headers {
+            write_header(&mut out, name, value);
+        }
+        out.push_str("\r\n");
+        out.push_str(&self.body_string());
+        out
+    }
+
+    /// Serializes the body only, as used for `Message::contents`.
+    pub fn body_string(&self) -> String {
+        match &self.body {
+            Body::Single(body) => body.clone(),
+            Body::Multipart {
+                boundary,
+                preamble,
+                parts,
+                epilogue,
+            } => {
+                let mut out = String::new();
+                if let Some(preamble) = preamble {
+                    out.push_str(preamble);
+                    out.push_str("\r\n");
+                }
+                for part in parts {
+                    out.push_str("--");
+                    out.push_str(boundary);
+                    out.push_str("\r\n");
+                    out.push_str(&part.to_mime_string());
+                    out.push_str("\r\n");
+                }
+                out.push_str("--");
+                out.push_str(boundary);
+                out.push_str("--");
+                out.push_str(epilogue);
+                out
+            }
+        }
+    }
+}
+
+/// The first value of the header `name` in `headers`, trimmed.
+pub fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// Appends `name: value` with a trailing CRLF, accepting values with or
+/// without the leading space and line end as found in hook requests.
+pub fn write_header(out: &mut String, name: &str, value: &str) {
+    out.push_str(name);
+    out.push(':');
+    if !value.starts_with([' ', '\t']) {
+        out.push(' ');
+    }
+    out.push_str(value.trim_end_matches(['\r', '\n']));
+    out.push_str("\r\n");
+}
+
+/// Splits a raw part at the first empty line.
+pub fn split_header_body(raw: &str) -> (&str, &str) {
+    if let Some(body) = raw.strip_prefix("\r\n").or_else(|| raw.strip_prefix('\n')) {
+        return ("", body);
+    }
+    let crlf = raw.find("\r\n\r\n").map(|pos| (pos, 4));
+    let lf = raw.find("\n\n").map(|pos| (pos, 2));
+    match (crlf, lf) {
+        (Some(a), Some(b)) => {
+            let (pos, len) = if a.0 < b.0 { a } else { b };
+            (&raw[..pos], &raw[pos + len..])
+        }
+        (Some((pos, len)), None) | (None, Some((pos, len))) => (&raw[..pos], &raw[pos + len..]),
+        (None, None) => (raw, ""),
+    }
+}
+
+/// Parses a header block, unfolding continuation lines.
+pub fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in raw.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push_str(line);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim_start().to_string()));
+        }
+    }
+    headers
+}
+
+fn split_multipart(body: &str, boundary: &str, depth: usize) -> Option<Body> {
+    let delimiter = format!("--{}", boundary);
+    let mut preamble = None;
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut pos = 0;
+
+    for line in body.split_inclusive('\n') {
+        let text = line.trim_end();
+        if text.starts_with(&delimiter) {
+            let rest = &text[delimiter.len()..];
+            let closing = rest == "--";
+            if rest.is_empty() || closing {
+                let before = strip_line_end(&body[start.unwrap_or(0)..pos]);
+                match start {
+                    Some(_) => parts.push(Part::parse_nested(before, depth + 1)),
+                    None if pos > 0 => preamble = Some(before.to_string()),
+                    None => {}
+                }
+                if closing {
+                    let epilogue = &body[pos + delimiter.len() + 2..];
+                    return Some(Body::Multipart {
+                        boundary: boundary.to_string(),
+                        preamble,
+                        parts,
+                        epilogue: epilogue.to_string(),
+                    });
+                }
+                start = Some(pos + line.len());
+            }
+        }
+        pos += line.len();
+    }
+
+    let start = start?;
+    parts.push(Part::parse_nested(&body[start..], depth + 1));
+    Some(Body::Multipart {
+        boundary: boundary.to_string(),
+        preamble,
+        parts,
+        epilogue: String::new(),
+    })
+}
+
+fn strip_line_end(text: &str) -> &str {
+    text.strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+        .unwrap_or(text)
+}
+
+/// Splits a structured header value into its lowercased main value and its
+/// parameters, decoding RFC 2231 extended and continued parameters.
+pub fn parse_params(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = split_unquoted(value, ';').into_iter();
+    let main = fields
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    let mut params: Vec<(String, String)> = Vec::new();
+    let mut continued: Vec<(String, usize, String)> = Vec::new();
+    for field in fields {
+        let Some((name, value)) = field.split_once('=') else {
+            continue;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let (name, extended) = match name.strip_suffix('*') {
+            Some(name) => (name.to_string(), true),
+            None => (name, false),
+        };
+        let index = name
+            .split_once('*')
+            .and_then(|(base, index)| Some((base.to_string(), index.parse::<usize>().ok()?)));
+        let value = if extended {
+            decode_extended_value(value, index.as_ref().is_some_and(|(_, index)| *index > 0))
+        } else {
+            unquote(value)
+        };
+        match index {
+            Some((base, index)) => continued.push((base, index, value)),
+            None => params.push((name, value)),
+        }
+    }
+
+    continued.sort_by_key(|(name, index, _)| (name.clone(), *index));
+    for (name, _, value) in continued {
+        match params.iter_mut().find(|(param, _)| *param == name) {
+            Some((_, existing)) => existing.push_str(&value),
+            None => params.push((name, value)),
+        }
+    }
+    (main, params)
+}
+
+/// Decodes `charset'language'percent-encoded` values; later continuations
+/// carry no charset prefix.
+fn decode_extended_value(value: &str, continuation: bool) -> String {
+    let value = unquote(value);
+    let (charset, encoded) = match value.splitn(3, '\'').collect::<Vec<_>>()[..] {
+        [charset, _, encoded] if !continuation => (charset.to_string(), encoded.to_string()),
+        _ => ("utf-8".to_string(), value.clone()),
+    };
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex = [chars.next().unwrap_or(b'0'), chars.next().unwrap_or(b'0')];
+            match std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(decoded) => bytes.push(decoded),
+                None => bytes.extend_from_slice(&[b'%', hex[0], hex[1]]),
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+    decode_charset(&charset, &bytes)
+}
+
+fn param(params: &[(String, String)], name: &str) -> Option<String> {
+    params
+        .iter()
+        .find(|(param, _)| param.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+}
+
+fn split_unquoted(value: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for ch in value.chars() {
+        if escaped {
+            escaped = false;
+        } else if quoted && ch == '\\' {
+            escaped = true;
+        } else if ch == '"' {
+            quoted = !quoted;
+        } else if ch == separator && !quoted {
+            fields.push(std::mem::take(&mut current));
+            continue;
+        }
+        current.push(ch);
+    }
+    fields.push(current);
+    fields
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(inner) => {
+            let mut out = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(ch) = chars.next() {
+                match ch {
+                    '\\' => out.extend(chars.next()),
+                    ch => out.push(ch),
+                }
+            }
+            out
+        }
+        None => value.to_string(),
+    }
+}
+
+/// Decodes base64, ignoring whitespace and missing padding.
+pub fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let compact: String = data
+        .chars()
+        .filter(|ch| !ch.is_ascii_whitespace())
+        .collect();
+    BASE64.decode(compact).ok()
+}
+
+pub fn decode_quoted_printable(data: &str) -> Vec<u8> {
+    let bytes = data.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'=' if bytes[pos + 1..].starts_with(b"\r\n") => pos += 3,
+            b'=' if bytes[pos + 1..].starts_with(b"\n") => pos += 2,
+            b'=' => match bytes
+                .get(pos + 1..pos + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    pos += 3;
+                }
+                None => {
+                    out.push(b'=');
+                    pos += 1;
+                }
+            },
+            byte => {
+                out.push(byte);
+                pos += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Encodes base64 in lines of 76 characters, each ending in CRLF.
+pub fn encode_base64(data: &[u8]) -> String {
+    let encoded = BASE64.encode(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38 + 2);
+    for line in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Encodes quoted-printable with CRLF line ends and soft line breaks
+/// keeping lines within 76 characters.
+pub fn encode_quoted_printable(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 3 / 2);
+    let mut lines = data.split(|byte| *byte == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut width = 0;
+        for (pos, byte) in line.iter().enumerate() {
+            let last = pos + 1 == line.len();
+            let encoded = match byte {
+                b' ' | b'\t' if !last => (*byte as char).to_string(),
+                b'!'..=b'<' | b'>'..=b'~' => (*byte as char).to_string(),
+                _ => format!("={:02X}", byte),
+            };
+            if width + encoded.len() > 75 {
+                out.push_str("=\r\n");
+                width = 0;
+            }
+            width += encoded.len();
+            out.push_str(&encoded);
+        }
+        if lines.peek().is_some() {
+            out.push_str("\r\n");
+        }
+    }
+    out
+}
+
+/// Converts text in `charset` to a string. UTF-8 and ASCII are decoded
+/// lossily, ISO-8859-1 and Windows-1252 byte by byte; unknown charsets
+/// are treated as UTF-8.
+pub fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    match charset.trim().to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "latin1" | "iso8859-1" | "windows-1252" | "cp1252" => {
+            bytes.iter().map(|byte| *byte as char).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Decodes RFC 2047 encoded words such as `=?UTF-8?B?...?=`, dropping the
+/// whitespace between adjacent encoded words.
+pub fn decode_encoded_words(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut pending_space = String::new();
+    let mut after_word = false;
+
+    while !rest.is_empty() {
+        if let Some((decoded, len)) = rest.strip_prefix("=?").and_then(decode_encoded_word) {
+            if !after_word {
+                out.push_str(&pending_space);
+            }
+            pending_space.clear();
+            out.push_str(&decoded);
+            rest = &rest[len + 2..];
+            after_word = true;
+            continue;
+        }
+        let ch = rest.chars().next().unwrap_or_default();
+        if ch.is_whitespace() {
+            pending_space.push(ch);
+        } else {
+            out.push_str(&pending_space);
+            pending_space.clear();
+            out.push(ch);
+            after_word = false;
+        }
+        rest = &rest[ch.len_utf8()..];
+    }
+    out.push_str(&pending_space);
+    out
+}
+
+/// Encodes non-ASCII text as RFC 2047 `UTF-8` base64 encoded words of at
+/// most 75 characters, separated by spaces. ASCII text is returned as is.
+pub fn encode_encoded_words(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for ch in value.chars() {
+        if chunk.len() + ch.len_utf8() > 45 {
+            words.push(format!("=?UTF-8?B?{}?=", BASE64.encode(&chunk)));
+            chunk.clear();
+        }
+        chunk.push(ch);
+    }
+    if !chunk.is_empty() {
+        words.push(format!("=?UTF-8?B?{}?=", BASE64.encode(&chunk)));
+    }
+    words.join(" ")
+}
+
+/// Decodes `charset?encoding?text?=`, returning the text and the consumed
+/// length.
+fn decode_encoded_word(word: &str) -> Option<(String, usize)> {
+    let mut sections = word.splitn(3, '?');
+    let charset = sections.next()?;
+    let encoding = sections.next()?;
+    let rest = sections.next()?;
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+    // RFC 2231 allows a language suffix such as `UTF-8*en`.
+    let charset = charset.split('*').next().unwrap_or(charset);
+    let bytes = match encoding {
+        "B" | "b" => decode_base64(text)?,
+        "Q" | "q" => decode_quoted_printable(&text.replace('_', " ")),
+        _ => return None,
+    };
+    let len = charset.len() + encoding.len() + end + 4;
+    Some((decode_charset(charset, &bytes), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "From: john@example.com\r\n\
+        Content-Type: multipart/mixed;\r\n\tboundary=\"outer\"\r\n\
+        \r\n\
+        This is a multi-part message.\r\n\
+        --outer\r\n\
+        Content-Type: multipart/alternative; boundary=inner\r\n\
+        \r\n\
+        --inner\r\n\
+        Content-Type: text/plain; charset=iso-8859-1\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        Gr=FC=DFe, soft =\r\nbreak\r\n\
+        --inner\r\n\
+        Content-Type: text/html; charset=utf-8\r\n\
+        \r\n\
+        <p>Hello</p>\r\n\
+        --inner--\r\n\
+        --outer\r\n\
+        Content-Type: application/octet-stream; name=\"=?UTF-8?Q?r=C3=A9sum=C3=A9?= .pdf\"\r\n\
+        Content-Disposition: attachment; filename*=utf-8''r%C3%A9sum%C3%A9.pdf\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        SGVsbG8g\r\nV29ybGQ=\r\n\
+        --outer--\r\n\
+        epilogue\r\n";
+
+    #[test]
+    fn test_parse_tree() {
+        let part = Part::parse(MESSAGE);
+        assert_eq!(part.content_type(), "multipart/mixed");
+        assert_eq!(
+            part.content_type_param("boundary").as_deref(),
+            Some("outer")
+        );
+
+        let leaves = part.leaves();
+        assert_eq!(leaves.len(), 3);
+        assert_eq!(leaves[0].decoded_text(), "Grüße, soft break");
+        assert_eq!(leaves[1].content_type(), "text/html");
+        assert_eq!(leaves[1].decoded_text(), "<p>Hello</p>");
+        assert!(!leaves[1].is_attachment());
+        assert!(leaves[2].is_attachment());
+        assert_eq!(leaves[2].filename().as_deref(), Some("résumé.pdf"));
+        assert_eq!(leaves[2].decoded_body(), b"Hello World");
+
+        assert_eq!(
+            part.to_mime_string(),
+            MESSAGE.replace("\r\n\tboundary", "\tboundary")
+        );
+    }
+
+    #[test]
+    fn test_from_message() {
+        let (headers, body) = split_header_body(MESSAGE);
+        let message = Message {
+            headers: parse_headers(headers)
+                .into_iter()
+                .map(|(name, value)| (name, format!(" {}\r\n", value)))
+                .collect(),
+            server_headers: Vec::new(),
+            contents: body.into(),
+            size: MESSAGE.len(),
+        };
+        let part = Part::from_message(&message);
+        assert_eq!(part.leaves().len(), 3);
+        assert_eq!(part.body_string(), body);
+    }
+
+    #[test]
+    fn test_encoded_words_and_params() {
+        assert_eq!(
+            decode_encoded_words("=?UTF-8?B?w6Rw?= =?ISO-8859-1?Q?fel_=FC?= and more"),
+            "äpfel ü and more"
+        );
+        assert_eq!(decode_encoded_words("plain =?bogus"), "plain =?bogus");
+        let long = "Grüße aus Köln ".repeat(5);
+        let encoded = encode_encoded_words(&long);
+        assert!(encoded.split(' ').all(|word| word.len() <= 75));
+        assert_eq!(decode_encoded_words(&encoded), long);
+        assert_eq!(encode_encoded_words("plain"), "plain");
+
+        let (main, params) =
+            parse_params("Attachment; filename*0=\"long\"; filename*1=\"name.txt\"; size=3");
+        assert_eq!(main, "attachment");
+        assert_eq!(param(&params, "filename").as_deref(), Some("longname.txt"));
+        assert_eq!(param(&params, "size").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_encoders() {
+        let text = format!("Grüße = {} \r\nend\t", "x".repeat(80));
+        let encoded = encode_quoted_printable(text.as_bytes());
+        assert!(encoded.lines().all(|line| line.len() <= 76));
+        assert!(encoded.starts_with("Gr=C3=BC=C3=9Fe =3D x"));
+        assert!(encoded.ends_with("=20\r\nend=09"));
+        assert_eq!(decode_quoted_printable(&encoded), text.as_bytes());
+
+        let data: Vec<u8> = (0..=255).collect();
+        let encoded = encode_base64(&data);
+        assert!(encoded.lines().all(|line| line.len() <= 76));
+        assert_eq!(decode_base64(&encoded).unwrap(), data);
+    }
+}
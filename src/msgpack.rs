@@ -0,0 +1,121 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! MessagePack encoding of requests and responses.
+//!
+//! Structs are written as maps with the field names of the JSON form, so
+//! archives stay readable when fields are added, and the same coercion
+//! rules apply. Bodies that are not UTF-8 are written as binary.
+//!
+//! ```
+//! use stalwart_mta_hook_types::{msgpack, Response};
+//!
+//! let data = msgpack::to_vec(&Response::discard()).unwrap();
+//! let response: Response = msgpack::from_slice(&data).unwrap();
+//! assert_eq!(response.action, stalwart_mta_hook_types::Action::Discard);
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum MsgpackError {
+    Encode(rmp_serde::encode::Error),
+    Decode(rmp_serde::decode::Error),
+}
+
+impl fmt::Display for MsgpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MsgpackError::Encode(err) => write!(f, "cannot encode MessagePack: {}", err),
+            MsgpackError::Decode(err) => write!(f, "cannot decode MessagePack: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MsgpackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MsgpackError::Encode(err) => Some(err),
+            MsgpackError::Decode(err) => Some(err),
+        }
+    }
+}
+
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, MsgpackError> {
+    rmp_serde::to_vec_named(value).map_err(MsgpackError::Encode)
+}
+
+pub fn to_writer<T: Serialize>(value: &T, mut writer: impl io::Write) -> Result<(), MsgpackError> {
+    rmp_serde::encode::write_named(&mut writer, value).map_err(MsgpackError::Encode)
+}
+
+pub fn from_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T, MsgpackError> {
+    rmp_serde::from_slice(data).map_err(MsgpackError::Decode)
+}
+
+pub fn from_reader<T: DeserializeOwned>(reader: impl io::Read) -> Result<T, MsgpackError> {
+    rmp_serde::from_read(reader).map_err(MsgpackError::Decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modifications::Modification;
+    use crate::request::{Request, Stage};
+    use crate::response::Response;
+    use serde_json::json;
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let request = Request::builder()
+            .stage(Stage::Data)
+            .from("john@example.com")
+            .from_param("SIZE", "1024")
+            .to("bill@example.org")
+            .raw_message(b"Subject: Hi\r\n\r\ncaf\xe9\r\n")
+            .build();
+        let mut data = Vec::new();
+        to_writer(&request, &mut data).unwrap();
+        let decoded: Request = from_reader(&data[..]).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&request).unwrap()
+        );
+
+        let response = Response::accept().with_modifications(vec![
+            Modification::replace_contents(b"caf\xe9\r\n".to_vec()),
+            Modification::delete_header(1, "X-Spam".to_string()),
+        ]);
+        let decoded: Response = from_slice(&to_vec(&response).unwrap()).unwrap();
+        match &decoded.modifications[0] {
+            Modification::ReplaceContents { value } => assert_eq!(value, &b"caf\xe9\r\n"[..]),
+            _ => panic!("expected ReplaceContents"),
+        }
+    }
+
+    #[test]
+    fn test_msgpack_coercion() {
+        let value = json!({
+            "action": "accept",
+            "modifications": [
+                {"type": "changeFrom", "value": "john@example.com",
+                 "parameters": {"SIZE": 1024, "SMTPUTF8": true, "ENVID": null}},
+            ],
+        });
+        let response: Response = from_slice(&to_vec(&value).unwrap()).unwrap();
+        match &response.modifications[0] {
+            Modification::ChangeFrom { parameters, .. } => {
+                assert_eq!(parameters["SIZE"].as_deref(), Some("1024"));
+                assert_eq!(parameters["SMTPUTF8"].as_deref(), Some("true"));
+                assert_eq!(parameters["ENVID"], None);
+            }
+            _ => panic!("expected ChangeFrom"),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! IP network helpers for matching and grouping client addresses.
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// An IP address with a prefix length, such as `192.168.0.0/16`.
+///
+/// The address is always stored with the host bits cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Returns `None` if the prefix is longer than the address.
+    pub fn new(address: IpAddr, prefix: u8) -> Option<Self> {
+        let address = address.to_canonical();
+        let max = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        (prefix <= max).then(|| Self {
+            address: mask(address, prefix),
+            prefix,
+        })
+    }
+
+    /// The network containing `address`, using `ipv4_prefix` or
+    /// `ipv6_prefix` depending on the address family.
+    pub fn containing(address: IpAddr, ipv4_prefix: u8, ipv6_prefix: u8) -> Self {
+        let address = address.to_canonical();
+        let prefix = match address {
+            IpAddr::V4(_) => ipv4_prefix.min(32),
+            IpAddr::V6(_) => ipv6_prefix.min(128),
+        };
+        Self {
+            address: mask(address, prefix),
+            prefix,
+        }
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        let address = address.to_canonical();
+        address.is_ipv4() == self.address.is_ipv4() && mask(address, self.prefix) == self.address
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    /// Parses `address/prefix`, or a single address as a host network.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.trim().split_once('/') {
+            Some((address, prefix)) => (
+                address,
+                Some(
+                    prefix
+                        .parse::<u8>()
+                        .map_err(|_| format!("invalid prefix length in {}", value))?,
+                ),
+            ),
+            None => (value.trim(), None),
+        };
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("invalid IP address in {}", value))?;
+        let prefix = prefix.unwrap_or(match address.to_canonical() {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        });
+        Self::new(address, prefix).ok_or_else(|| format!("invalid prefix length in {}", value))
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// Connects to `host:port`, trying each resolved address in turn, and
+/// applies `timeout` to connecting, reading and writing.
+#[cfg_attr(not(any(feature = "clamav", feature = "spam")), allow(dead_code))]
+pub(crate) fn connect_tcp(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address did not resolve")))
+}
+
+fn mask(address: IpAddr, prefix: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(address) => {
+            let bits = u32::from(address)
+                & u32::MAX
+                    .checked_shl(32 - prefix.min(32) as u32)
+                    .unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(bits))
+        }
+        IpAddr::V6(address) => {
+            let bits = u128::from(address)
+                & u128::MAX
+                    .checked_shl(128 - prefix.min(128) as u32)
+                    .unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(bits))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_contains() {
+        let network: IpNetwork = "192.168.1.77/24".parse().unwrap();
+        assert_eq!(network.to_string(), "192.168.1.0/24");
+        assert!(network.contains(&"192.168.1.1".parse().unwrap()));
+        assert!(network.contains(&"::ffff:192.168.1.200".parse().unwrap()));
+        assert!(!network.contains(&"192.168.2.1".parse().unwrap()));
+        assert!(!network.contains(&"2001:db8::1".parse().unwrap()));
+
+        let host: IpNetwork = "10.0.0.1".parse().unwrap();
+        assert_eq!(host.prefix(), 32);
+        assert!(host.contains(&"10.0.0.1".parse().unwrap()));
+        assert!(!host.contains(&"10.0.0.2".parse().unwrap()));
+
+        let any: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(&"8.8.8.8".parse().unwrap()));
+
+        let v6: IpNetwork = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(&"2001:db8:1::1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("not-an-ip".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn test_containing() {
+        let network = IpNetwork::containing("203.0.113.45".parse().unwrap(), 24, 64);
+        assert_eq!(network.to_string(), "203.0.113.0/24");
+        let network = IpNetwork::containing("2001:db8:1:2:3::1".parse().unwrap(), 24, 64);
+        assert_eq!(network.to_string(), "2001:db8:1:2::/64");
+    }
+}
@@ -0,0 +1,270 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Hook protocol versions.
+//!
+//! Each request names its protocol version in `context.protocol.version`.
+//! The types of each version live in their own module, [`v1`] for now, and
+//! [`VersionedRequest`] parses a request into the types of the version the
+//! server speaks. Hooks work with the latest types: requests are upgraded
+//! with [`VersionedRequest::into_latest`] and answers are converted back
+//! with [`VersionedRequest::respond`], so one hook serves old and new
+//! servers during a rolling upgrade.
+//!
+//! ```
+//! use stalwart_mta_hook_types::protocol;
+//! use stalwart_mta_hook_types::Response;
+//!
+//! let json = r#"{"context":{"stage":"connect","client":{"ip":"192.0.2.1",
+//!     "port":49152,"activeConnections":1},"server":{"port":25},
+//!     "protocol":{"version":1}}}"#;
+//! let answer = protocol::dispatch(json.as_bytes(), |request| {
+//!     assert_eq!(request.context.client.ip, "192.0.2.1");
+//!     Response::accept()
+//! })
+//! .unwrap();
+//! let answer: Response = serde_json::from_slice(&answer).unwrap();
+//! assert_eq!(answer.action, stalwart_mta_hook_types::Action::Accept);
+//! ```
+
+use crate::request::Request;
+use crate::response::Response;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+pub use crate::v1;
+
+/// The version of the types in the crate root.
+pub const LATEST_VERSION: u32 = v1::VERSION;
+
+/// All versions this crate parses, oldest first.
+pub const SUPPORTED_VERSIONS: &[u32] = &[v1::VERSION];
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The body is not a valid request of its version.
+    Json(serde_json::Error),
+    /// The request names a version this crate does not know.
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Json(err) => write!(f, "invalid hook request: {}", err),
+            ProtocolError::UnsupportedVersion(version) => {
+                write!(f, "unsupported hook protocol version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtocolError::Json(err) => Some(err),
+            ProtocolError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProtocolError {
+    fn from(err: serde_json::Error) -> Self {
+        ProtocolError::Json(err)
+    }
+}
+
+/// The part of a request that every version shares.
+#[derive(Deserialize)]
+struct Probe {
+    context: ProbeContext,
+}
+
+#[derive(Deserialize)]
+struct ProbeContext {
+    protocol: Option<ProbeProtocol>,
+}
+
+#[derive(Deserialize)]
+struct ProbeProtocol {
+    version: u32,
+}
+
+/// Reads the protocol version of a JSON request without parsing the rest.
+///
+/// Requests without a `protocol` object are taken as version 1, the only
+/// version that existed before the field was used.
+pub fn version_of(json: &[u8]) -> Result<u32, ProtocolError> {
+    let probe: Probe = serde_json::from_slice(json)?;
+    Ok(probe
+        .context
+        .protocol
+        .map_or(v1::VERSION, |protocol| protocol.version))
+}
+
+/// A request in the types of the version it was sent in.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum VersionedRequest {
+    V1(v1::Request),
+}
+
+impl VersionedRequest {
+    /// Parses a JSON request into the types of its version.
+    pub fn from_slice(json: &[u8]) -> Result<Self, ProtocolError> {
+        match version_of(json)? {
+            v1::VERSION => Ok(VersionedRequest::V1(serde_json::from_slice(json)?)),
+            version => Err(ProtocolError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            VersionedRequest::V1(_) => v1::VERSION,
+        }
+    }
+
+    /// Converts the request to the latest version.
+    pub fn into_latest(self) -> Request {
+        match self {
+            VersionedRequest::V1(request) => request,
+        }
+    }
+
+    /// Converts an answer in the latest version to the version of this
+    /// request.
+    pub fn respond(&self, response: Response) -> VersionedResponse {
+        match self {
+            VersionedRequest::V1(_) => VersionedResponse::V1(response),
+        }
+    }
+}
+
+impl std::str::FromStr for VersionedRequest {
+    type Err = ProtocolError;
+
+    fn from_str(json: &str) -> Result<Self, Self::Err> {
+        Self::from_slice(json.as_bytes())
+    }
+}
+
+impl From<v1::Request> for VersionedRequest {
+    fn from(request: v1::Request) -> Self {
+        VersionedRequest::V1(request)
+    }
+}
+
+/// A response in the types of the version it is sent in.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum VersionedResponse {
+    V1(v1::Response),
+}
+
+impl VersionedResponse {
+    /// Converts an answer in the latest version to the given version.
+    pub fn from_latest(version: u32, response: Response) -> Result<Self, ProtocolError> {
+        match version {
+            v1::VERSION => Ok(VersionedResponse::V1(response)),
+            version => Err(ProtocolError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            VersionedResponse::V1(_) => v1::VERSION,
+        }
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, ProtocolError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+impl Serialize for VersionedResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            VersionedResponse::V1(response) => response.serialize(serializer),
+        }
+    }
+}
+
+impl From<v1::Response> for VersionedResponse {
+    fn from(response: v1::Response) -> Self {
+        VersionedResponse::V1(response)
+    }
+}
+
+/// Parses a JSON request of any supported version, passes it to `hook` in
+/// the latest version and returns the answer as JSON in the version of the
+/// request.
+pub fn dispatch(
+    json: &[u8],
+    hook: impl FnOnce(Request) -> Response,
+) -> Result<Vec<u8>, ProtocolError> {
+    let request = VersionedRequest::from_slice(json)?;
+    let version = request.version();
+    VersionedResponse::from_latest(version, hook(request.into_latest()))?.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Stage;
+    use crate::response::Action;
+
+    fn request_json(version: Option<u32>) -> String {
+        let protocol = version
+            .map(|version| format!(r#","protocol":{{"version":{}}}"#, version))
+            .unwrap_or_default();
+        format!(
+            r#"{{"context":{{"stage":"RCPT","client":{{"ip":"192.0.2.1","port":49152,
+            "activeConnections":1}},"server":{{"port":25}}{}}},
+            "envelope":{{"from":{{"address":"john@example.com"}},
+            "to":[{{"address":"bill@example.org"}}]}}}}"#,
+            protocol
+        )
+    }
+
+    #[test]
+    fn test_version_dispatch() {
+        let request = VersionedRequest::from_slice(request_json(Some(1)).as_bytes()).unwrap();
+        assert_eq!(request.version(), 1);
+        let latest = request.clone().into_latest();
+        assert_eq!(latest.context.stage, Stage::Rcpt);
+        assert_eq!(latest.envelope.unwrap().to[0].address, "bill@example.org");
+
+        let answer = request.respond(Response::discard());
+        assert_eq!(answer.version(), 1);
+        let json: serde_json::Value = serde_json::from_slice(&answer.to_vec().unwrap()).unwrap();
+        assert_eq!(json["action"], "discard");
+
+        assert!(matches!(
+            VersionedRequest::from_slice(request_json(Some(2)).as_bytes()),
+            Err(ProtocolError::UnsupportedVersion(2))
+        ));
+        let request: VersionedRequest = request_json(None).parse().unwrap();
+        assert_eq!(request.into_latest().context.protocol.version, 1);
+        assert!(matches!(
+            version_of(b"{\"context\":{}"),
+            Err(ProtocolError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let answer = dispatch(request_json(Some(1)).as_bytes(), |request| {
+            assert_eq!(request.context.protocol.version, 1);
+            Response::accept()
+        })
+        .unwrap();
+        let answer: Response = serde_json::from_slice(&answer).unwrap();
+        assert_eq!(answer.action, Action::Accept);
+
+        let err = dispatch(request_json(Some(7)).as_bytes(), |_| Response::accept()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported hook protocol version 7");
+    }
+}
@@ -0,0 +1,517 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Hook-side quarantine, as Stalwart does not implement
+//! `Action::Quarantine` yet.
+//!
+//! Quarantined requests are stored with their metadata as JSON next to the
+//! raw message, either as `<id>.json` and `<id>.eml` in a directory or as a
+//! Maildir whose metadata lives in its `meta` folder. Stalwart is answered
+//! with `Action::Discard` unless another response is configured. Items can
+//! be listed, inspected, released to a [`ReleaseSink`] and purged.
+//!
+//! ```no_run
+//! use stalwart_mta_hook_types::quarantine::{CommandSink, Quarantine};
+//! use stalwart_mta_hook_types::{Request, Stage};
+//! use std::time::Duration;
+//!
+//! let request = Request::builder()
+//!     .stage(Stage::Data)
+//!     .queue_id("1234")
+//!     .from("john@example.com")
+//!     .to("bill@example.org")
+//!     .raw_message("Subject: Invoice\r\n\r\nPlease find it attached.\r\n")
+//!     .build();
+//! let quarantine = Quarantine::maildir("/var/lib/hook/quarantine")?;
+//! let response = quarantine.quarantine(&request, "Virus found")?;
+//!
+//! for entry in quarantine.list()? {
+//!     println!("{} from {:?}: {}", entry.id, entry.from, entry.reason);
+//! }
+//! quarantine.release("1700000000.P42Q0", &CommandSink::sendmail())?;
+//! quarantine.purge(Duration::from_secs(30 * 24 * 3600))?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::request::{Request, Stage};
+use crate::response::Response;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Metadata of a quarantined message, times in seconds since the Unix
+/// epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: String,
+    pub created: u64,
+    pub reason: String,
+    pub stage: Stage,
+    pub client_ip: String,
+    pub queue_id: Option<String>,
+    pub from: Option<String>,
+    pub recipients: Vec<String>,
+    pub subject: Option<String>,
+    pub size: usize,
+}
+
+/// A quarantined request with its metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedMessage {
+    pub entry: QuarantineEntry,
+    pub request: Request,
+}
+
+/// Delivers released messages.
+pub trait ReleaseSink {
+    fn release(&self, entry: &QuarantineEntry, raw: &[u8]) -> io::Result<()>;
+}
+
+impl<F: Fn(&QuarantineEntry, &[u8]) -> io::Result<()>> ReleaseSink for F {
+    fn release(&self, entry: &QuarantineEntry, raw: &[u8]) -> io::Result<()> {
+        self(entry, raw)
+    }
+}
+
+/// Re-injects released messages by piping them to a command.
+///
+/// The argument `{from}` is replaced by the envelope sender and
+/// `{recipients}` by one argument per recipient.
+#[derive(Debug, Clone)]
+pub struct CommandSink {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandSink {
+    pub fn new(program: String, args: Vec<String>) -> Self {
+        Self { program, args }
+    }
+
+    /// Runs `/usr/sbin/sendmail -i -f {from} -- {recipients}`.
+    pub fn sendmail() -> Self {
+        Self::new(
+            "/usr/sbin/sendmail".to_string(),
+            ["-i", "-f", "{from}", "--", "{recipients}"]
+                .map(String::from)
+                .to_vec(),
+        )
+    }
+}
+
+impl ReleaseSink for CommandSink {
+    fn release(&self, entry: &QuarantineEntry, raw: &[u8]) -> io::Result<()> {
+        let mut command = Command::new(&self.program);
+        for arg in &self.args {
+            match arg.as_str() {
+                "{recipients}" => {
+                    command.args(&entry.recipients);
+                }
+                _ => {
+                    command.arg(arg.replace("{from}", entry.from.as_deref().unwrap_or_default()));
+                }
+            }
+        }
+        let mut child = command.stdin(Stdio::piped()).spawn()?;
+        let written = child
+            .stdin
+            .take()
+            .map(|mut stdin| stdin.write_all(raw))
+            .unwrap_or(Ok(()));
+        let status = child.wait()?;
+        written?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} failed with {}",
+                self.program, status
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    Directory,
+    Maildir,
+}
+
+#[derive(Debug, Clone)]
+pub struct Quarantine {
+    root: PathBuf,
+    layout: Layout,
+    response: Response,
+}
+
+impl Quarantine {
+    /// Stores items as `<id>.json` and `<id>.eml` in `root`.
+    pub fn directory(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self {
+            root,
+            layout: Layout::Directory,
+            response: Response::discard(),
+        })
+    }
+
+    /// Stores messages in the Maildir `root` and their metadata in its
+    /// `meta` folder.
+    pub fn maildir(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        for folder in ["tmp", "new", "cur", "meta"] {
+            std::fs::create_dir_all(root.join(folder))?;
+        }
+        Ok(Self {
+            root,
+            layout: Layout::Maildir,
+            response: Response::discard(),
+        })
+    }
+
+    /// Sets the response for quarantined requests, `Response::discard()`
+    /// by default.
+    pub fn with_response(mut self, response: Response) -> Self {
+        self.response = response;
+        self
+    }
+
+    fn meta_dir(&self) -> PathBuf {
+        match self.layout {
+            Layout::Directory => self.root.clone(),
+            Layout::Maildir => self.root.join("meta"),
+        }
+    }
+
+    fn meta_path(&self, id: &str) -> io::Result<PathBuf> {
+        if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid quarantine id {:?}", id),
+            ));
+        }
+        Ok(self.meta_dir().join(format!("{}.json", id)))
+    }
+
+    /// The message file, which a mail client may have moved to `cur` and
+    /// renamed with flags in a Maildir.
+    fn message_path(&self, id: &str) -> io::Result<Option<PathBuf>> {
+        if self.layout == Layout::Directory {
+            let path = self.root.join(format!("{}.eml", id));
+            return Ok(path.exists().then_some(path));
+        }
+        for folder in ["new", "cur"] {
+            for file in std::fs::read_dir(self.root.join(folder))? {
+                let path = file?.path();
+                let name = path.file_name().and_then(|name| name.to_str());
+                if name.is_some_and(|name| name == id || name.starts_with(&format!("{}:", id))) {
+                    return Ok(Some(path));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Stores the request and returns the configured response.
+    pub fn quarantine(&self, request: &Request, reason: &str) -> io::Result<Response> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.quarantine_at(request, reason, now)
+            .map(|_| self.response.clone())
+    }
+
+    /// Stores the request as quarantined at `now`, in seconds since the
+    /// Unix epoch.
+    pub fn quarantine_at(
+        &self,
+        request: &Request,
+        reason: &str,
+        now: u64,
+    ) -> io::Result<QuarantineEntry> {
+        let id = format!(
+            "{}.P{}Q{}",
+            now,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let raw = request
+            .message
+            .as_ref()
+            .map(|message| message.to_rfc5322())
+            .unwrap_or_default();
+        let entry = QuarantineEntry {
+            id: id.clone(),
+            created: now,
+            reason: reason.to_string(),
+            stage: request.context.stage,
+            client_ip: request.context.client.ip.clone(),
+            queue_id: request.context.queue.as_ref().map(|queue| queue.id.clone()),
+            from: request
+                .envelope
+                .as_ref()
+                .map(|envelope| envelope.from.address.clone()),
+            recipients: request
+                .envelope
+                .iter()
+                .flat_map(|envelope| &envelope.to)
+                .map(|to| to.address.clone())
+                .collect(),
+            subject: request.message.as_ref().and_then(|message| {
+                message
+                    .headers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("Subject"))
+                    .map(|(_, value)| value.trim().to_string())
+            }),
+            size: raw.len(),
+        };
+
+        // The message first, so that listed metadata always has one.
+        match self.layout {
+            Layout::Directory => write_atomic(&self.root.join(format!("{}.eml", id)), &raw)?,
+            Layout::Maildir => {
+                let temporary = self.root.join("tmp").join(&id);
+                std::fs::write(&temporary, &raw)?;
+                std::fs::rename(&temporary, self.root.join("new").join(&id))?;
+            }
+        }
+        let item = QuarantinedMessage {
+            entry: entry.clone(),
+            request: request.clone(),
+        };
+        let data = serde_json::to_vec_pretty(&item)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        write_atomic(&self.meta_path(&id)?, &data)?;
+        Ok(entry)
+    }
+
+    /// All quarantined items, oldest first.
+    pub fn list(&self) -> io::Result<Vec<QuarantineEntry>> {
+        let mut entries = Vec::new();
+        for file in std::fs::read_dir(self.meta_dir())? {
+            let path = file?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                entries.push(read_item(&path)?.entry);
+            }
+        }
+        entries.sort_by(|a, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+        Ok(entries)
+    }
+
+    /// The stored request of a quarantined item.
+    pub fn inspect(&self, id: &str) -> io::Result<Option<QuarantinedMessage>> {
+        match read_item(&self.meta_path(id)?) {
+            Ok(item) => Ok(Some(item)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The raw message of a quarantined item.
+    pub fn raw(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+        self.meta_path(id)?;
+        self.message_path(id)?.map(std::fs::read).transpose()
+    }
+
+    /// Hands the message to `sink` and removes it from the quarantine.
+    /// Returns `false` if there is no such item.
+    pub fn release(&self, id: &str, sink: &dyn ReleaseSink) -> io::Result<bool> {
+        let (Some(item), Some(raw)) = (self.inspect(id)?, self.raw(id)?) else {
+            return Ok(false);
+        };
+        sink.release(&item.entry, &raw)?;
+        self.remove(id)
+    }
+
+    /// Deletes an item, returning whether it existed.
+    pub fn remove(&self, id: &str) -> io::Result<bool> {
+        let meta = self.meta_path(id)?;
+        let message = self.message_path(id)?;
+        if let Some(message) = &message {
+            std::fs::remove_file(message)?;
+        }
+        match std::fs::remove_file(meta) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(message.is_some()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Deletes items older than `max_age`, returning how many.
+    pub fn purge(&self, max_age: Duration) -> io::Result<usize> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.purge_at(max_age, now)
+    }
+
+    pub fn purge_at(&self, max_age: Duration, now: u64) -> io::Result<usize> {
+        let mut removed = 0;
+        for entry in self.list()? {
+            if entry.created.saturating_add(max_age.as_secs()) < now && self.remove(&entry.id)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+fn read_item(path: &Path) -> io::Result<QuarantinedMessage> {
+    let data = std::fs::read(path)?;
+    serde_json::from_slice(&data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, data)?;
+    std::fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Action;
+    use std::cell::RefCell;
+
+    fn request() -> Request {
+        Request::builder()
+            .stage(Stage::Data)
+            .client_ip("192.0.2.10")
+            .queue_id("1234")
+            .from("john@example.com")
+            .to("bill@foobar.com")
+            .to("jane@foobar.com")
+            .raw_message("From: john@example.com\r\nSubject: Cheap pills\r\n\r\nBuy now\r\n")
+            .build()
+    }
+
+    fn root(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("quarantine-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn test_directory() {
+        let root = root("directory");
+        let quarantine = Quarantine::directory(&root).unwrap();
+        let response = quarantine.quarantine(&request(), "Spam").unwrap();
+        assert_eq!(response.action, Action::Discard);
+        let old = quarantine
+            .quarantine_at(&request(), "Virus found", 1_700_000_000)
+            .unwrap();
+
+        let entries = quarantine.list().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], old);
+        assert_eq!(old.queue_id.as_deref(), Some("1234"));
+        assert_eq!(old.recipients, ["bill@foobar.com", "jane@foobar.com"]);
+        assert_eq!(old.subject.as_deref(), Some("Cheap pills"));
+
+        let item = quarantine.inspect(&old.id).unwrap().unwrap();
+        assert_eq!(
+            item.request.envelope.unwrap().from.address,
+            "john@example.com"
+        );
+        assert_eq!(
+            quarantine.raw(&old.id).unwrap().unwrap(),
+            b"From: john@example.com\r\nSubject: Cheap pills\r\n\r\nBuy now\r\n"
+        );
+        assert!(quarantine.inspect("../etc/passwd").is_err());
+        assert!(quarantine.inspect("missing").unwrap().is_none());
+
+        assert_eq!(
+            quarantine
+                .purge_at(Duration::from_secs(86400), 1_700_100_000)
+                .unwrap(),
+            1
+        );
+        assert_eq!(quarantine.list().unwrap().len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_maildir_release() {
+        let root = root("maildir");
+        let quarantine = Quarantine::maildir(&root)
+            .unwrap()
+            .with_response(Response::reject(550, "Message quarantined".to_string()));
+        assert_eq!(
+            quarantine.quarantine(&request(), "Spam").unwrap().action,
+            Action::Reject
+        );
+        let id = quarantine.list().unwrap()[0].id.clone();
+        assert!(root.join("new").join(&id).exists());
+
+        // A mail client marking it as seen moves it to cur.
+        std::fs::rename(
+            root.join("new").join(&id),
+            root.join("cur").join(format!("{}:2,S", id)),
+        )
+        .unwrap();
+
+        let released = RefCell::new(Vec::new());
+        let sink = |entry: &QuarantineEntry, raw: &[u8]| {
+            released
+                .borrow_mut()
+                .push((entry.recipients.clone(), raw.to_vec()));
+            Ok(())
+        };
+        assert!(quarantine.release(&id, &sink).unwrap());
+        assert!(!quarantine.release(&id, &sink).unwrap());
+        let released = released.into_inner();
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].0, ["bill@foobar.com", "jane@foobar.com"]);
+        assert!(released[0].1.ends_with(b"\r\n\r\nBuy now\r\n"));
+        assert!(quarantine.list().unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(root.join("cur")).unwrap().count(), 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_sink() {
+        let root = root("command");
+        let quarantine = Quarantine::directory(&root).unwrap();
+        let entry = quarantine.quarantine_at(&request(), "Spam", 1).unwrap();
+        let output = root.join("released.txt");
+        let sink = CommandSink::new(
+            "sh".to_string(),
+            [
+                "-c",
+                "{ echo \"$1 $2 $3\"; cat; } > \"$0\"",
+                output.to_str().unwrap(),
+                "{from}",
+                "{recipients}",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+        assert!(quarantine.release(&entry.id, &sink).unwrap());
+        let released = std::fs::read_to_string(&output).unwrap();
+        assert!(released.starts_with(
+            "john@example.com bill@foobar.com jane@foobar.com\nFrom: john@example.com"
+        ));
+
+        let entry = quarantine.quarantine_at(&request(), "Spam", 1).unwrap();
+        let failing = CommandSink::new("false".to_string(), Vec::new());
+        assert!(quarantine.release(&entry.id, &failing).is_err());
+        assert!(quarantine.inspect(&entry.id).unwrap().is_some());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
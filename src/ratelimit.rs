@@ -0,0 +1,588 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! In-process rate limiting keyed on request attributes.
+//!
+//! ```
+//! use std::time::Duration;
+//! use stalwart_mta_hook_types::ratelimit::{RateLimit, RateLimiter};
+//! use stalwart_mta_hook_types::Stage;
+//!
+//! let limiter = RateLimiter::new()
+//!     .with_limit(
+//!         RateLimit::sliding_window("per-login".to_string(), 100, Duration::from_secs(3600))
+//!             .with_key("sasl.login".parse().unwrap())
+//!             .with_stages(vec![Stage::Data]),
+//!     )
+//!     .with_limit(
+//!         RateLimit::token_bucket("rcpt-per-net".to_string(), 20, Duration::from_secs(3))
+//!             .with_key("client.ip/24".parse().unwrap())
+//!             .with_stages(vec![Stage::Rcpt]),
+//!     );
+//! ```
+
+use crate::net::IpNetwork;
+use crate::request::{Request, Stage};
+use crate::response::{Action, Response, SmtpResponse};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A request attribute that is part of a rate limit key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPart {
+    /// The client address, masked to the given prefix lengths.
+    ClientIp {
+        ipv4_prefix: u8,
+        ipv6_prefix: u8,
+    },
+    ClientHelo,
+    SaslLogin,
+    SenderAddress,
+    SenderDomain,
+}
+
+impl KeyPart {
+    fn value(&self, request: &Request) -> Option<String> {
+        let context = &request.context;
+        match self {
+            KeyPart::ClientIp {
+                ipv4_prefix,
+                ipv6_prefix,
+            } => context
+                .client
+                .ip_addr()
+                .map(|ip| IpNetwork::containing(ip, *ipv4_prefix, *ipv6_prefix).to_string()),
+            KeyPart::ClientHelo => context
+                .client
+                .helo
+                .as_ref()
+                .map(|helo| helo.to_ascii_lowercase()),
+            KeyPart::SaslLogin => context.sasl.as_ref().map(|sasl| sasl.login.clone()),
+            KeyPart::SenderAddress => request
+                .envelope
+                .as_ref()
+                .map(|envelope| envelope.from.address.to_lowercase()),
+            KeyPart::SenderDomain => request.envelope.as_ref().and_then(|envelope| {
+                envelope
+                    .from
+                    .address
+                    .rsplit_once('@')
+                    .map(|(_, domain)| domain.to_ascii_lowercase())
+            }),
+        }
+    }
+}
+
+impl FromStr for KeyPart {
+    type Err = String;
+
+    /// Parses `client.ip[/v4prefix[/v6prefix]]`, `client.helo`,
+    /// `sasl.login`, `envelope.from` or `envelope.from.domain`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(prefixes) = value.strip_prefix("client.ip") {
+            if !prefixes.is_empty() && !prefixes.starts_with('/') {
+                return Err(format!("unknown rate limit key {}", value));
+            }
+            let mut prefixes = prefixes.split('/').skip(1);
+            let mut prefix = |default: u8, max: u8| -> Result<u8, String> {
+                match prefixes.next() {
+                    Some(prefix) => prefix
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|prefix| *prefix <= max)
+                        .ok_or_else(|| format!("invalid prefix length in {}", value)),
+                    None => Ok(default),
+                }
+            };
+            let ipv4_prefix = prefix(32, 32)?;
+            let ipv6_prefix = prefix(if ipv4_prefix == 32 { 128 } else { 64 }, 128)?;
+            if prefixes.next().is_some() {
+                return Err(format!("too many prefix lengths in {}", value));
+            }
+            return Ok(KeyPart::ClientIp {
+                ipv4_prefix,
+                ipv6_prefix,
+            });
+        }
+        match value {
+            "client.helo" => Ok(KeyPart::ClientHelo),
+            "sasl.login" => Ok(KeyPart::SaslLogin),
+            "envelope.from" => Ok(KeyPart::SenderAddress),
+            "envelope.from.domain" => Ok(KeyPart::SenderDomain),
+            _ => Err(format!("unknown rate limit key {}", value)),
+        }
+    }
+}
+
+/// A combination of key parts, written as `sasl.login+client.ip/24`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateKey(pub Vec<KeyPart>);
+
+impl RateKey {
+    /// Builds the key for a request, or `None` if an attribute is missing.
+    fn value(&self, request: &Request) -> Option<String> {
+        let parts = self
+            .0
+            .iter()
+            .map(|part| part.value(request))
+            .collect::<Option<Vec<_>>>()?;
+        Some(parts.join("|"))
+    }
+}
+
+impl FromStr for RateKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .split('+')
+            .map(KeyPart::from_str)
+            .collect::<Result<_, _>>()
+            .map(RateKey)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// At most `limit` requests in any `window`, approximated from the
+    /// counts of the current and the previous fixed window.
+    SlidingWindow { limit: u64, window: Duration },
+    /// Bursts of up to `capacity` requests, refilled by one token per
+    /// `refill_interval`.
+    TokenBucket {
+        capacity: u64,
+        refill_interval: Duration,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    name: String,
+    key: RateKey,
+    stages: Vec<Stage>,
+    algorithm: Algorithm,
+    response: SmtpResponse,
+}
+
+impl RateLimit {
+    pub fn new(name: String, algorithm: Algorithm) -> Self {
+        Self {
+            name,
+            key: RateKey(vec![KeyPart::ClientIp {
+                ipv4_prefix: 32,
+                ipv6_prefix: 128,
+            }]),
+            stages: Vec::new(),
+            algorithm,
+            response: SmtpResponse {
+                status: Some(451),
+                enhanced_status: Some("4.7.1".to_string()),
+                message: Some("Rate limit exceeded, try again later".to_string()),
+                disconnect: false,
+            },
+        }
+    }
+
+    pub fn sliding_window(name: String, limit: u64, window: Duration) -> Self {
+        Self::new(name, Algorithm::SlidingWindow { limit, window })
+    }
+
+    pub fn token_bucket(name: String, capacity: u64, refill_interval: Duration) -> Self {
+        Self::new(
+            name,
+            Algorithm::TokenBucket {
+                capacity,
+                refill_interval,
+            },
+        )
+    }
+
+    /// Sets the key requests are counted under (the client IP by default).
+    pub fn with_key(mut self, key: RateKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Restricts the limit to the given stages (all stages by default).
+    /// Each stage is counted separately.
+    pub fn with_stages(mut self, stages: Vec<Stage>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// Sets the temporary failure returned once the limit is hit
+    /// (451 4.7.1 by default).
+    pub fn with_response(mut self, status: u16, enhanced_status: String, message: String) -> Self {
+        self.response = SmtpResponse {
+            status: Some(status),
+            enhanced_status: Some(enhanced_status),
+            message: Some(message),
+            disconnect: false,
+        };
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn applies_to(&self, stage: Stage) -> bool {
+        self.stages.is_empty() || self.stages.contains(&stage)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Counter {
+    Window {
+        start: Instant,
+        current: u64,
+        previous: u64,
+    },
+    Bucket {
+        tokens: f64,
+        updated: Instant,
+    },
+}
+
+impl Counter {
+    fn new(algorithm: &Algorithm, now: Instant) -> Self {
+        match algorithm {
+            Algorithm::SlidingWindow { .. } => Counter::Window {
+                start: now,
+                current: 0,
+                previous: 0,
+            },
+            Algorithm::TokenBucket { capacity, .. } => Counter::Bucket {
+                tokens: *capacity as f64,
+                updated: now,
+            },
+        }
+    }
+
+    /// Brings the counter up to `now` and reports whether one more request
+    /// fits.
+    fn refresh(&mut self, algorithm: &Algorithm, now: Instant) -> bool {
+        match (self, algorithm) {
+            (
+                Counter::Window {
+                    start,
+                    current,
+                    previous,
+                },
+                Algorithm::SlidingWindow { limit, window },
+            ) => {
+                let elapsed = now.saturating_duration_since(*start);
+                if elapsed >= window.saturating_mul(2) {
+                    *start = now;
+                    *previous = 0;
+                    *current = 0;
+                } else if elapsed >= *window {
+                    *start += *window;
+                    *previous = *current;
+                    *current = 0;
+                }
+                let elapsed = now.saturating_duration_since(*start).as_secs_f64();
+                let weight = 1.0 - (elapsed / window.as_secs_f64()).min(1.0);
+                (*previous as f64 * weight + *current as f64 + 1.0) <= *limit as f64
+            }
+            (
+                Counter::Bucket { tokens, updated },
+                Algorithm::TokenBucket {
+                    capacity,
+                    refill_interval,
+                },
+            ) => {
+                let elapsed = now.saturating_duration_since(*updated).as_secs_f64();
+                let refill = if refill_interval.is_zero() {
+                    f64::INFINITY
+                } else {
+                    elapsed / refill_interval.as_secs_f64()
+                };
+                *tokens = (*tokens + refill).min(*capacity as f64);
+                *updated = now;
+                *tokens >= 1.0
+            }
+            _ => false,
+        }
+    }
+
+    fn consume(&mut self) {
+        match self {
+            Counter::Window { current, .. } => *current += 1,
+            Counter::Bucket { tokens, .. } => *tokens -= 1.0,
+        }
+    }
+
+    fn is_idle(&self, algorithm: &Algorithm, now: Instant) -> bool {
+        match (self, algorithm) {
+            (Counter::Window { start, .. }, Algorithm::SlidingWindow { window, .. }) => {
+                now.saturating_duration_since(*start) >= window.saturating_mul(2)
+            }
+            (
+                Counter::Bucket { tokens, updated },
+                Algorithm::TokenBucket {
+                    capacity,
+                    refill_interval,
+                },
+            ) => {
+                let missing = *capacity as f64 - *tokens;
+                now.saturating_duration_since(*updated).as_secs_f64()
+                    >= missing * refill_interval.as_secs_f64()
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Applies a set of rate limits to incoming requests.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: Vec<RateLimit>,
+    counters: Mutex<HashMap<(usize, Stage, String), Counter>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(mut self, limit: RateLimit) -> Self {
+        self.limits.push(limit);
+        self
+    }
+
+    /// Counts the request against all applicable limits.
+    ///
+    /// Returns the tempfail response of the first exceeded limit, in which
+    /// case the request is not counted against any limit.
+    pub fn check(&self, request: &Request) -> Option<Response> {
+        self.check_at(request, Instant::now())
+    }
+
+    /// Same as [`RateLimiter::check`] at an explicit point in time.
+    pub fn check_at(&self, request: &Request, now: Instant) -> Option<Response> {
+        let stage = request.context.stage;
+        let keys: Vec<(usize, Stage, String)> = self
+            .limits
+            .iter()
+            .enumerate()
+            .filter(|(_, limit)| limit.applies_to(stage))
+            .filter_map(|(pos, limit)| limit.key.value(request).map(|key| (pos, stage, key)))
+            .collect();
+
+        let mut counters = self.counters.lock().unwrap_or_else(|err| err.into_inner());
+        for key in &keys {
+            let algorithm = &self.limits[key.0].algorithm;
+            let counter = counters
+                .entry(key.clone())
+                .or_insert_with(|| Counter::new(algorithm, now));
+            if !counter.refresh(algorithm, now) {
+                return Some(Response {
+                    action: Action::Reject,
+                    response: Some(self.limits[key.0].response.clone()),
+                    modifications: Vec::new(),
+                });
+            }
+        }
+        for key in &keys {
+            if let Some(counter) = counters.get_mut(key) {
+                counter.consume();
+            }
+        }
+        None
+    }
+
+    /// Drops counters that no longer hold any state.
+    pub fn purge(&self) {
+        self.purge_at(Instant::now())
+    }
+
+    pub fn purge_at(&self, now: Instant) {
+        let mut counters = self.counters.lock().unwrap_or_else(|err| err.into_inner());
+        counters.retain(|(pos, _, _), counter| !counter.is_idle(&self.limits[*pos].algorithm, now));
+    }
+
+    /// Number of keys currently tracked.
+    pub fn len(&self) -> usize {
+        self.counters
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(stage: Stage, ip: &str, login: Option<&str>) -> Request {
+        let mut builder = Request::builder()
+            .stage(stage)
+            .client_ip(ip)
+            .from("John@Example.com");
+        if let Some(login) = login {
+            builder = builder.authenticated(login);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            "client.ip/24".parse::<KeyPart>().unwrap(),
+            KeyPart::ClientIp {
+                ipv4_prefix: 24,
+                ipv6_prefix: 64
+            }
+        );
+        assert_eq!(
+            "client.ip".parse::<KeyPart>().unwrap(),
+            KeyPart::ClientIp {
+                ipv4_prefix: 32,
+                ipv6_prefix: 128
+            }
+        );
+        assert_eq!(
+            "sasl.login+envelope.from.domain"
+                .parse::<RateKey>()
+                .unwrap(),
+            RateKey(vec![KeyPart::SaslLogin, KeyPart::SenderDomain])
+        );
+        assert!("client.ip/33".parse::<KeyPart>().is_err());
+        assert!("client.port".parse::<KeyPart>().is_err());
+        for typo in ["client.ipv6", "client.ip4/24", "client.ip/24/64/8"] {
+            assert!(typo.parse::<KeyPart>().is_err(), "{}", typo);
+        }
+
+        let request = request(Stage::Mail, "192.0.2.77", Some("alice"));
+        let key: RateKey = "sasl.login+client.ip/24+envelope.from.domain"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            key.value(&request).unwrap(),
+            "alice|192.0.2.0/24|example.com"
+        );
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let limiter = RateLimiter::new().with_limit(
+            RateLimit::sliding_window("login".to_string(), 3, Duration::from_secs(60))
+                .with_key("sasl.login".parse().unwrap())
+                .with_stages(vec![Stage::Data])
+                .with_response(452, "4.7.0".to_string(), "Slow down".to_string()),
+        );
+        let start = Instant::now();
+        let data = request(Stage::Data, "192.0.2.1", Some("alice"));
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(&data, start).is_none());
+        }
+        let response = limiter.check_at(&data, start).expect("limit should be hit");
+        assert!(matches!(response.action, Action::Reject));
+        let smtp = response.response.unwrap();
+        assert_eq!(smtp.status, Some(452));
+        assert_eq!(smtp.enhanced_status.as_deref(), Some("4.7.0"));
+
+        // Other stages, logins and anonymous clients are not affected.
+        assert!(limiter
+            .check_at(&request(Stage::Rcpt, "192.0.2.1", Some("alice")), start)
+            .is_none());
+        assert!(limiter
+            .check_at(&request(Stage::Data, "192.0.2.1", Some("bob")), start)
+            .is_none());
+        assert!(limiter
+            .check_at(&request(Stage::Data, "192.0.2.1", None), start)
+            .is_none());
+
+        // Halfway through the next window half of the old count remains.
+        let later = start + Duration::from_secs(90);
+        assert!(limiter.check_at(&data, later).is_none());
+        assert!(limiter.check_at(&data, later).is_some());
+        assert!(limiter
+            .check_at(&data, start + Duration::from_secs(180))
+            .is_none());
+
+        // A window too long to double does not overflow.
+        let forever = RateLimiter::new().with_limit(RateLimit::sliding_window(
+            "forever".to_string(),
+            1,
+            Duration::MAX,
+        ));
+        assert!(forever.check_at(&data, start).is_none());
+        assert!(forever.check_at(&data, later).is_some());
+        forever.purge_at(later);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new().with_limit(
+            RateLimit::token_bucket("net".to_string(), 2, Duration::from_secs(10))
+                .with_key("client.ip/24".parse().unwrap()),
+        );
+        let start = Instant::now();
+
+        assert!(limiter
+            .check_at(&request(Stage::Rcpt, "192.0.2.1", None), start)
+            .is_none());
+        assert!(limiter
+            .check_at(&request(Stage::Rcpt, "192.0.2.2", None), start)
+            .is_none());
+        assert!(limiter
+            .check_at(&request(Stage::Rcpt, "192.0.2.3", None), start)
+            .is_some());
+        assert!(limiter
+            .check_at(&request(Stage::Rcpt, "198.51.100.1", None), start)
+            .is_none());
+
+        let later = start + Duration::from_secs(10);
+        assert!(limiter
+            .check_at(&request(Stage::Rcpt, "192.0.2.3", None), later)
+            .is_none());
+        assert!(limiter
+            .check_at(&request(Stage::Rcpt, "192.0.2.3", None), later)
+            .is_some());
+
+        assert_eq!(limiter.len(), 2);
+        limiter.purge_at(start + Duration::from_secs(35));
+        assert_eq!(limiter.len(), 0);
+    }
+
+    #[test]
+    fn test_rejected_requests_are_not_counted() {
+        let limiter = RateLimiter::new()
+            .with_limit(RateLimit::sliding_window(
+                "wide".to_string(),
+                10,
+                Duration::from_secs(60),
+            ))
+            .with_limit(RateLimit::sliding_window(
+                "narrow".to_string(),
+                1,
+                Duration::from_secs(60),
+            ));
+        let start = Instant::now();
+        let request = request(Stage::Connect, "192.0.2.1", None);
+
+        assert!(limiter.check_at(&request, start).is_none());
+        for _ in 0..20 {
+            assert!(limiter.check_at(&request, start).is_some());
+        }
+        let counters = limiter.counters.lock().unwrap();
+        let wide = counters
+            .iter()
+            .find(|((pos, _, _), _)| *pos == 0)
+            .map(|(_, counter)| counter.clone());
+        assert!(matches!(wide, Some(Counter::Window { current: 1, .. })));
+    }
+}
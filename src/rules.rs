@@ -0,0 +1,645 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Declarative rules evaluated over a [`Request`].
+//!
+//! Rules are loaded from TOML or YAML. Each rule has a `when` block of
+//! conditions that must all hold, an optional `unless` block, and a `then`
+//! block with the [`Response`] to return:
+//!
+//! ```toml
+//! mode = "first-match"
+//!
+//! [[rules]]
+//! name = "internal-relay"
+//! when = { stage = ["rcpt"], client_ip = ["10.0.0.0/8"] }
+//! then = { action = "accept" }
+//!
+//! [[rules]]
+//! name = "no-pills"
+//! when = { headers = [{ name = "Subject", pattern = "(?i)viagra" }] }
+//! then = { action = "reject", response = { status = 550, enhancedStatus = "5.7.1", message = "Rejected" } }
+//! ```
+//!
+//! With `mode = "score"` every matching rule adds its `score` and the
+//! modifications of its `then` block, and the response is taken from the
+//! highest entry in `thresholds` that the total reaches.
+
+use crate::net::IpNetwork;
+use crate::request::{Request, Stage};
+use crate::response::Response;
+use regex::Regex;
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum RuleError {
+    Io(std::io::Error),
+    Parse(String),
+    Invalid { rule: String, reason: String },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Io(err) => write!(f, "failed to read rules: {}", err),
+            RuleError::Parse(reason) => write!(f, "failed to parse rules: {}", reason),
+            RuleError::Invalid { rule, reason } => write!(f, "invalid rule {}: {}", rule, reason),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+impl From<std::io::Error> for RuleError {
+    fn from(err: std::io::Error) -> Self {
+        RuleError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvaluationMode {
+    #[default]
+    FirstMatch,
+    Score,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConditionsConfig {
+    stage: Vec<Stage>,
+    client_ip: Vec<String>,
+    sasl_login: Option<String>,
+    authenticated: Option<bool>,
+    tls: Option<bool>,
+    tls_version: Vec<String>,
+    helo: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    headers: Vec<HeaderConfig>,
+    body: Option<String>,
+    min_size: Option<usize>,
+    max_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeaderConfig {
+    name: String,
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    name: String,
+    #[serde(default)]
+    when: ConditionsConfig,
+    #[serde(default)]
+    unless: Option<ConditionsConfig>,
+    #[serde(default)]
+    score: f64,
+    #[serde(default)]
+    then: Response,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThresholdConfig {
+    score: f64,
+    then: Response,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSetConfig {
+    #[serde(default)]
+    mode: EvaluationMode,
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    #[serde(default)]
+    thresholds: Vec<ThresholdConfig>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Conditions {
+    stage: Vec<Stage>,
+    client_ip: Vec<IpNetwork>,
+    sasl_login: Option<Regex>,
+    authenticated: Option<bool>,
+    tls: Option<bool>,
+    tls_version: Vec<String>,
+    helo: Option<Regex>,
+    from: Option<Regex>,
+    to: Option<Regex>,
+    headers: Vec<(String, Regex)>,
+    body: Option<Regex>,
+    min_size: Option<usize>,
+    max_size: Option<usize>,
+}
+
+impl Conditions {
+    fn compile(rule: &str, config: ConditionsConfig) -> Result<Self, RuleError> {
+        let invalid = |reason: String| RuleError::Invalid {
+            rule: rule.to_string(),
+            reason,
+        };
+        let regex = |pattern: Option<String>| {
+            pattern
+                .map(|pattern| Regex::new(&pattern).map_err(|err| invalid(err.to_string())))
+                .transpose()
+        };
+
+        Ok(Conditions {
+            stage: config.stage,
+            client_ip: config
+                .client_ip
+                .iter()
+                .map(|network| network.parse().map_err(invalid))
+                .collect::<Result<_, _>>()?,
+            sasl_login: regex(config.sasl_login)?,
+            authenticated: config.authenticated,
+            tls: config.tls,
+            tls_version: config.tls_version,
+            helo: regex(config.helo)?,
+            from: regex(config.from)?,
+            to: regex(config.to)?,
+            headers: config
+                .headers
+                .into_iter()
+                .map(|header| {
+                    Regex::new(&header.pattern)
+                        .map(|pattern| (header.name, pattern))
+                        .map_err(|err| invalid(err.to_string()))
+                })
+                .collect::<Result<_, _>>()?,
+            body: regex(config.body)?,
+            min_size: config.min_size,
+            max_size: config.max_size,
+        })
+    }
+
+    /// Returns the reasons the conditions hold, or `None` if one fails.
+    fn matches(&self, request: &Request) -> Option<Vec<String>> {
+        let context = &request.context;
+        let envelope = request.envelope.as_ref();
+        let message = request.message.as_ref();
+        let mut reasons = Vec::new();
+
+        if !self.stage.is_empty() {
+            self.stage.contains(&context.stage).then_some(())?;
+            reasons.push(format!("stage is {}", context.stage.as_str()));
+        }
+
+        if !self.client_ip.is_empty() {
+            let ip = context.client.ip_addr()?;
+            let network = self
+                .client_ip
+                .iter()
+                .find(|network| network.contains(&ip))?;
+            reasons.push(format!("client ip {} in {}", ip, network));
+        }
+
+        if let Some(pattern) = &self.sasl_login {
+            let login = &context.sasl.as_ref()?.login;
+            pattern.is_match(login).then_some(())?;
+            reasons.push(format!("sasl login {} matches {}", login, pattern));
+        }
+
+        if let Some(authenticated) = self.authenticated {
+            (context.sasl.is_some() == authenticated).then_some(())?;
+            reasons.push(if authenticated {
+                "client is authenticated".to_string()
+            } else {
+                "client is not authenticated".to_string()
+            });
+        }
+
+        if let Some(tls) = self.tls {
+            (context.tls.is_some() == tls).then_some(())?;
+            reasons.push(if tls {
+                "connection uses tls".to_string()
+            } else {
+                "connection is not encrypted".to_string()
+            });
+        }
+
+        if !self.tls_version.is_empty() {
+            let version = context.tls.as_ref()?.version.trim();
+            self.tls_version
+                .iter()
+                .any(|expected| expected.trim().eq_ignore_ascii_case(version))
+                .then_some(())?;
+            reasons.push(format!("tls version is {}", version));
+        }
+
+        if let Some(pattern) = &self.helo {
+            let helo = context.client.helo.as_deref()?;
+            pattern.is_match(helo).then_some(())?;
+            reasons.push(format!("helo {} matches {}", helo, pattern));
+        }
+
+        if let Some(pattern) = &self.from {
+            let from = &envelope?.from.address;
+            pattern.is_match(from).then_some(())?;
+            reasons.push(format!("envelope from {} matches {}", from, pattern));
+        }
+
+        if let Some(pattern) = &self.to {
+            let to = envelope?
+                .to
+                .iter()
+                .find(|recipient| pattern.is_match(&recipient.address))?;
+            reasons.push(format!("envelope to {} matches {}", to.address, pattern));
+        }
+
+        for (name, pattern) in &self.headers {
+            message?
+                .headers
+                .iter()
+                .any(|(header, value)| {
+                    header.eq_ignore_ascii_case(name) && pattern.is_match(value.trim())
+                })
+                .then_some(())?;
+            reasons.push(format!("header {} matches {}", name, pattern));
+        }
+
+        if let Some(pattern) = &self.body {
+            pattern
+                .is_match(&message?.contents.to_str_lossy())
+                .then_some(())?;
+            reasons.push(format!("body matches {}", pattern));
+        }
+
+        if let Some(min_size) = self.min_size {
+            let size = message?.size;
+            (size >= min_size).then_some(())?;
+            reasons.push(format!("size {} is at least {}", size, min_size));
+        }
+
+        if let Some(max_size) = self.max_size {
+            let size = message?.size;
+            (size <= max_size).then_some(())?;
+            reasons.push(format!("size {} is at most {}", size, max_size));
+        }
+
+        Some(reasons)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    when: Conditions,
+    unless: Option<Conditions>,
+    score: f64,
+    then: Response,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleMatch {
+    pub rule: String,
+    pub score: f64,
+    pub reasons: Vec<String>,
+}
+
+/// The outcome of evaluating a [`RuleSet`].
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub response: Response,
+    pub matches: Vec<RuleMatch>,
+    pub score: f64,
+    /// The threshold reached in score mode.
+    pub threshold: Option<f64>,
+}
+
+impl Evaluation {
+    /// The rule that decided the response in first-match mode.
+    pub fn fired(&self) -> Option<&RuleMatch> {
+        self.matches.first()
+    }
+
+    /// Describes which rules fired and why, one line per rule.
+    pub fn explain(&self) -> String {
+        if self.matches.is_empty() {
+            return "no rule matched".to_string();
+        }
+        let mut lines: Vec<String> = self
+            .matches
+            .iter()
+            .map(|rule| {
+                let mut line = format!("rule {} matched", rule.rule);
+                if rule.score != 0.0 {
+                    line.push_str(&format!(" (score {})", rule.score));
+                }
+                if !rule.reasons.is_empty() {
+                    line.push_str(": ");
+                    line.push_str(&rule.reasons.join(", "));
+                }
+                line
+            })
+            .collect();
+        if let Some(threshold) = self.threshold {
+            lines.push(format!(
+                "total score {} reached threshold {}",
+                self.score, threshold
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    mode: EvaluationMode,
+    rules: Vec<Rule>,
+    thresholds: Vec<(f64, Response)>,
+}
+
+impl RuleSet {
+    pub fn from_toml(source: &str) -> Result<Self, RuleError> {
+        toml::from_str(source)
+            .map_err(|err| RuleError::Parse(err.to_string()))
+            .and_then(Self::compile)
+    }
+
+    pub fn from_yaml(source: &str) -> Result<Self, RuleError> {
+        serde_yaml::from_str(source)
+            .map_err(|err| RuleError::Parse(err.to_string()))
+            .and_then(Self::compile)
+    }
+
+    /// Loads rules from a `.toml`, `.yaml` or `.yml` file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, RuleError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&source),
+            _ => Self::from_toml(&source),
+        }
+    }
+
+    fn compile(config: RuleSetConfig) -> Result<Self, RuleError> {
+        let rules = config
+            .rules
+            .into_iter()
+            .map(|rule| {
+                Ok(Rule {
+                    when: Conditions::compile(&rule.name, rule.when)?,
+                    unless: rule
+                        .unless
+                        .map(|unless| Conditions::compile(&rule.name, unless))
+                        .transpose()?,
+                    score: rule.score,
+                    then: rule.then,
+                    name: rule.name,
+                })
+            })
+            .collect::<Result<_, RuleError>>()?;
+
+        let mut thresholds: Vec<(f64, Response)> = config
+            .thresholds
+            .into_iter()
+            .map(|threshold| (threshold.score, threshold.then))
+            .collect();
+        thresholds.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        Ok(RuleSet {
+            mode: config.mode,
+            rules,
+            thresholds,
+        })
+    }
+
+    pub fn mode(&self) -> EvaluationMode {
+        self.mode
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn evaluate(&self, request: &Request) -> Evaluation {
+        let mut evaluation = Evaluation {
+            response: Response::accept(),
+            matches: Vec::new(),
+            score: 0.0,
+            threshold: None,
+        };
+        let mut modifications = Vec::new();
+
+        for rule in &self.rules {
+            let Some(reasons) = rule.when.matches(request) else {
+                continue;
+            };
+            if rule
+                .unless
+                .as_ref()
+                .is_some_and(|unless| unless.matches(request).is_some())
+            {
+                continue;
+            }
+
+            evaluation.matches.push(RuleMatch {
+                rule: rule.name.clone(),
+                score: rule.score,
+                reasons,
+            });
+
+            match self.mode {
+                EvaluationMode::FirstMatch => {
+                    evaluation.score = rule.score;
+                    evaluation.response = rule.then.clone();
+                    return evaluation;
+                }
+                EvaluationMode::Score => {
+                    evaluation.score += rule.score;
+                    modifications.extend(rule.then.modifications.iter().cloned());
+                }
+            }
+        }
+
+        if let Some((threshold, response)) = self
+            .thresholds
+            .iter()
+            .find(|(threshold, _)| evaluation.score >= *threshold)
+        {
+            evaluation.threshold = Some(*threshold);
+            evaluation.response = response.clone();
+        }
+        evaluation.response.modifications.extend(modifications);
+        evaluation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modifications::Modification;
+    use crate::request::{Message, Tls};
+    use crate::response::Action;
+
+    fn request(stage: Stage, ip: &str, subject: &str) -> Request {
+        Request::builder()
+            .stage(stage)
+            .client_ip(ip)
+            .helo("mail.example.com")
+            .authenticated("alice")
+            .tls(Tls {
+                version: "1.3".to_string(),
+                cipher: "TLS_AES_256_GCM_SHA384".to_string(),
+                bits: None,
+                issuer: None,
+                subject: None,
+            })
+            .from("john@example.com")
+            .to("bill@foobar.com")
+            .message(Message {
+                headers: vec![("Subject".to_string(), subject.to_string())],
+                server_headers: vec![],
+                contents: "Buy now!\r\n".into(),
+                size: 2048,
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_first_match_toml() {
+        let rules = RuleSet::from_toml(
+            r#"
+[[rules]]
+name = "internal"
+when = { client_ip = ["10.0.0.0/8"], tls_version = ["1.2", "1.3"] }
+then = { action = "accept" }
+
+[[rules]]
+name = "pills"
+when = { stage = ["data"], headers = [{ name = "subject", pattern = "(?i)viagra" }] }
+unless = { sasl_login = "^postmaster$" }
+
+[rules.then]
+action = "reject"
+response = { status = 550, enhancedStatus = "5.7.1", message = "Rejected by policy" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules.mode(), EvaluationMode::FirstMatch);
+
+        let evaluation = rules.evaluate(&request(Stage::Data, "10.1.2.3", "VIAGRA"));
+        assert_eq!(evaluation.fired().unwrap().rule, "internal");
+        assert!(matches!(evaluation.response.action, Action::Accept));
+        assert!(evaluation
+            .explain()
+            .contains("client ip 10.1.2.3 in 10.0.0.0/8"));
+
+        let evaluation = rules.evaluate(&request(Stage::Data, "192.0.2.1", "cheap Viagra"));
+        assert_eq!(evaluation.fired().unwrap().rule, "pills");
+        assert!(matches!(evaluation.response.action, Action::Reject));
+        let smtp = evaluation.response.response.unwrap();
+        assert_eq!(smtp.status, Some(550));
+        assert_eq!(smtp.enhanced_status.as_deref(), Some("5.7.1"));
+
+        let evaluation = rules.evaluate(&request(Stage::Rcpt, "192.0.2.1", "cheap Viagra"));
+        assert!(evaluation.fired().is_none());
+        assert_eq!(evaluation.explain(), "no rule matched");
+        assert!(matches!(evaluation.response.action, Action::Accept));
+    }
+
+    #[test]
+    fn test_score_yaml() {
+        let rules = RuleSet::from_yaml(
+            r#"
+mode: score
+rules:
+  - name: body
+    score: 3
+    when:
+      body: "(?i)buy now"
+    then:
+      action: accept
+      modifications:
+        - type: addHeader
+          name: X-Rule-Body
+          value: "yes"
+  - name: large
+    score: 2.5
+    when:
+      min_size: 1024
+      authenticated: true
+  - name: never
+    score: 100
+    when:
+      from: "@spammer\\.example$"
+thresholds:
+  - score: 5
+    then:
+      action: reject
+      response:
+        status: 550
+        message: Too spammy
+  - score: 3
+    then:
+      action: accept
+      modifications:
+        - type: addHeader
+          name: X-Spam-Flag
+          value: "YES"
+"#,
+        )
+        .unwrap();
+
+        let evaluation = rules.evaluate(&request(Stage::Data, "192.0.2.1", "Hello"));
+        assert_eq!(evaluation.matches.len(), 2);
+        assert_eq!(evaluation.score, 5.5);
+        assert_eq!(evaluation.threshold, Some(5.0));
+        assert!(matches!(evaluation.response.action, Action::Reject));
+        assert_eq!(evaluation.response.modifications.len(), 1);
+        assert!(evaluation
+            .explain()
+            .ends_with("total score 5.5 reached threshold 5"));
+
+        let mut small = request(Stage::Data, "192.0.2.1", "Hello");
+        small.message.as_mut().unwrap().size = 10;
+        let evaluation = rules.evaluate(&small);
+        assert_eq!(evaluation.score, 3.0);
+        assert!(matches!(evaluation.response.action, Action::Accept));
+        let names: Vec<&str> = evaluation
+            .response
+            .modifications
+            .iter()
+            .map(|modification| match modification {
+                Modification::AddHeader { name, .. } => name.as_str(),
+                _ => panic!("Expected AddHeader modification"),
+            })
+            .collect();
+        assert_eq!(names, ["X-Spam-Flag", "X-Rule-Body"]);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(matches!(
+            RuleSet::from_toml(
+                "[[rules]]\nname = \"bad\"\nwhen = { client_ip = [\"10.0.0.0/40\"] }"
+            ),
+            Err(RuleError::Invalid { .. })
+        ));
+        assert!(matches!(
+            RuleSet::from_toml("[[rules]]\nname = \"bad\"\nwhen = { body = \"(\" }"),
+            Err(RuleError::Invalid { .. })
+        ));
+        assert!(matches!(
+            RuleSet::from_toml("[[rules]]\nname = \"bad\"\nwhen = { unknown = 1 }"),
+            Err(RuleError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! JSON Schema and OpenAPI documents for the hook protocol.
+//!
+//! The schemas describe what the types accept when deserializing: stages
+//! in any letter case, envelope ESMTP parameters given as strings, numbers
+//! or booleans, and modification parameters of any type. The `hook-schema` command writes them to standard output.
+//!
+//! ```
+//! use stalwart_mta_hook_types::schema;
+//!
+//! let document = schema::openapi("/hook");
+//! assert_eq!(document["paths"]["/hook"]["post"]["operationId"], "mtaHook");
+//! ```
+
+use crate::contents::Contents;
+use crate::request::{Request, Stage};
+use crate::response::Response;
+use schemars::generate::SchemaSettings;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+
+/// Schema of a request, as JSON Schema 2020-12.
+pub fn request_schema() -> Schema {
+    schemars::schema_for!(Request)
+}
+
+/// Schema of a response, as JSON Schema 2020-12.
+pub fn response_schema() -> Schema {
+    schemars::schema_for!(Response)
+}
+
+/// An OpenAPI 3.1 document for a hook endpoint at `path`, optionally
+/// protected by HTTP basic authentication.
+pub fn openapi(path: &str) -> Value {
+    let mut generator = SchemaSettings::draft2020_12()
+        .with(|settings| settings.definitions_path = "/components/schemas".into())
+        .into_generator();
+    let request = generator.subschema_for::<Request>();
+    let response = generator.subschema_for::<Response>();
+    let schemas = generator.take_definitions(true);
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Stalwart MTA hook",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Called by Stalwart at each configured SMTP stage; the \
+                response decides how the transaction continues.",
+        },
+        "paths": {
+            path: {
+                "post": {
+                    "operationId": "mtaHook",
+                    "summary": "Decide on an SMTP transaction",
+                    "requestBody": {
+                        "required": true,
+                        "content": {"application/json": {"schema": request}},
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The verdict for the transaction",
+                            "content": {"application/json": {"schema": response}},
+                        },
+                    },
+                    "security": [{}, {"basicAuth": []}],
+                },
+            },
+        },
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {"basicAuth": {"type": "http", "scheme": "basic"}},
+        },
+    })
+}
+
+/// An ESMTP parameter value, which Stalwart may send as a number.
+pub(crate) struct ParameterValue;
+
+impl JsonSchema for ParameterValue {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "ParameterValue".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({"type": ["string", "number", "boolean"]})
+    }
+}
+
+/// A modification parameter value, which may be any JSON value: arrays and
+/// objects are kept as their JSON text.
+pub(crate) struct ModificationParameterValue;
+
+impl JsonSchema for ModificationParameterValue {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "ModificationParameterValue".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({})
+    }
+}
+
+impl JsonSchema for Contents {
+    fn schema_name() -> Cow<'static, str> {
+        "Contents".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "The message body.",
+            "type": "string",
+        })
+    }
+}
+
+impl JsonSchema for Stage {
+    fn schema_name() -> Cow<'static, str> {
+        "Stage".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let stages = [
+            Stage::Connect,
+            Stage::Ehlo,
+            Stage::Auth,
+            Stage::Mail,
+            Stage::Rcpt,
+            Stage::Data,
+        ];
+        // JSON Schema patterns have no case-insensitive flag.
+        let alternatives: Vec<String> = stages
+            .iter()
+            .map(|stage| {
+                stage
+                    .as_str()
+                    .chars()
+                    .map(|ch| format!("[{}{}]", ch, ch.to_ascii_uppercase()))
+                    .collect()
+            })
+            .collect();
+        json_schema!({
+            "type": "string",
+            "description": "The SMTP stage, in any letter case.",
+            "pattern": format!("^(?:{})$", alternatives.join("|")),
+            "examples": stages.iter().map(Stage::as_str).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(target)) = map.get("$ref") {
+                    found.push(target);
+                }
+                map.values().for_each(|value| refs(value, found));
+            }
+            Value::Array(values) => values.iter().for_each(|value| refs(value, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_request_schema() {
+        let schema = request_schema().to_value();
+        let defs = &schema["$defs"];
+        assert!(defs["Client"]["properties"]["activeConnections"].is_object());
+        assert!(defs["Message"]["properties"]["serverHeaders"].is_object());
+        assert!(defs["Tls"]["properties"]["cipherBits"].is_object());
+        assert_eq!(
+            defs["Stage"]["pattern"].as_str().unwrap(),
+            "^(?:[cC][oO][nN][nN][eE][cC][tT]|[eE][hH][lL][oO]|[aA][uU][tT][hH]|\
+             [mM][aA][iI][lL]|[rR][cC][pP][tT]|[dD][aA][tT][aA])$"
+        );
+        let parameters = &defs["Address"]["properties"]["parameters"];
+        assert_eq!(
+            parameters["additionalProperties"]["type"],
+            json!(["string", "number", "boolean"])
+        );
+        assert_eq!(parameters["type"], json!(["object", "null"]));
+        assert_eq!(defs["Contents"]["type"], "string");
+        assert!(defs["Contents"].get("anyOf").is_none());
+    }
+
+    #[test]
+    fn test_response_schema() {
+        let schema = response_schema().to_value();
+        assert_eq!(schema["required"], json!(["action"]));
+        let actions = &schema["$defs"]["Action"];
+        assert_eq!(
+            actions["enum"],
+            json!(["accept", "discard", "reject", "quarantine"])
+        );
+        let modification = serde_json::to_string(&schema["$defs"]["Modification"]).unwrap();
+        for tag in ["changeFrom", "replaceContents", "deleteHeader"] {
+            assert!(modification.contains(tag), "{} missing", tag);
+        }
+        assert!(schema["$defs"]["SmtpResponse"]["properties"]["enhancedStatus"].is_object());
+        let change_from = schema["$defs"]["Modification"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|variant| variant["properties"]["type"]["const"] == "changeFrom")
+            .unwrap();
+        // Modification parameters accept any value, also arrays and objects.
+        let parameters = &change_from["properties"]["parameters"];
+        assert_eq!(parameters["additionalProperties"], json!({}));
+        assert_eq!(parameters["type"], json!(["object", "null"]));
+    }
+
+    #[test]
+    fn test_openapi_references() {
+        let document = openapi("/hook");
+        let mut found = Vec::new();
+        refs(&document, &mut found);
+        assert!(found.contains(&"#/components/schemas/Request"));
+        for target in found {
+            let name = target.strip_prefix("#/components/schemas/").unwrap();
+            assert!(
+                document["components"]["schemas"][name].is_object(),
+                "{} unresolved",
+                target
+            );
+        }
+    }
+}
@@ -0,0 +1,433 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Hook logic written as [Rhai](https://rhai.rs) scripts.
+//!
+//! The request is available to scripts as the `request` variable, with the
+//! shortcuts `context`, `envelope` and `message`. Field names follow the
+//! JSON protocol (e.g. `context.client.activeConnections`); missing
+//! envelopes and messages are `()`.
+//!
+//! Scripts build their result with the same constructors as the Rust API:
+//!
+//! ```rhai
+//! if context.client.ip.starts_with("10.") {
+//!     return accept();
+//! }
+//! let response = reject(550, "5.7.1", "Rejected by policy");
+//! response.push(add_header("X-Policy", "rejected"));
+//! response
+//! ```
+//!
+//! A script may also return a single modification, an array of
+//! modifications (both meaning accept) or nothing at all (accept).
+
+use crate::modifications::Modification;
+use crate::request::Request;
+use crate::response::{Response, SmtpResponse};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::cell::Cell;
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(std::io::Error),
+    Compile(String),
+    Runtime(String),
+    Timeout,
+    LimitExceeded(String),
+    InvalidResult(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(err) => write!(f, "failed to read script: {}", err),
+            ScriptError::Compile(reason) => write!(f, "failed to compile script: {}", reason),
+            ScriptError::Runtime(reason) => write!(f, "script failed: {}", reason),
+            ScriptError::Timeout => f.write_str("script exceeded its time limit"),
+            ScriptError::LimitExceeded(reason) => write!(f, "script exceeded a limit: {}", reason),
+            ScriptError::InvalidResult(kind) => {
+                write!(f, "script returned {} instead of a Response", kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<std::io::Error> for ScriptError {
+    fn from(err: std::io::Error) -> Self {
+        ScriptError::Io(err)
+    }
+}
+
+/// Resource limits applied to every script run.
+///
+/// Memory use is bounded through the size limits on strings, arrays and
+/// maps, together with the operation count.
+#[derive(Debug, Clone)]
+pub struct ScriptLimits {
+    pub max_duration: Duration,
+    pub max_operations: u64,
+    pub max_call_levels: usize,
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_duration: Duration::from_millis(100),
+            max_operations: 1_000_000,
+            max_call_levels: 32,
+            max_string_size: 16 * 1024 * 1024,
+            max_array_size: 10_000,
+            max_map_size: 10_000,
+        }
+    }
+}
+
+/// A compiled hook script.
+pub struct HookScript {
+    engine: Engine,
+    ast: AST,
+    max_duration: Duration,
+}
+
+impl fmt::Debug for HookScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookScript")
+            .field("max_duration", &self.max_duration)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HookScript {
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        Self::compile_with_limits(source, ScriptLimits::default())
+    }
+
+    pub fn compile_with_limits(source: &str, limits: ScriptLimits) -> Result<Self, ScriptError> {
+        let engine = engine(&limits);
+        let ast = engine
+            .compile(source)
+            .map_err(|err| ScriptError::Compile(err.to_string()))?;
+        Ok(Self {
+            engine,
+            ast,
+            max_duration: limits.max_duration,
+        })
+    }
+
+    /// Loads and compiles a script file, so that filter logic can be
+    /// changed by replacing the file and reloading it.
+    pub fn from_path(path: impl AsRef<Path>, limits: ScriptLimits) -> Result<Self, ScriptError> {
+        Self::compile_with_limits(&std::fs::read_to_string(path)?, limits)
+    }
+
+    pub fn run(&self, request: &Request) -> Result<Response, ScriptError> {
+        let mut scope = Scope::new();
+        scope.push_constant("request", to_dynamic(request)?);
+        scope.push_constant("context", to_dynamic(&request.context)?);
+        scope.push_constant("envelope", to_dynamic(&request.envelope)?);
+        scope.push_constant("message", to_dynamic(&request.message)?);
+
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + self.max_duration)));
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        DEADLINE.with(|deadline| deadline.set(None));
+
+        into_response(result.map_err(runtime_error)?)
+    }
+}
+
+fn engine(limits: &ScriptLimits) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(limits.max_operations)
+        .set_max_call_levels(limits.max_call_levels)
+        .set_max_string_size(limits.max_string_size)
+        .set_max_array_size(limits.max_array_size)
+        .set_max_map_size(limits.max_map_size)
+        .on_progress(|operations| {
+            if operations % 256 != 0 {
+                return None;
+            }
+            DEADLINE.with(|deadline| match deadline.get() {
+                Some(deadline) if Instant::now() >= deadline => Some(Dynamic::UNIT),
+                _ => None,
+            })
+        });
+
+    engine
+        .register_type_with_name::<Response>("Response")
+        .register_fn("accept", Response::accept)
+        .register_fn("discard", Response::discard)
+        .register_fn("quarantine", Response::quarantine)
+        .register_fn("reject", |status: i64, message: &str| {
+            Response::reject(status_code(status), message.to_string())
+        })
+        .register_fn(
+            "reject",
+            |status: i64, enhanced_status: &str, message: &str| {
+                let mut response = Response::reject(status_code(status), message.to_string());
+                if let Some(smtp) = response.response.as_mut() {
+                    smtp.enhanced_status = Some(enhanced_status.to_string());
+                }
+                response
+            },
+        )
+        .register_fn(
+            "push",
+            |response: &mut Response, modification: Modification| {
+                response.modifications.push(modification)
+            },
+        )
+        .register_fn("disconnect", |response: &mut Response| {
+            response
+                .response
+                .get_or_insert_with(SmtpResponse::default)
+                .disconnect = true
+        });
+
+    engine
+        .register_type_with_name::<Modification>("Modification")
+        .register_fn("change_from", |address: &str| {
+            Modification::change_from(address.to_string())
+        })
+        .register_fn("add_recipient", |address: &str| {
+            Modification::add_recipient(address.to_string())
+        })
+        .register_fn("delete_recipient", |address: &str| {
+            Modification::delete_recipient(address.to_string())
+        })
+        .register_fn("replace_contents", |contents: &str| {
+            Modification::replace_contents(contents.to_string())
+        })
+        .register_fn("add_header", |name: &str, value: &str| {
+            Modification::add_header(name.to_string(), value.to_string())
+        })
+        .register_fn("insert_header", |index: i64, name: &str, value: &str| {
+            Modification::insert_header(header_index(index), name.to_string(), value.to_string())
+        })
+        .register_fn("change_header", |index: i64, name: &str, value: &str| {
+            Modification::change_header(header_index(index), name.to_string(), value.to_string())
+        })
+        .register_fn("delete_header", |index: i64, name: &str| {
+            Modification::delete_header(header_index(index), name.to_string())
+        });
+
+    engine
+}
+
+fn to_dynamic<T: serde::Serialize>(value: &T) -> Result<Dynamic, ScriptError> {
+    rhai::serde::to_dynamic(value).map_err(|err| ScriptError::Runtime(err.to_string()))
+}
+
+fn status_code(status: i64) -> u16 {
+    status.clamp(0, u16::MAX as i64) as u16
+}
+
+fn header_index(index: i64) -> u32 {
+    index.clamp(0, u32::MAX as i64) as u32
+}
+
+fn runtime_error(err: Box<EvalAltResult>) -> ScriptError {
+    match err.unwrap_inner() {
+        EvalAltResult::ErrorTerminated(..) => ScriptError::Timeout,
+        EvalAltResult::ErrorTooManyOperations(..)
+        | EvalAltResult::ErrorDataTooLarge(..)
+        | EvalAltResult::ErrorStackOverflow(..) => ScriptError::LimitExceeded(err.to_string()),
+        _ => ScriptError::Runtime(err.to_string()),
+    }
+}
+
+fn into_response(result: Dynamic) -> Result<Response, ScriptError> {
+    if result.is_unit() {
+        return Ok(Response::accept());
+    }
+    if result.is::<Response>() {
+        return Ok(result.cast::<Response>());
+    }
+    if result.is::<Modification>() {
+        return Ok(Response::accept().with_modifications(vec![result.cast::<Modification>()]));
+    }
+    if result.is_array() {
+        let modifications = result
+            .cast::<rhai::Array>()
+            .into_iter()
+            .map(|item| {
+                item.try_cast::<Modification>().ok_or_else(|| {
+                    ScriptError::InvalidResult("an array of non-modifications".to_string())
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        return Ok(Response::accept().with_modifications(modifications));
+    }
+    Err(ScriptError::InvalidResult(result.type_name().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{Address, Client, Context, Envelope, Message, Protocol, Server, Stage};
+    use crate::response::Action;
+
+    fn request() -> Request {
+        Request {
+            context: Context {
+                stage: Stage::Data,
+                client: Client {
+                    ip: "192.168.1.1".to_string(),
+                    port: 34567,
+                    ptr: None,
+                    helo: Some("mail.example.com".to_string()),
+                    active_connections: 3,
+                },
+                sasl: None,
+                tls: None,
+                server: Server {
+                    name: None,
+                    port: 25,
+                    ip: None,
+                },
+                queue: None,
+                protocol: Protocol { version: 1 },
+            },
+            envelope: Some(Envelope {
+                from: Address {
+                    address: "john@example.com".to_string(),
+                    parameters: None,
+                },
+                to: vec![Address {
+                    address: "bill@foobar.com".to_string(),
+                    parameters: None,
+                }],
+            }),
+            message: Some(Message {
+                headers: vec![("Subject".to_string(), "Hello, World!".to_string())],
+                server_headers: vec![],
+                contents: "Hello, World!\r\n".into(),
+                size: 15,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_reject_with_modifications() {
+        let script = HookScript::compile(
+            r#"
+            if context.stage == "data" && envelope.to[0].address.ends_with("@foobar.com")
+                && context.client.activeConnections > 2 {
+                let response = reject(550, "5.7.1", "Too many connections");
+                response.push(add_header("X-Script", message.headers[0][1]));
+                response.disconnect();
+                return response;
+            }
+            accept()
+            "#,
+        )
+        .unwrap();
+
+        let response = script.run(&request()).unwrap();
+        assert!(matches!(response.action, Action::Reject));
+        let smtp = response.response.unwrap();
+        assert_eq!(smtp.status, Some(550));
+        assert_eq!(smtp.enhanced_status.as_deref(), Some("5.7.1"));
+        assert!(smtp.disconnect);
+        match &response.modifications[0] {
+            Modification::AddHeader { name, value } => {
+                assert_eq!(name, "X-Script");
+                assert_eq!(value, "Hello, World!");
+            }
+            _ => panic!("Expected AddHeader modification"),
+        }
+    }
+
+    #[test]
+    fn test_modification_results() {
+        let script = HookScript::compile(
+            r#"[add_recipient("archive@example.com"), delete_header(0, "Subject")]"#,
+        )
+        .unwrap();
+        let response = script.run(&request()).unwrap();
+        assert!(matches!(response.action, Action::Accept));
+        assert_eq!(response.modifications.len(), 2);
+
+        let script = HookScript::compile("if message == () { discard() }").unwrap();
+        let mut without_message = request();
+        without_message.message = None;
+        assert!(matches!(
+            script.run(&without_message).unwrap().action,
+            Action::Discard
+        ));
+        assert!(matches!(
+            script.run(&request()).unwrap().action,
+            Action::Accept
+        ));
+
+        let script = HookScript::compile("42").unwrap();
+        assert!(matches!(
+            script.run(&request()),
+            Err(ScriptError::InvalidResult(_))
+        ));
+    }
+
+    #[test]
+    fn test_limits() {
+        let script = HookScript::compile_with_limits(
+            "loop {}",
+            ScriptLimits {
+                max_duration: Duration::from_millis(20),
+                max_operations: 0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(matches!(script.run(&request()), Err(ScriptError::Timeout)));
+
+        let script = HookScript::compile_with_limits(
+            "loop {}",
+            ScriptLimits {
+                max_operations: 10_000,
+                max_duration: Duration::from_secs(60),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            script.run(&request()),
+            Err(ScriptError::LimitExceeded(_))
+        ));
+
+        let script = HookScript::compile_with_limits(
+            r#"let s = "x"; loop { s += s; }"#,
+            ScriptLimits {
+                max_string_size: 1024,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            script.run(&request()),
+            Err(ScriptError::LimitExceeded(_))
+        ));
+
+        assert!(matches!(
+            HookScript::compile("let = ;"),
+            Err(ScriptError::Compile(_))
+        ));
+    }
+}
@@ -0,0 +1,343 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Per-session state shared between the hook calls of one SMTP session.
+//!
+//! Stalwart calls the hook once per stage. Sessions are identified by the
+//! client and server address and port, and can additionally be looked up
+//! by queue id once the message has been queued.
+//!
+//! ```
+//! use std::time::Duration;
+//! use stalwart_mta_hook_types::session::SessionTracker;
+//!
+//! struct AuthFailures(u32);
+//!
+//! let tracker = SessionTracker::new(Duration::from_secs(600));
+//! # let request: stalwart_mta_hook_types::Request = serde_json::from_str(r#"{
+//! #   "context": {"stage": "auth", "client": {"ip": "192.0.2.1", "port": 4000,
+//! #     "activeConnections": 1}, "server": {"port": 25}, "protocol": {"version": 1}}
+//! # }"#).unwrap();
+//! tracker.with_session(&request, |session| {
+//!     let failures = session.get_or_insert_with(|| AuthFailures(0));
+//!     failures.0 += 1;
+//! });
+//! ```
+
+use crate::request::{Request, Stage};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Identifies an SMTP session by both ends of its connection.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub client_ip: String,
+    pub client_port: u16,
+    pub server_ip: Option<String>,
+    pub server_port: u16,
+}
+
+impl SessionKey {
+    pub fn from_request(request: &Request) -> Self {
+        let context = &request.context;
+        Self {
+            client_ip: context.client.ip.clone(),
+            client_port: context.client.port,
+            server_ip: context.server.ip.clone(),
+            server_port: context.server.port,
+        }
+    }
+}
+
+/// State of a single session, holding at most one value per type.
+pub struct Session {
+    created: Instant,
+    stages: Vec<Stage>,
+    queue_id: Option<String>,
+    data: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl Session {
+    fn new(now: Instant) -> Self {
+        Self {
+            created: now,
+            stages: Vec::new(),
+            queue_id: None,
+            data: HashMap::new(),
+        }
+    }
+
+    pub fn created(&self) -> Instant {
+        self.created
+    }
+
+    /// The stages seen so far, in order, without consecutive repeats.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// The queue id of the most recent message in this session.
+    pub fn queue_id(&self) -> Option<&str> {
+        self.queue_id.as_deref()
+    }
+
+    /// Stores a value, returning the previous value of the same type.
+    pub fn insert<T: Any + Send>(&mut self, value: T) -> Option<T> {
+        self.data
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn get<T: Any + Send>(&self) -> Option<&T> {
+        self.data
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    pub fn get_mut<T: Any + Send>(&mut self) -> Option<&mut T> {
+        self.data
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    pub fn get_or_insert_with<T: Any + Send>(&mut self, default: impl FnOnce() -> T) -> &mut T {
+        self.data
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(default()))
+            .downcast_mut()
+            .expect("session value stored under its own type id")
+    }
+
+    pub fn remove<T: Any + Send>(&mut self) -> Option<T> {
+        self.data
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    pub fn contains<T: Any + Send>(&self) -> bool {
+        self.data.contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("created", &self.created)
+            .field("stages", &self.stages)
+            .field("queue_id", &self.queue_id)
+            .field("values", &self.data.len())
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    sessions: HashMap<SessionKey, (Instant, Session)>,
+    queue_ids: HashMap<String, SessionKey>,
+}
+
+/// Tracks sessions across hook calls, expiring them after `ttl` without
+/// activity.
+#[derive(Debug)]
+pub struct SessionTracker {
+    ttl: Duration,
+    inner: Mutex<Sessions>,
+}
+
+impl SessionTracker {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            inner: Mutex::new(Sessions::default()),
+        }
+    }
+
+    /// Runs `f` on the session of `request`, creating it if needed and
+    /// recording the stage and queue id of the request. A `Stage::Connect`
+    /// request always starts a new session, as a new connection may reuse
+    /// the client port of an earlier one.
+    pub fn with_session<R>(&self, request: &Request, f: impl FnOnce(&mut Session) -> R) -> R {
+        self.with_session_at(request, Instant::now(), f)
+    }
+
+    /// Same as [`SessionTracker::with_session`] at an explicit time.
+    pub fn with_session_at<R>(
+        &self,
+        request: &Request,
+        now: Instant,
+        f: impl FnOnce(&mut Session) -> R,
+    ) -> R {
+        let key = SessionKey::from_request(request);
+        let mut inner = self.lock();
+        let Sessions {
+            sessions,
+            queue_ids,
+        } = &mut *inner;
+
+        let (last_seen, session) = sessions
+            .entry(key.clone())
+            .or_insert_with(|| (now, Session::new(now)));
+        if now.duration_since(*last_seen) > self.ttl || request.context.stage == Stage::Connect {
+            if let Some(queue_id) = session.queue_id.take() {
+                queue_ids.remove(&queue_id);
+            }
+            *session = Session::new(now);
+        }
+        *last_seen = now;
+
+        let stage = request.context.stage;
+        if session.stages.last() != Some(&stage) {
+            session.stages.push(stage);
+        }
+        if let Some(queue) = &request.context.queue {
+            if session.queue_id.as_deref() != Some(queue.id.as_str()) {
+                if let Some(previous) = session.queue_id.replace(queue.id.clone()) {
+                    queue_ids.remove(&previous);
+                }
+                queue_ids.insert(queue.id.clone(), key);
+            }
+        }
+        f(session)
+    }
+
+    /// Runs `f` on the session that queued `queue_id`, if it is still known.
+    pub fn with_queue_session<R>(
+        &self,
+        queue_id: &str,
+        f: impl FnOnce(&mut Session) -> R,
+    ) -> Option<R> {
+        let mut inner = self.lock();
+        let key = inner.queue_ids.get(queue_id)?.clone();
+        inner.sessions.get_mut(&key).map(|(_, session)| f(session))
+    }
+
+    /// Removes the session of `request`, e.g. once the message was accepted.
+    pub fn end(&self, request: &Request) -> Option<Session> {
+        let mut inner = self.lock();
+        let (_, session) = inner.sessions.remove(&SessionKey::from_request(request))?;
+        if let Some(queue_id) = &session.queue_id {
+            inner.queue_ids.remove(queue_id);
+        }
+        Some(session)
+    }
+
+    /// Drops sessions without activity for longer than the ttl.
+    pub fn purge(&self) -> usize {
+        self.purge_at(Instant::now())
+    }
+
+    pub fn purge_at(&self, now: Instant) -> usize {
+        let mut inner = self.lock();
+        let before = inner.sessions.len();
+        inner
+            .sessions
+            .retain(|_, (last_seen, _)| now.duration_since(*last_seen) <= self.ttl);
+        let Sessions {
+            sessions,
+            queue_ids,
+        } = &mut *inner;
+        queue_ids.retain(|_, key| sessions.contains_key(key));
+        before - sessions.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Sessions> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(stage: Stage, port: u16, queue: Option<&str>) -> Request {
+        let mut builder = Request::builder().stage(stage).client_port(port);
+        if let Some(id) = queue {
+            builder = builder.queue_id(id);
+        }
+        builder.build()
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Helo(String);
+
+    #[test]
+    fn test_state_across_stages() {
+        let tracker = SessionTracker::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        tracker.with_session_at(&request(Stage::Connect, 4000, None), start, |session| {
+            assert!(session.insert(Helo("mx.example.com".to_string())).is_none());
+            *session.get_or_insert_with(|| 0u32) += 1;
+        });
+        tracker.with_session_at(&request(Stage::Connect, 4001, None), start, |session| {
+            assert!(!session.contains::<Helo>());
+        });
+
+        let data = request(Stage::Data, 4000, Some("abc123"));
+        let seen = tracker.with_session_at(&data, start + Duration::from_secs(30), |session| {
+            *session.get_mut::<u32>().unwrap() += 1;
+            assert_eq!(session.stages(), &[Stage::Connect, Stage::Data]);
+            assert_eq!(session.queue_id(), Some("abc123"));
+            session.get::<Helo>().map(|helo| helo.0.clone())
+        });
+        assert_eq!(seen.as_deref(), Some("mx.example.com"));
+        assert_eq!(
+            tracker.with_queue_session("abc123", |session| *session.get::<u32>().unwrap()),
+            Some(2)
+        );
+        assert_eq!(tracker.len(), 2);
+
+        let session = tracker.end(&data).unwrap();
+        assert_eq!(
+            session.get::<Helo>(),
+            Some(&Helo("mx.example.com".to_string()))
+        );
+        assert!(tracker.with_queue_session("abc123", |_| ()).is_none());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let tracker = SessionTracker::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let connect = request(Stage::Connect, 4000, None);
+
+        tracker.with_session_at(&connect, start, |session| session.insert(1u8));
+        let later = start + Duration::from_secs(120);
+        tracker.with_session_at(&connect, later, |session| {
+            assert!(session.get::<u8>().is_none());
+            assert_eq!(session.created(), later);
+        });
+
+        tracker.with_session_at(&request(Stage::Connect, 4001, None), later, |_| ());
+
+        // A new connection from a reused port starts over within the ttl.
+        let data = request(Stage::Data, 4000, Some("abc123"));
+        tracker.with_session_at(&data, later, |session| session.insert(2u8));
+        tracker.with_session_at(&connect, later, |session| {
+            assert!(session.get::<u8>().is_none());
+            assert_eq!(session.stages(), &[Stage::Connect]);
+            assert_eq!(session.queue_id(), None);
+        });
+        assert!(tracker.with_queue_session("abc123", |_| ()).is_none());
+
+        assert_eq!(tracker.purge_at(later + Duration::from_secs(30)), 0);
+        assert_eq!(tracker.purge_at(later + Duration::from_secs(90)), 2);
+        assert!(tracker.is_empty());
+    }
+}
@@ -0,0 +1,719 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Spam scanning with rspamd and SpamAssassin's spamd.
+//!
+//! Both clients rebuild the message from the request and pass the envelope
+//! and client details along: rspamd receives them as HTTP headers of its
+//! `/checkv2` API, spamd as a `Received` header prepended to the message.
+//! The resulting [`SpamResult`] converts into a [`Response`] with
+//! `X-Spam-*` headers.
+//!
+//! ```no_run
+//! use stalwart_mta_hook_types::spam::Rspamd;
+//! use stalwart_mta_hook_types::{Request, Response, Stage};
+//!
+//! let rspamd = Rspamd::new("127.0.0.1:11333".to_string());
+//! let request = Request::builder()
+//!     .stage(Stage::Data)
+//!     .helo("mail.example.com")
+//!     .from("john@example.com")
+//!     .to("bill@example.org")
+//!     .raw_message("Subject: Cheap watches\r\n\r\nBuy now!\r\n")
+//!     .build();
+//! let response = match rspamd.check(&request) {
+//!     Ok(result) => result.to_response(&request),
+//!     Err(_) => Response::accept(),
+//! };
+//! ```
+
+use crate::mime::{encode_encoded_words, write_header};
+use crate::modifications::Modification;
+use crate::net::connect_tcp;
+use crate::request::Request;
+use crate::response::{Action, Response, SmtpResponse};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum SpamError {
+    Io(io::Error),
+    Timeout,
+    /// The scanner answered with an error or an unparsable reply.
+    Protocol(String),
+    /// The request carries no message.
+    MissingMessage,
+}
+
+impl fmt::Display for SpamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpamError::Io(err) => write!(f, "spam scanner connection failed: {}", err),
+            SpamError::Timeout => write!(f, "spam scanner timed out"),
+            SpamError::Protocol(reply) => write!(f, "unexpected spam scanner reply: {}", reply),
+            SpamError::MissingMessage => write!(f, "request has no message"),
+        }
+    }
+}
+
+impl std::error::Error for SpamError {}
+
+impl From<io::Error> for SpamError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => SpamError::Timeout,
+            _ => SpamError::Io(err),
+        }
+    }
+}
+
+/// The action recommended by the scanner, named as in rspamd.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamAction {
+    NoAction,
+    Greylist,
+    AddHeader,
+    RewriteSubject,
+    SoftReject,
+    Reject,
+}
+
+impl SpamAction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "no action" => Some(SpamAction::NoAction),
+            "greylist" => Some(SpamAction::Greylist),
+            "add header" => Some(SpamAction::AddHeader),
+            "rewrite subject" => Some(SpamAction::RewriteSubject),
+            "soft reject" => Some(SpamAction::SoftReject),
+            "reject" => Some(SpamAction::Reject),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub score: f64,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpamResult {
+    pub score: f64,
+    pub required_score: f64,
+    pub action: SpamAction,
+    /// Matched rules, sorted by name.
+    pub symbols: Vec<Symbol>,
+    /// The rewritten subject for [`SpamAction::RewriteSubject`].
+    pub subject: Option<String>,
+}
+
+impl SpamResult {
+    pub fn is_spam(&self) -> bool {
+        !matches!(self.action, SpamAction::NoAction | SpamAction::Greylist)
+    }
+
+    /// The `X-Spam-Flag`, `X-Spam-Score` and `X-Spam-Status` headers.
+    pub fn headers(&self) -> Vec<Modification> {
+        let flag = if self.is_spam() { "YES" } else { "NO" };
+        let tests = self
+            .symbols
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        vec![
+            Modification::add_header("X-Spam-Flag".to_string(), flag.to_string()),
+            Modification::add_header("X-Spam-Score".to_string(), format!("{:.2}", self.score)),
+            Modification::add_header(
+                "X-Spam-Status".to_string(),
+                format!(
+                    "{}, score={:.2} required={:.2} tests={}",
+                    if self.is_spam() { "Yes" } else { "No" },
+                    self.score,
+                    self.required_score,
+                    tests
+                ),
+            ),
+        ]
+    }
+
+    /// Follows the recommended action: rejects, defers, or accepts with the
+    /// `X-Spam-*` headers and a rewritten subject, which replaces the
+    /// subject of the checked `request` or is added if it has none.
+    pub fn to_response(&self, request: &Request) -> Response {
+        let reject = |status: u16, enhanced: &str, message: &str| Response {
+            action: Action::Reject,
+            response: Some(SmtpResponse {
+                status: Some(status),
+                enhanced_status: Some(enhanced.to_string()),
+                message: Some(message.to_string()),
+                disconnect: false,
+            }),
+            modifications: Vec::new(),
+        };
+        match self.action {
+            SpamAction::Reject => reject(554, "5.7.1", "Message rejected as spam"),
+            SpamAction::SoftReject | SpamAction::Greylist => {
+                reject(451, "4.7.1", "Try again later")
+            }
+            SpamAction::RewriteSubject => {
+                let mut modifications = self.headers();
+                if let Some(subject) = &self.subject {
+                    let subject = encode_encoded_words(subject);
+                    let has_subject = request.message.as_ref().is_some_and(|message| {
+                        message
+                            .headers
+                            .iter()
+                            .any(|(name, _)| name.eq_ignore_ascii_case("Subject"))
+                    });
+                    modifications.push(if has_subject {
+                        Modification::change_header(1, "Subject".to_string(), subject)
+                    } else {
+                        Modification::add_header("Subject".to_string(), subject)
+                    });
+                }
+                Response::accept().with_modifications(modifications)
+            }
+            SpamAction::NoAction | SpamAction::AddHeader => {
+                Response::accept().with_modifications(self.headers())
+            }
+        }
+    }
+}
+
+/// The message as received, with the headers the server is about to add.
+fn raw_message(request: &Request) -> Result<Vec<u8>, SpamError> {
+    let message = request.message.as_ref().ok_or(SpamError::MissingMessage)?;
+    Ok(message.to_rfc5322())
+}
+
+/// A client for rspamd's HTTP protocol.
+#[derive(Debug, Clone)]
+pub struct Rspamd {
+    address: String,
+    path: String,
+    password: Option<String>,
+    timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct RspamdReply {
+    #[serde(default)]
+    score: f64,
+    #[serde(default)]
+    required_score: f64,
+    action: String,
+    #[serde(default)]
+    symbols: HashMap<String, RspamdSymbol>,
+    subject: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RspamdSymbol {
+    #[serde(default)]
+    score: f64,
+    description: Option<String>,
+}
+
+impl Rspamd {
+    /// Connects to the normal worker at `host:port`, usually port 11333.
+    pub fn new(address: String) -> Self {
+        Self {
+            address,
+            path: "/checkv2".to_string(),
+            password: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the request path (`/checkv2` by default).
+    pub fn with_path(mut self, path: String) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Sends the controller password with each request.
+    pub fn with_password(mut self, password: String) -> Self {
+        self.password = Some(password);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The rspamd request headers describing envelope and client.
+    fn metadata(&self, request: &Request) -> Vec<(&'static str, String)> {
+        let context = &request.context;
+        let mut headers = vec![("IP", context.client.ip.clone())];
+        headers.extend(context.client.helo.clone().map(|helo| ("Helo", helo)));
+        headers.extend(context.client.ptr.clone().map(|ptr| ("Hostname", ptr)));
+        headers.extend(
+            context
+                .sasl
+                .as_ref()
+                .map(|sasl| ("User", sasl.login.clone())),
+        );
+        headers.extend(
+            context
+                .queue
+                .as_ref()
+                .map(|queue| ("Queue-Id", queue.id.clone())),
+        );
+        headers.extend(context.server.name.clone().map(|name| ("MTA-Name", name)));
+        if let Some(tls) = &context.tls {
+            headers.push(("TLS-Version", tls.version.clone()));
+            headers.push(("TLS-Cipher", tls.cipher.clone()));
+        }
+        if let Some(envelope) = &request.envelope {
+            headers.push(("From", envelope.from.address.clone()));
+            headers.extend(
+                envelope
+                    .to
+                    .iter()
+                    .map(|rcpt| ("Rcpt", rcpt.address.clone())),
+            );
+        }
+        headers.extend(self.password.clone().map(|password| ("Password", password)));
+        headers
+    }
+
+    pub fn check(&self, request: &Request) -> Result<SpamResult, SpamError> {
+        let message = raw_message(request)?;
+        let mut http = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            self.path,
+            self.address,
+            message.len()
+        );
+        for (name, value) in self.metadata(request) {
+            http.push_str(&format!(
+                "{}: {}\r\n",
+                name,
+                value.replace(['\r', '\n'], "")
+            ));
+        }
+        http.push_str("\r\n");
+
+        let mut stream = connect_tcp(&self.address, self.timeout)?;
+        stream.write_all(http.as_bytes())?;
+        stream.write_all(&message)?;
+        stream.flush()?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+
+        let body = http_body(&reply)?;
+        let reply: RspamdReply = serde_json::from_slice(&body)
+            .map_err(|err| SpamError::Protocol(format!("invalid rspamd reply: {}", err)))?;
+        let action = SpamAction::parse(&reply.action)
+            .ok_or_else(|| SpamError::Protocol(format!("unknown action {}", reply.action)))?;
+        let mut symbols: Vec<Symbol> = reply
+            .symbols
+            .into_iter()
+            .map(|(name, symbol)| Symbol {
+                name,
+                score: symbol.score,
+                description: symbol.description,
+            })
+            .collect();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(SpamResult {
+            score: reply.score,
+            required_score: reply.required_score,
+            action,
+            symbols,
+            subject: reply.subject,
+        })
+    }
+}
+
+/// Extracts the body of a successful HTTP/1.1 response.
+fn http_body(reply: &[u8]) -> Result<Vec<u8>, SpamError> {
+    let split = reply
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| SpamError::Protocol("truncated HTTP response".to_string()))?;
+    let head = String::from_utf8_lossy(&reply[..split]);
+    let body = &reply[split + 4..];
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(SpamError::Protocol(status.to_string()));
+    }
+    let chunked = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked")
+        })
+    });
+    if !chunked {
+        return Ok(body.to_vec());
+    }
+
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut rest = body;
+    loop {
+        let line_end = rest
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| SpamError::Protocol("truncated chunk".to_string()))?;
+        let size = std::str::from_utf8(&rest[..line_end])
+            .ok()
+            .and_then(|size| usize::from_str_radix(size.split(';').next()?.trim(), 16).ok())
+            .ok_or_else(|| SpamError::Protocol("invalid chunk size".to_string()))?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = rest
+            .get(..size)
+            .ok_or_else(|| SpamError::Protocol("truncated chunk".to_string()))?;
+        decoded.extend_from_slice(chunk);
+        rest = rest.get(size + 2..).unwrap_or_default();
+    }
+}
+
+/// A client for SpamAssassin's spamd, speaking SPAMC/1.5.
+#[derive(Debug, Clone)]
+pub struct Spamd {
+    address: String,
+    user: Option<String>,
+    reject_score: Option<f64>,
+    timeout: Duration,
+}
+
+impl Spamd {
+    /// Connects to spamd at `host:port`, usually port 783.
+    pub fn new(address: String) -> Self {
+        Self {
+            address,
+            user: None,
+            reject_score: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the user whose preferences spamd applies.
+    pub fn with_user(mut self, user: String) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Recommends rejecting messages scoring at least `score`; spamd only
+    /// flags messages otherwise.
+    pub fn with_reject_score(mut self, score: f64) -> Self {
+        self.reject_score = Some(score);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// A `Received` header describing the SMTP session, so that spamd sees
+    /// the client even if the server headers are not part of the request.
+    fn received(request: &Request) -> String {
+        let context = &request.context;
+        let client = &context.client;
+        let mut received = format!(
+            "from {} ({} [{}])",
+            client.helo.as_deref().unwrap_or("unknown"),
+            client.ptr.as_deref().unwrap_or("unknown"),
+            client.ip
+        );
+        if let Some(name) = &context.server.name {
+            received.push_str(&format!("\r\n\tby {}", name));
+        }
+        received.push_str(match (&context.tls, &context.sasl) {
+            (Some(_), Some(_)) => " with ESMTPSA",
+            (Some(_), None) => " with ESMTPS",
+            (None, Some(_)) => " with ESMTPA",
+            (None, None) => " with ESMTP",
+        });
+        if let Some(queue) = &context.queue {
+            received.push_str(&format!(" id {}", queue.id));
+        }
+        if let Some(envelope) = &request.envelope {
+            if let [rcpt] = envelope.to.as_slice() {
+                received.push_str(&format!("\r\n\tfor <{}>", rcpt.address));
+            }
+        }
+        received
+    }
+
+    pub fn check(&self, request: &Request) -> Result<SpamResult, SpamError> {
+        let mut message = String::new();
+        let has_received = request
+            .message
+            .as_ref()
+            .is_some_and(|message| !message.server_headers.is_empty());
+        if !has_received {
+            write_header(&mut message, "Received", &Self::received(request));
+        }
+        if let Some(envelope) = &request.envelope {
+            write_header(
+                &mut message,
+                "Return-Path",
+                &format!("<{}>", envelope.from.address),
+            );
+        }
+        let mut message = message.into_bytes();
+        message.extend(raw_message(request)?);
+
+        let mut spamc = format!("SYMBOLS SPAMC/1.5\r\nContent-length: {}\r\n", message.len());
+        if let Some(user) = &self.user {
+            spamc.push_str(&format!("User: {}\r\n", user));
+        }
+        spamc.push_str("\r\n");
+
+        let mut stream = connect_tcp(&self.address, self.timeout)?;
+        stream.write_all(spamc.as_bytes())?;
+        stream.write_all(&message)?;
+        stream.flush()?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        self.parse_reply(&String::from_utf8_lossy(&reply))
+    }
+
+    fn parse_reply(&self, reply: &str) -> Result<SpamResult, SpamError> {
+        let (head, body) = reply
+            .split_once("\r\n\r\n")
+            .unwrap_or((reply.trim_end(), ""));
+        let mut lines = head.lines();
+        let status = lines.next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("0") {
+            return Err(SpamError::Protocol(status.to_string()));
+        }
+        // Spam: True ; 15.3 / 5.0
+        let spam = lines
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("spam")
+                    .then(|| value.trim())
+            })
+            .ok_or_else(|| SpamError::Protocol("missing Spam header".to_string()))?;
+        let (flag, scores) = spam
+            .split_once(';')
+            .ok_or_else(|| SpamError::Protocol(spam.to_string()))?;
+        let (score, required_score) = scores
+            .split_once('/')
+            .and_then(|(score, required)| {
+                Some((score.trim().parse().ok()?, required.trim().parse().ok()?))
+            })
+            .ok_or_else(|| SpamError::Protocol(spam.to_string()))?;
+
+        let is_spam = matches!(flag.trim().to_ascii_lowercase().as_str(), "true" | "yes");
+        let action = match self.reject_score {
+            Some(reject) if score >= reject => SpamAction::Reject,
+            _ if is_spam => SpamAction::AddHeader,
+            _ => SpamAction::NoAction,
+        };
+        let mut symbols: Vec<Symbol> = body
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| Symbol {
+                name: name.to_string(),
+                score: 0.0,
+                description: None,
+            })
+            .collect();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(SpamResult {
+            score,
+            required_score,
+            action,
+            symbols,
+            subject: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Stage;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn request() -> Request {
+        Request::builder()
+            .stage(Stage::Data)
+            .client_port(4000)
+            .ptr("mail.example.com")
+            .helo("mail.example.com")
+            .server_name("mx.foobar.com")
+            .from("john@example.com")
+            .to("bill@foobar.com")
+            .to("jane@foobar.com")
+            .raw_message("From: john@example.com\r\nSubject: Cheap watches\r\n\r\nBuy now!\r\n")
+            .build()
+    }
+
+    /// Accepts one connection, hands the request head and body to `check`
+    /// and sends back `reply`.
+    fn serve(reply: String, check: impl FnOnce(&str, &str) + Send + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut data = Vec::new();
+            let mut buf = [0u8; 1024];
+            let (head, body) = loop {
+                let read = stream.read(&mut buf).unwrap();
+                data.extend_from_slice(&buf[..read]);
+                let text = String::from_utf8_lossy(&data).into_owned();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse().unwrap())
+                        })
+                        .unwrap();
+                    if body.len() >= length {
+                        break (format!("{}\r\n", head), body.to_string());
+                    }
+                }
+            };
+            check(&head, &body);
+            stream.write_all(reply.as_bytes()).unwrap();
+        });
+        address
+    }
+
+    #[test]
+    fn test_rspamd() {
+        let json = r#"{"is_skipped":false,"score":9.5,"required_score":15.0,
+            "action":"rewrite subject","subject":"*** SPAM *** Cheap watches",
+            "symbols":{"BAYES_SPAM":{"name":"BAYES_SPAM","score":5.1,"description":"Bayes"},
+            "R_DKIM_NA":{"name":"R_DKIM_NA","score":0.0}}}"#;
+        let reply = format!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            json.len(),
+            json
+        );
+        let address = serve(reply, |head, body| {
+            assert!(head.starts_with("POST /checkv2 HTTP/1.1\r\n"));
+            assert!(head.contains("\r\nIP: 192.0.2.1\r\n"));
+            assert!(head.contains("\r\nFrom: john@example.com\r\n"));
+            assert!(head.contains("\r\nRcpt: bill@foobar.com\r\nRcpt: jane@foobar.com\r\n"));
+            assert!(head.contains("\r\nHelo: mail.example.com\r\n"));
+            assert_eq!(
+                body,
+                "From: john@example.com\r\nSubject: Cheap watches\r\n\r\nBuy now!\r\n"
+            );
+        });
+
+        let result = Rspamd::new(address).check(&request()).unwrap();
+        assert_eq!(result.action, SpamAction::RewriteSubject);
+        assert_eq!(result.symbols[0].name, "BAYES_SPAM");
+        assert_eq!(result.symbols[0].description.as_deref(), Some("Bayes"));
+
+        let response = result.to_response(&request());
+        assert_eq!(response.action, Action::Accept);
+        let values: Vec<String> = response
+            .modifications
+            .iter()
+            .map(|modification| match modification {
+                Modification::AddHeader { name, value } => format!("{}: {}", name, value),
+                Modification::ChangeHeader { index, name, value } => {
+                    format!("{}[{}]: {}", name, index, value)
+                }
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                "X-Spam-Flag: YES",
+                "X-Spam-Score: 9.50",
+                "X-Spam-Status: Yes, score=9.50 required=15.00 tests=BAYES_SPAM,R_DKIM_NA",
+                "Subject[1]: *** SPAM *** Cheap watches",
+            ]
+        );
+
+        // A missing subject is added, a non-ASCII one encoded.
+        let mut result = result;
+        result.subject = Some("*** SPAM *** Grüße".to_string());
+        let mut no_subject = request();
+        let message = no_subject.message.as_mut().unwrap();
+        message.headers.retain(|(name, _)| name != "Subject");
+        let response = result.to_response(&no_subject);
+        let Some(Modification::AddHeader { name, value }) = response.modifications.last() else {
+            panic!("expected AddHeader");
+        };
+        assert_eq!(name, "Subject");
+        assert_eq!(value, "=?UTF-8?B?KioqIFNQQU0gKioqIEdyw7zDn2U=?=");
+    }
+
+    #[test]
+    fn test_rspamd_errors() {
+        let address = serve(
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n".to_string(),
+            |_, _| {},
+        );
+        assert!(matches!(
+            Rspamd::new(address).check(&request()),
+            Err(SpamError::Protocol(_))
+        ));
+
+        let mut no_message = request();
+        no_message.message = None;
+        assert!(matches!(
+            Rspamd::new("127.0.0.1:1".to_string()).check(&no_message),
+            Err(SpamError::MissingMessage)
+        ));
+    }
+
+    #[test]
+    fn test_spamd() {
+        let reply = "SPAMD/1.1 0 EX_OK\r\nContent-length: 27\r\nSpam: True ; 15.3 / 5.0\r\n\r\nBAYES_99,HTML_MESSAGE\r\n";
+        let address = serve(reply.to_string(), |head, body| {
+            assert!(head.starts_with("SYMBOLS SPAMC/1.5\r\n"));
+            assert!(head.contains("\r\nUser: bill\r\n"));
+            assert!(body.starts_with(
+                "Received: from mail.example.com (mail.example.com [192.0.2.1])\r\n\
+                 \tby mx.foobar.com with ESMTP\r\n\
+                 Return-Path: <john@example.com>\r\n\
+                 From: john@example.com\r\n"
+            ));
+        });
+        let spamd = Spamd::new(address).with_user("bill".to_string());
+        let result = spamd.check(&request()).unwrap();
+        assert_eq!(result.score, 15.3);
+        assert_eq!(result.required_score, 5.0);
+        assert_eq!(result.action, SpamAction::AddHeader);
+        assert_eq!(result.symbols.len(), 2);
+
+        let rejecting = spamd.with_reject_score(15.0);
+        let result = rejecting
+            .parse_reply("SPAMD/1.1 0 EX_OK\r\nSpam: True ; 15.3 / 5.0\r\n\r\n")
+            .unwrap();
+        let response = result.to_response(&request());
+        assert_eq!(response.action, Action::Reject);
+        assert_eq!(response.response.unwrap().status, Some(554));
+
+        let result = rejecting
+            .parse_reply("SPAMD/1.1 0 EX_OK\r\nSpam: False ; 1.0 / 5.0\r\n\r\n")
+            .unwrap();
+        assert!(!result.is_spam());
+        assert!(rejecting
+            .parse_reply("SPAMD/1.0 76 Bad header line")
+            .is_err());
+    }
+}
@@ -0,0 +1,425 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Sender Rewriting Scheme for forwarded envelopes.
+//!
+//! Addresses are rewritten in the format used by libsrs2 and postsrsd:
+//!
+//! ```text
+//! SRS0=HHHH=TT=original.domain=local@forwarder.domain
+//! SRS1=HHHH=first.forwarder==HHHH=TT=original.domain=local@forwarder.domain
+//! ```
+
+use crate::modifications::Modification;
+use crate::request::{Request, Stage};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const TIMESTAMP_PRECISION: u64 = 60 * 60 * 24;
+const TIMESTAMP_SLOTS: u64 = 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SrsError {
+    InvalidAddress,
+    NotSrs,
+    HashMismatch,
+    InvalidTimestamp,
+    Expired,
+}
+
+impl fmt::Display for SrsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SrsError::InvalidAddress => f.write_str("invalid email address"),
+            SrsError::NotSrs => f.write_str("address is not an SRS address"),
+            SrsError::HashMismatch => f.write_str("SRS hash does not match"),
+            SrsError::InvalidTimestamp => f.write_str("invalid SRS timestamp"),
+            SrsError::Expired => f.write_str("SRS address has expired"),
+        }
+    }
+}
+
+impl std::error::Error for SrsError {}
+
+#[derive(Debug, Clone)]
+pub struct Srs {
+    domain: String,
+    secrets: Vec<Vec<u8>>,
+    max_age: u64,
+    hash_length: usize,
+}
+
+impl Srs {
+    /// Creates a rewriter for the forwarding `domain`, signing with `secret`.
+    pub fn new(domain: String, secret: String) -> Self {
+        Self {
+            domain: domain.to_ascii_lowercase(),
+            secrets: vec![secret.into_bytes()],
+            max_age: 21,
+            hash_length: 4,
+        }
+    }
+
+    /// Adds a secret that is accepted when decoding but not used for new
+    /// addresses, allowing secrets to be rotated.
+    pub fn with_previous_secret(mut self, secret: String) -> Self {
+        self.secrets.push(secret.into_bytes());
+        self
+    }
+
+    /// Sets how many days an SRS address remains valid (21 by default).
+    pub fn with_max_age(mut self, days: u64) -> Self {
+        self.max_age = days;
+        self
+    }
+
+    pub fn with_hash_length(mut self, length: usize) -> Self {
+        self.hash_length = length.clamp(1, 28);
+        self
+    }
+
+    pub fn is_srs(address: &str) -> bool {
+        let prefix = address.get(..5).unwrap_or_default();
+        (prefix.eq_ignore_ascii_case("SRS0=")
+            || prefix.eq_ignore_ascii_case("SRS0+")
+            || prefix.eq_ignore_ascii_case("SRS0-")
+            || prefix.eq_ignore_ascii_case("SRS1=")
+            || prefix.eq_ignore_ascii_case("SRS1+")
+            || prefix.eq_ignore_ascii_case("SRS1-"))
+            && address.contains('@')
+    }
+
+    pub fn encode(&self, address: &str) -> Result<String, SrsError> {
+        self.encode_at(address, unix_time())
+    }
+
+    /// Same as [`Srs::encode`] at an explicit point in time.
+    pub fn encode_at(&self, address: &str, now: u64) -> Result<String, SrsError> {
+        let (local, domain) = split_address(address)?;
+        if domain.eq_ignore_ascii_case(&self.domain) {
+            return Ok(address.to_string());
+        }
+
+        let is_srs = Self::is_srs(address);
+        if is_srs && local[..4].eq_ignore_ascii_case("SRS1") {
+            // Already forwarded twice, only the final hop changes.
+            let (first_hop, rest) = local[5..]
+                .split_once("==")
+                .ok_or(SrsError::InvalidAddress)?;
+            let (_, first_hop) = first_hop.split_once('=').ok_or(SrsError::InvalidAddress)?;
+            let hash = self.hash(&[first_hop, rest]);
+            return Ok(format!(
+                "SRS1={}={}=={}@{}",
+                hash, first_hop, rest, self.domain
+            ));
+        }
+
+        if is_srs && local[..4].eq_ignore_ascii_case("SRS0") {
+            let rest = &local[5..];
+            let hash = self.hash(&[domain, rest]);
+            return Ok(format!(
+                "SRS1={}={}=={}@{}",
+                hash, domain, rest, self.domain
+            ));
+        }
+
+        let timestamp = timestamp(now);
+        let hash = self.hash(&[&timestamp, domain, local]);
+        Ok(format!(
+            "SRS0={}={}={}={}@{}",
+            hash, timestamp, domain, local, self.domain
+        ))
+    }
+
+    pub fn decode(&self, address: &str) -> Result<String, SrsError> {
+        self.decode_at(address, unix_time())
+    }
+
+    /// Same as [`Srs::decode`] at an explicit point in time.
+    ///
+    /// SRS0 addresses decode to the original sender, SRS1 addresses to the
+    /// SRS0 address of the first forwarder.
+    pub fn decode_at(&self, address: &str, now: u64) -> Result<String, SrsError> {
+        if !Self::is_srs(address) {
+            return Err(SrsError::NotSrs);
+        }
+        let (local, _) = split_address(address)?;
+        let kind = &local[..4];
+        let mut parts = local[5..].splitn(2, '=');
+        let hash = parts.next().ok_or(SrsError::InvalidAddress)?;
+        let rest = parts.next().ok_or(SrsError::InvalidAddress)?;
+
+        if kind.eq_ignore_ascii_case("SRS1") {
+            let (first_hop, srs0) = rest.split_once("==").ok_or(SrsError::InvalidAddress)?;
+            self.check_hash(hash, &[first_hop, srs0])?;
+            return Ok(format!("SRS0={}@{}", srs0, first_hop));
+        }
+
+        let mut parts = rest.splitn(3, '=');
+        let (Some(timestamp), Some(domain), Some(local)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(SrsError::InvalidAddress);
+        };
+        self.check_hash(hash, &[timestamp, domain, local])?;
+        self.check_timestamp(timestamp, now)?;
+        Ok(format!("{}@{}", local, domain))
+    }
+
+    /// Emits the `ChangeFrom` modification rewriting the envelope sender.
+    ///
+    /// Returns `None` for the null sender and for senders already in the
+    /// forwarding domain.
+    pub fn rewrite_sender(&self, request: &Request) -> Result<Option<Modification>, SrsError> {
+        let Some(from) = request.envelope.as_ref().map(|envelope| &envelope.from) else {
+            return Ok(None);
+        };
+        if from.address.is_empty() {
+            return Ok(None);
+        }
+        let rewritten = self.encode(&from.address)?;
+        if rewritten == from.address {
+            return Ok(None);
+        }
+        let parameters: HashMap<String, Option<String>> = from
+            .parameters
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key.clone(), Some(value.clone())))
+            .collect();
+        Ok(Some(Modification::change_from_with_params(
+            rewritten, parameters,
+        )))
+    }
+
+    /// Replaces SRS recipients in our domain with their decoded address.
+    ///
+    /// Meant for `Stage::Rcpt`, where bounces to rewritten senders arrive.
+    /// Recipients that fail to decode are left untouched so that the hook
+    /// can decide whether to reject them.
+    pub fn reverse_recipients(&self, request: &Request) -> Vec<Modification> {
+        if !matches!(request.context.stage, Stage::Rcpt) {
+            return Vec::new();
+        }
+        let mut modifications = Vec::new();
+        for recipient in request.envelope.iter().flat_map(|envelope| &envelope.to) {
+            let in_domain = split_address(&recipient.address)
+                .is_ok_and(|(_, domain)| domain.eq_ignore_ascii_case(&self.domain));
+            if !in_domain || !Self::is_srs(&recipient.address) {
+                continue;
+            }
+            if let Ok(original) = self.decode(&recipient.address) {
+                modifications.push(Modification::delete_recipient(recipient.address.clone()));
+                modifications.push(Modification::add_recipient(original));
+            }
+        }
+        modifications
+    }
+
+    fn hash(&self, parts: &[&str]) -> String {
+        self.hash_with(&self.secrets[0], parts)
+    }
+
+    fn hash_with(&self, secret: &[u8], parts: &[&str]) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+        for part in parts {
+            mac.update(part.to_ascii_lowercase().as_bytes());
+        }
+        let mut hash = STANDARD.encode(mac.finalize().into_bytes());
+        hash.truncate(self.hash_length);
+        hash
+    }
+
+    fn check_hash(&self, hash: &str, parts: &[&str]) -> Result<(), SrsError> {
+        if self
+            .secrets
+            .iter()
+            .any(|secret| self.hash_with(secret, parts).eq_ignore_ascii_case(hash))
+        {
+            Ok(())
+        } else {
+            Err(SrsError::HashMismatch)
+        }
+    }
+
+    fn check_timestamp(&self, timestamp: &str, now: u64) -> Result<(), SrsError> {
+        let mut value = 0u64;
+        for ch in timestamp.bytes() {
+            let digit = BASE32
+                .iter()
+                .position(|b| *b == ch.to_ascii_uppercase())
+                .ok_or(SrsError::InvalidTimestamp)?;
+            value = (value << 5) | digit as u64;
+        }
+        if timestamp.len() != 2 {
+            return Err(SrsError::InvalidTimestamp);
+        }
+        let today = (now / TIMESTAMP_PRECISION) % TIMESTAMP_SLOTS;
+        let age = (today + TIMESTAMP_SLOTS - value) % TIMESTAMP_SLOTS;
+        if age > self.max_age {
+            Err(SrsError::Expired)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn timestamp(now: u64) -> String {
+    let days = (now / TIMESTAMP_PRECISION) % TIMESTAMP_SLOTS;
+    [days >> 5, days & 31]
+        .iter()
+        .map(|digit| BASE32[*digit as usize] as char)
+        .collect()
+}
+
+fn split_address(address: &str) -> Result<(&str, &str), SrsError> {
+    address
+        .rsplit_once('@')
+        .filter(|(local, domain)| !local.is_empty() && !domain.is_empty())
+        .ok_or(SrsError::InvalidAddress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const DAY: u64 = 60 * 60 * 24;
+
+    fn request(stage: Stage, from: &str, to: &[&str]) -> Request {
+        let mut builder = Request::builder()
+            .stage(stage)
+            .client_ip("127.0.0.1")
+            .from(from)
+            .from_param("size", "1000");
+        for address in to {
+            builder = builder.to(*address);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_srs0_round_trip() {
+        let srs = Srs::new("forward.example".to_string(), "secret".to_string());
+        let encoded = srs.encode_at("john@example.com", NOW).unwrap();
+        assert!(encoded.starts_with("SRS0="));
+        assert!(encoded.ends_with("=example.com=john@forward.example"));
+        assert_eq!(
+            encoded.len(),
+            "SRS0=HHHH=TT=example.com=john@forward.example".len()
+        );
+
+        assert_eq!(srs.decode_at(&encoded, NOW).unwrap(), "john@example.com");
+        assert_eq!(
+            srs.decode_at(&encoded.to_lowercase(), NOW + DAY).unwrap(),
+            "john@example.com"
+        );
+        assert_eq!(
+            srs.decode_at(&encoded, NOW + 30 * DAY),
+            Err(SrsError::Expired)
+        );
+
+        let other = Srs::new("forward.example".to_string(), "other".to_string());
+        assert_eq!(other.decode_at(&encoded, NOW), Err(SrsError::HashMismatch));
+        let rotated = other.with_previous_secret("secret".to_string());
+        assert_eq!(
+            rotated.decode_at(&encoded, NOW).unwrap(),
+            "john@example.com"
+        );
+    }
+
+    #[test]
+    fn test_srs1_round_trip() {
+        let first = Srs::new("first.example".to_string(), "one".to_string());
+        let second = Srs::new("second.example".to_string(), "two".to_string());
+        let third = Srs::new("third.example".to_string(), "three".to_string());
+
+        let srs0 = first.encode_at("john@example.com", NOW).unwrap();
+        let srs1 = second.encode_at(&srs0, NOW).unwrap();
+        assert!(srs1.starts_with("SRS1="));
+        assert!(srs1.contains("=first.example=="));
+        assert!(srs1.ends_with("@second.example"));
+
+        let srs1_again = third.encode_at(&srs1, NOW).unwrap();
+        assert!(srs1_again.ends_with("@third.example"));
+        assert!(srs1_again.contains("=first.example=="));
+
+        let back = second.decode_at(&srs1, NOW).unwrap();
+        assert_eq!(back, srs0);
+        assert_eq!(third.decode_at(&srs1_again, NOW).unwrap(), srs0);
+        assert_eq!(first.decode_at(&back, NOW).unwrap(), "john@example.com");
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        let srs = Srs::new("forward.example".to_string(), "secret".to_string());
+        assert_eq!(
+            srs.decode_at("john@example.com", NOW),
+            Err(SrsError::NotSrs)
+        );
+        assert_eq!(
+            srs.decode_at("SRS0=abcd@forward.example", NOW),
+            Err(SrsError::InvalidAddress)
+        );
+        assert_eq!(
+            srs.encode_at("not-an-address", NOW),
+            Err(SrsError::InvalidAddress)
+        );
+        assert_eq!(
+            srs.encode_at("local@forward.example", NOW).unwrap(),
+            "local@forward.example"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_sender() {
+        let srs = Srs::new("forward.example".to_string(), "secret".to_string());
+        let request = request(Stage::Mail, "john@example.com", &[]);
+        match srs.rewrite_sender(&request).unwrap() {
+            Some(Modification::ChangeFrom { value, parameters }) => {
+                assert!(value.starts_with("SRS0="));
+                assert_eq!(parameters.get("size"), Some(&Some("1000".to_string())));
+            }
+            _ => panic!("Expected ChangeFrom modification"),
+        }
+
+        let bounce = self::request(Stage::Mail, "", &[]);
+        assert!(srs.rewrite_sender(&bounce).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_reverse_recipients() {
+        let srs = Srs::new("forward.example".to_string(), "secret".to_string());
+        let encoded = srs.encode("john@example.com").unwrap();
+        let request = request(Stage::Rcpt, "", &[&encoded, "jane@forward.example"]);
+
+        let modifications = srs.reverse_recipients(&request);
+        assert_eq!(modifications.len(), 2);
+        match &modifications[0] {
+            Modification::DeleteRecipient { value } => assert_eq!(value, &encoded),
+            _ => panic!("Expected DeleteRecipient modification"),
+        }
+        match &modifications[1] {
+            Modification::AddRecipient { value, .. } => assert_eq!(value, "john@example.com"),
+            _ => panic!("Expected AddRecipient modification"),
+        }
+
+        let data = self::request(Stage::Data, "", &[&encoded]);
+        assert!(srs.reverse_recipients(&data).is_empty());
+    }
+}
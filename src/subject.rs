@@ -0,0 +1,242 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Subject tagging and header annotation.
+//!
+//! Tags such as `[SPAM]` are added at most once, also when the subject is
+//! RFC 2047 encoded: the encoded words of the original subject are kept
+//! and a non-ASCII tag is encoded on its own, together with the separating
+//! space when it is next to an encoded word.
+//!
+//! ```
+//! use stalwart_mta_hook_types::subject::{prepend_tag, set_header};
+//! use stalwart_mta_hook_types::{Modification, Request, Response, Stage};
+//!
+//! let request = Request::builder()
+//!     .stage(Stage::Data)
+//!     .raw_message("Subject: =?UTF-8?Q?Gr=C3=BC=C3=9Fe?=\r\n\r\nHello\r\n")
+//!     .build();
+//! let message = request.message.as_ref().unwrap();
+//! let mut modifications = vec![set_header(message, "X-Spam-Flag", "YES")];
+//! modifications.extend(prepend_tag(message, "[SPAM]"));
+//! let response = Response::accept().with_modifications(modifications);
+//! assert!(matches!(
+//!     &response.modifications[1],
+//!     Modification::ChangeHeader { value, .. } if value == "[SPAM] =?UTF-8?Q?Gr=C3=BC=C3=9Fe?="
+//! ));
+//! ```
+
+use crate::mime::{decode_encoded_words, encode_encoded_words};
+use crate::modifications::Modification;
+use crate::request::Message;
+
+/// Reply and forward prefixes skipped when looking for a leading tag.
+const REPLY_PREFIXES: &[&str] = &["re:", "fwd:", "fw:", "aw:", "wg:", "sv:", "vs:", "tr:"];
+
+/// The raw value of the first `name` header, unfolded and trimmed.
+fn raw_header(message: &Message, name: &str) -> Option<String> {
+    message
+        .headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| {
+            value
+                .replace("\r\n", "")
+                .replace('\n', "")
+                .trim()
+                .to_string()
+        })
+}
+
+/// The decoded subject of the message.
+pub fn subject(message: &Message) -> Option<String> {
+    raw_header(message, "Subject").map(|value| decode_encoded_words(&value))
+}
+
+/// Whether the subject starts with `tag`, possibly after reply prefixes,
+/// or ends with it.
+pub fn has_tag(message: &Message, tag: &str) -> bool {
+    let Some(subject) = subject(message) else {
+        return false;
+    };
+    let tag = tag.trim();
+    let mut rest = subject.trim();
+    loop {
+        if rest.starts_with(tag) {
+            return true;
+        }
+        let lower = rest.to_ascii_lowercase();
+        match REPLY_PREFIXES
+            .iter()
+            .find(|prefix| lower.starts_with(*prefix))
+        {
+            Some(prefix) => rest = rest[prefix.len()..].trim_start(),
+            None => break,
+        }
+    }
+    subject.trim_end().ends_with(tag)
+}
+
+/// Prepends `tag` to the subject, or adds the subject `tag` if there is
+/// none. Returns `None` if the subject is already tagged.
+pub fn prepend_tag(message: &Message, tag: &str) -> Option<Modification> {
+    tag_subject(message, tag, true)
+}
+
+/// Appends `tag` to the subject, or adds the subject `tag` if there is
+/// none. Returns `None` if the subject is already tagged.
+pub fn append_tag(message: &Message, tag: &str) -> Option<Modification> {
+    tag_subject(message, tag, false)
+}
+
+/// Whether `word` is an RFC 2047 encoded word.
+fn is_encoded_word(word: &str) -> bool {
+    word.len() > 4 && word.starts_with("=?") && word.ends_with("?=")
+}
+
+fn tag_subject(message: &Message, tag: &str, prepend: bool) -> Option<Modification> {
+    if has_tag(message, tag) {
+        return None;
+    }
+    let tag = tag.trim();
+    Some(match raw_header(message, "Subject") {
+        Some(raw) if !raw.is_empty() => {
+            // Whitespace between two encoded words is dropped when decoding,
+            // so an encoded tag next to one carries the separating space.
+            let value = if prepend {
+                let next = raw.split_whitespace().next().unwrap_or_default();
+                if !tag.is_ascii() && is_encoded_word(next) {
+                    format!("{} {}", encode_encoded_words(&format!("{} ", tag)), raw)
+                } else {
+                    format!("{} {}", encode_encoded_words(tag), raw)
+                }
+            } else {
+                let previous = raw.split_whitespace().next_back().unwrap_or_default();
+                if !tag.is_ascii() && is_encoded_word(previous) {
+                    format!("{} {}", raw, encode_encoded_words(&format!(" {}", tag)))
+                } else {
+                    format!("{} {}", raw, encode_encoded_words(tag))
+                }
+            };
+            Modification::change_header(1, "Subject".to_string(), value)
+        }
+        Some(_) => Modification::change_header(1, "Subject".to_string(), encode_encoded_words(tag)),
+        None => Modification::add_header("Subject".to_string(), encode_encoded_words(tag)),
+    })
+}
+
+/// Sets the header `name` to `value`, changing its first occurrence or
+/// adding it. Non-ASCII values are RFC 2047 encoded.
+pub fn set_header(message: &Message, name: &str, value: &str) -> Modification {
+    let value = encode_encoded_words(value);
+    match raw_header(message, name) {
+        Some(_) => Modification::change_header(1, name.to_string(), value),
+        None => Modification::add_header(name.to_string(), value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(subject: Option<&str>) -> Message {
+        let mut headers = vec![("From".to_string(), " joe@example.com\r\n".to_string())];
+        if let Some(subject) = subject {
+            headers.push(("Subject".to_string(), format!(" {}\r\n", subject)));
+        }
+        Message {
+            headers,
+            server_headers: Vec::new(),
+            contents: "Hello\r\n".into(),
+            size: 7,
+        }
+    }
+
+    fn changed(modification: Option<Modification>) -> String {
+        match modification {
+            Some(Modification::ChangeHeader {
+                index: 1,
+                name,
+                value,
+            }) if name == "Subject" => value,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_prepend_and_append() {
+        let plain = message(Some("Hello"));
+        assert_eq!(changed(prepend_tag(&plain, "[SPAM]")), "[SPAM] Hello");
+        assert_eq!(
+            changed(append_tag(&plain, "(external)")),
+            "Hello (external)"
+        );
+
+        for tagged in ["[SPAM] Hello", "Re: AW: [SPAM] Hello", "Hello [SPAM]"] {
+            assert!(prepend_tag(&message(Some(tagged)), "[SPAM]").is_none());
+        }
+
+        let Some(Modification::AddHeader { name, value }) = prepend_tag(&message(None), "[SPAM]")
+        else {
+            panic!("expected AddHeader");
+        };
+        assert_eq!((name.as_str(), value.as_str()), ("Subject", "[SPAM]"));
+    }
+
+    #[test]
+    fn test_encoded_subjects() {
+        let folded = message(Some(
+            "=?UTF-8?B?R3LDvMOfZQ==?=\r\n =?UTF-8?Q?_aus_K=C3=B6ln?=",
+        ));
+        assert_eq!(subject(&folded).as_deref(), Some("Grüße aus Köln"));
+        let value = changed(prepend_tag(&folded, "[SPAM]"));
+        assert_eq!(
+            value,
+            "[SPAM] =?UTF-8?B?R3LDvMOfZQ==?= =?UTF-8?Q?_aus_K=C3=B6ln?="
+        );
+        let tagged = message(Some(&value));
+        assert_eq!(subject(&tagged).as_deref(), Some("[SPAM] Grüße aus Köln"));
+        assert!(prepend_tag(&tagged, "[SPAM]").is_none());
+
+        let value = changed(prepend_tag(&message(Some("Hello")), "[EXTERN – Vorsicht]"));
+        assert!(value.is_ascii());
+        assert_eq!(
+            subject(&message(Some(&value))).as_deref(),
+            Some("[EXTERN – Vorsicht] Hello")
+        );
+
+        // The space between an encoded tag and an encoded subject is part of
+        // the tag's encoded word.
+        let value = changed(prepend_tag(&folded, "[EXTERN – Vorsicht]"));
+        assert!(value.is_ascii());
+        assert_eq!(
+            subject(&message(Some(&value))).as_deref(),
+            Some("[EXTERN – Vorsicht] Grüße aus Köln")
+        );
+        let value = changed(append_tag(&folded, "(geprüft)"));
+        assert_eq!(
+            subject(&message(Some(&value))).as_deref(),
+            Some("Grüße aus Köln (geprüft)")
+        );
+    }
+
+    #[test]
+    fn test_set_header() {
+        let message = message(Some("Hello"));
+        let Modification::ChangeHeader { index, value, .. } =
+            set_header(&message, "from", "jane@example.com")
+        else {
+            panic!("expected ChangeHeader");
+        };
+        assert_eq!((index, value.as_str()), (1, "jane@example.com"));
+        let Modification::AddHeader { name, value } = set_header(&message, "X-Note", "geprüft")
+        else {
+            panic!("expected AddHeader");
+        };
+        assert_eq!(name, "X-Note");
+        assert_eq!(value, "=?UTF-8?B?Z2VwcsO8ZnQ=?=");
+    }
+}
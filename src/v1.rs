@@ -0,0 +1,20 @@
+/*
+ * SPDX-FileCopyrightText: 2025 Franz Dietrich <dietrich@teilgedanken.de>
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Version 1 of the hook protocol.
+//!
+//! These types stay as they are when a later version is added; the crate
+//! root re-exports the types of the latest version.
+
+pub mod modifications;
+pub mod request;
+pub mod response;
+
+pub use modifications::*;
+pub use request::*;
+pub use response::*;
+
+pub const VERSION: u32 = 1;
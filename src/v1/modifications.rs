@@ -5,9 +5,11 @@
  * SPDX-License-Identifier: MIT OR Apache-2.0
  */
 
+use crate::contents::Contents;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
 // Custom deserializer to handle null as empty HashMap and convert integers to strings
 fn deserialize_null_as_empty_map<'de, D>(
@@ -16,46 +18,209 @@ fn deserialize_null_as_empty_map<'de, D>(
 where
     D: Deserializer<'de>,
 {
-    let opt: Option<HashMap<String, Option<Value>>> = Option::deserialize(deserializer)?;
-
-    match opt {
-        None => Ok(HashMap::new()),
-        Some(map) => {
-            let mut result = HashMap::new();
-            for (key, value) in map {
-                let string_value = match value {
-                    Some(Value::String(s)) => Some(s),
-                    Some(Value::Number(n)) => Some(n.to_string()),
-                    Some(Value::Bool(b)) => Some(b.to_string()),
-                    Some(_) => Some(value.unwrap().to_string()),
-                    None => None,
-                };
-                result.insert(key, string_value);
+    let opt: Option<HashMap<String, Option<ParameterText>>> = Option::deserialize(deserializer)?;
+
+    Ok(opt
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, value)| (key, value.map(|ParameterText(value)| value)))
+        .collect())
+}
+
+/// A modification parameter value. Numbers and booleans are converted to
+/// strings, arrays and objects to their compact JSON text.
+struct ParameterText(String);
+
+impl<'de> Deserialize<'de> for ParameterText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ParameterVisitor;
+
+        impl<'de> Visitor<'de> for ParameterVisitor {
+            type Value = ParameterText;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a parameter value")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(ParameterText(value.to_string()))
+            }
+
+            fn visit_string<E: de::Error>(self, value: String) -> Result<Self::Value, E> {
+                Ok(ParameterText(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                JsonTextVisitor
+                    .visit_i64(value)
+                    .map(|JsonText(text)| ParameterText(text))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                JsonTextVisitor
+                    .visit_u64(value)
+                    .map(|JsonText(text)| ParameterText(text))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+                JsonTextVisitor
+                    .visit_f64(value)
+                    .map(|JsonText(text)| ParameterText(text))
+            }
+
+            fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+                JsonTextVisitor
+                    .visit_bool(value)
+                    .map(|JsonText(text)| ParameterText(text))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                JsonTextVisitor
+                    .visit_seq(seq)
+                    .map(|JsonText(text)| ParameterText(text))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                JsonTextVisitor
+                    .visit_map(map)
+                    .map(|JsonText(text)| ParameterText(text))
             }
-            Ok(result)
+        }
+
+        deserializer.deserialize_any(ParameterVisitor)
+    }
+}
+
+/// Any value as compact JSON text, with object keys sorted as
+/// `serde_json::Value` writes them.
+struct JsonText(String);
+
+struct JsonTextVisitor;
+
+impl<'de> Deserialize<'de> for JsonText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonTextVisitor)
+    }
+}
+
+impl<'de> Visitor<'de> for JsonTextVisitor {
+    type Value = JsonText;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(JsonText(json_string(value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(JsonText(value.to_string()))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(JsonText(value.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        // Debug keeps the ".0" of whole numbers, as JSON writes them.
+        Ok(JsonText(if value.is_finite() {
+            format!("{:?}", value)
+        } else {
+            "null".to_string()
+        }))
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(JsonText(value.to_string()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(JsonText("null".to_string()))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        JsonText::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(JsonText(item)) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(JsonText(format!("[{}]", items.join(","))))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = BTreeMap::new();
+        while let Some((key, JsonText(value))) = map.next_entry::<String, JsonText>()? {
+            entries.insert(key, value);
+        }
+        let entries: Vec<String> = entries
+            .into_iter()
+            .map(|(key, value)| format!("{}:{}", json_string(&key), value))
+            .collect();
+        Ok(JsonText(format!("{{{}}}", entries.join(","))))
+    }
+}
+
+/// Quotes and escapes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{8}' => quoted.push_str("\\b"),
+            '\u{c}' => quoted.push_str("\\f"),
+            ch if ch < ' ' => quoted.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => quoted.push(ch),
         }
     }
+    quoted.push('"');
+    quoted
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type")]
 pub enum Modification {
     #[serde(rename = "changeFrom")]
     ChangeFrom {
         value: String,
         #[serde(default, deserialize_with = "deserialize_null_as_empty_map")]
+        #[cfg_attr(
+            feature = "schema",
+            schemars(
+                with = "Option<HashMap<String, Option<crate::schema::ModificationParameterValue>>>"
+            )
+        )]
         parameters: HashMap<String, Option<String>>,
     },
     #[serde(rename = "addRecipient")]
     AddRecipient {
         value: String,
         #[serde(default, deserialize_with = "deserialize_null_as_empty_map")]
+        #[cfg_attr(
+            feature = "schema",
+            schemars(
+                with = "Option<HashMap<String, Option<crate::schema::ModificationParameterValue>>>"
+            )
+        )]
         parameters: HashMap<String, Option<String>>,
     },
     #[serde(rename = "deleteRecipient")]
     DeleteRecipient { value: String },
     #[serde(rename = "replaceContents")]
-    ReplaceContents { value: String },
+    ReplaceContents { value: Contents },
     #[serde(rename = "addHeader")]
     AddHeader { name: String, value: String },
     #[serde(rename = "insertHeader")]
@@ -113,8 +278,10 @@ impl Modification {
         Self::DeleteRecipient { value: address }
     }
 
-    pub fn replace_contents(contents: String) -> Self {
-        Self::ReplaceContents { value: contents }
+    pub fn replace_contents(contents: impl Into<Contents>) -> Self {
+        Self::ReplaceContents {
+            value: contents.into(),
+        }
     }
 
     pub fn add_header(name: String, value: String) -> Self {
@@ -247,6 +414,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_structured_parameters_deserialization() {
+        // Arrays and objects are kept as their JSON text
+        let json = r#"{
+            "type": "addRecipient",
+            "value": "test@example.com",
+            "parameters": {
+                "list": [1, "two\n", null, 2.5],
+                "object": {"b": true, "a": {"c": []}}
+            }
+        }"#;
+
+        let modification: Modification =
+            serde_json::from_str(json).expect("Failed to parse JSON with array parameters");
+
+        match modification {
+            Modification::AddRecipient { parameters, .. } => {
+                let value: serde_json::Value = serde_json::from_str(json).unwrap();
+                for key in ["list", "object"] {
+                    assert_eq!(
+                        parameters.get(key),
+                        Some(&Some(value["parameters"][key].to_string()))
+                    );
+                }
+                assert_eq!(
+                    parameters.get("list"),
+                    Some(&Some(r#"[1,"two\n",null,2.5]"#.to_string()))
+                );
+            }
+            _ => panic!("Expected AddRecipient modification"),
+        }
+    }
+
     #[test]
     fn test_string_parameters_deserialization() {
         // Test that string parameters work as before
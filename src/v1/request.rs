@@ -5,10 +5,63 @@
  * SPDX-License-Identifier: MIT OR Apache-2.0
  */
 
+use crate::contents::Contents;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
+use std::net::IpAddr;
 
-fn deserialize_string_or_int_map<'de, D>(
+/// An ESMTP parameter value given as a string, number or boolean, in any
+/// self-describing format.
+struct ParameterString(String);
+
+impl<'de> Deserialize<'de> for ParameterString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::{self, Visitor};
+        use std::fmt;
+
+        struct ParameterVisitor;
+
+        impl Visitor<'_> for ParameterVisitor {
+            type Value = ParameterString;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string, number or boolean parameter value")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(ParameterString(value.to_string()))
+            }
+
+            fn visit_string<E: de::Error>(self, value: String) -> Result<Self::Value, E> {
+                Ok(ParameterString(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                Ok(ParameterString(value.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(ParameterString(value.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+                // Debug keeps the ".0" of whole numbers, as JSON writes them.
+                Ok(ParameterString(format!("{:?}", value)))
+            }
+
+            fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+                Ok(ParameterString(value.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(ParameterVisitor)
+    }
+}
+
+pub(crate) fn deserialize_string_or_int_map<'de, D>(
     deserializer: D,
 ) -> Result<Option<HashMap<String, String>>, D::Error>
 where
@@ -63,15 +116,8 @@ where
         {
             let mut map = HashMap::new();
 
-            while let Some(key) = access.next_key::<String>()? {
-                let value: serde_json::Value = access.next_value()?;
-                let string_value = match value {
-                    serde_json::Value::String(s) => s,
-                    serde_json::Value::Number(n) => n.to_string(),
-                    serde_json::Value::Bool(b) => b.to_string(),
-                    _ => return Err(de::Error::custom("invalid parameter value type")),
-                };
-                map.insert(key, string_value);
+            while let Some((key, ParameterString(value))) = access.next_entry()? {
+                map.insert(key, value);
             }
 
             Ok(map)
@@ -82,6 +128,7 @@ where
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Request {
     pub context: Context,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -91,6 +138,7 @@ pub struct Request {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Context {
     pub stage: Stage,
     pub client: Client,
@@ -101,10 +149,12 @@ pub struct Context {
     pub server: Server,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub queue: Option<Queue>,
+    #[serde(default)]
     pub protocol: Protocol,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Sasl {
     pub login: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -112,6 +162,7 @@ pub struct Sasl {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Client {
     pub ip: String,
     pub port: u16,
@@ -121,7 +172,15 @@ pub struct Client {
     pub active_connections: u32,
 }
 
+impl Client {
+    /// Parses the client address, returning `None` if it is not a valid IP.
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        self.ip.parse().ok()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Tls {
     pub version: String,
     pub cipher: String,
@@ -137,6 +196,7 @@ pub struct Tls {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Server {
     pub name: Option<String>,
     pub port: u16,
@@ -144,16 +204,25 @@ pub struct Server {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Queue {
     pub id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Protocol {
     pub version: u32,
 }
 
-#[derive(Debug, Clone)]
+impl Default for Protocol {
+    /// Version 1, assumed when a request does not name its version.
+    fn default() -> Self {
+        Self { version: 1 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Stage {
     Connect,
     Ehlo,
@@ -163,20 +232,25 @@ pub enum Stage {
     Data,
 }
 
-impl Serialize for Stage {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let stage_str = match self {
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
             Stage::Connect => "connect",
             Stage::Ehlo => "ehlo",
             Stage::Auth => "auth",
             Stage::Mail => "mail",
             Stage::Rcpt => "rcpt",
             Stage::Data => "data",
-        };
-        serializer.serialize_str(stage_str)
+        }
+    }
+}
+
+impl Serialize for Stage {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
     }
 }
 
@@ -218,34 +292,122 @@ impl<'de> Deserialize<'de> for Stage {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Address {
     pub address: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(deserialize_with = "deserialize_string_or_int_map")]
     #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<HashMap<String, crate::schema::ParameterValue>>")
+    )]
     pub parameters: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Envelope {
     pub from: Address,
     pub to: Vec<Address>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Message {
     pub headers: Vec<(String, String)>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(rename = "serverHeaders")]
     #[serde(default)]
     pub server_headers: Vec<(String, String)>,
-    pub contents: String,
+    pub contents: Contents,
     pub size: usize,
 }
 
+impl Message {
+    /// Parses a raw RFC 5322 message such as an `.eml` file.
+    ///
+    /// Header values keep their leading whitespace, folding and line end as
+    /// in hook requests, so [`Message::to_rfc5322`] returns the input
+    /// unchanged. A leading mbox `From ` line is skipped, and a line that
+    /// is not a header ends the header section. All headers end up in
+    /// `headers`, as raw messages do not tell which ones Stalwart added.
+    /// The body is kept byte for byte; headers that are not UTF-8 are
+    /// decoded lossily.
+    pub fn parse(raw: impl AsRef<[u8]>) -> Self {
+        let raw = raw.as_ref();
+        let mut headers: Vec<(String, String)> = Vec::new();
+        let mut rest = raw;
+        if rest.starts_with(b"From ") {
+            rest = rest
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(&[][..], |pos| &rest[pos + 1..]);
+        }
+        while let Some(line) = rest.split_inclusive(|&byte| byte == b'\n').next() {
+            if line == b"\r\n" || line == b"\n" {
+                rest = &rest[line.len()..];
+                break;
+            }
+            let text = String::from_utf8_lossy(line);
+            match (
+                line.starts_with(b" ") || line.starts_with(b"\t"),
+                headers.last_mut(),
+            ) {
+                (true, Some((_, value))) => value.push_str(&text),
+                _ => match text.split_once(':') {
+                    Some((name, value)) if !name.is_empty() && !name.contains([' ', '\t']) => {
+                        headers.push((name.to_string(), value.to_string()))
+                    }
+                    _ => break,
+                },
+            }
+            rest = &rest[line.len()..];
+        }
+        Self {
+            headers,
+            server_headers: Vec::new(),
+            contents: Contents::from(rest),
+            size: raw.len(),
+        }
+    }
+
+    /// Reads and parses a raw message file.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::parse(std::fs::read(path)?))
+    }
+
+    /// The message as delivered: the server headers, the headers, an empty
+    /// line and the body.
+    ///
+    /// Header values are written as they are when they carry their line
+    /// end, as in hook requests; other values get a leading space and CRLF.
+    pub fn to_rfc5322(&self) -> Vec<u8> {
+        let mut raw = String::with_capacity(2048);
+        for (name, value) in self.server_headers.iter().chain(&self.headers) {
+            raw.push_str(name);
+            raw.push(':');
+            if value.ends_with('\n') {
+                raw.push_str(value);
+                continue;
+            }
+            if !value.is_empty() && !value.starts_with([' ', '\t']) {
+                raw.push(' ');
+            }
+            raw.push_str(value);
+            raw.push_str("\r\n");
+        }
+        raw.push_str("\r\n");
+        let mut raw = raw.into_bytes();
+        raw.extend_from_slice(&self.contents);
+        raw
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::v1::modifications::Modification;
     use std::collections::HashMap;
 
     #[test]
@@ -586,4 +748,73 @@ mod tests {
         assert_eq!(envelope.to.len(), 1);
         assert_eq!(envelope.to[0].address, "recipient@example.com");
     }
+
+    #[test]
+    fn test_rfc5322_round_trip() {
+        let raw = "Received: from client.example.com\r\n\tby mx.example.org; Mon, 1 Jan 2024\r\n\
+            From: John <john@example.com>\r\n\
+            Subject: Hello\r\n\
+            \r\n\
+            Body line\r\nFrom: not a header\r\n";
+        let message = Message::parse(raw);
+        assert_eq!(message.size, raw.len());
+        assert_eq!(message.headers.len(), 3);
+        assert_eq!(
+            message.headers[0].1,
+            " from client.example.com\r\n\tby mx.example.org; Mon, 1 Jan 2024\r\n"
+        );
+        assert_eq!(
+            message.headers[2],
+            ("Subject".to_string(), " Hello\r\n".to_string())
+        );
+        assert_eq!(message.contents, "Body line\r\nFrom: not a header\r\n");
+        assert_eq!(message.to_rfc5322(), raw.as_bytes());
+
+        let mbox =
+            Message::parse("From john@example.com Mon Jan  1 00:00:00 2024\nTo: bill\n\nHi\n");
+        assert_eq!(mbox.headers, [("To".to_string(), " bill\n".to_string())]);
+        assert_eq!(mbox.contents, "Hi\n");
+
+        let built = Message {
+            headers: vec![("Subject".to_string(), "Hi".to_string())],
+            server_headers: vec![("X-Spam".to_string(), " no\r\n".to_string())],
+            contents: "Body\r\n".into(),
+            size: 0,
+        };
+        assert_eq!(
+            built.to_rfc5322(),
+            b"X-Spam: no\r\nSubject: Hi\r\n\r\nBody\r\n"
+        );
+    }
+
+    #[test]
+    fn test_legacy_charset_round_trip() {
+        let raw = b"Subject: =?iso-8859-1?q?Gr=FC=DFe?=\r\n\
+            Content-Type: text/plain; charset=iso-8859-1\r\n\
+            Content-Transfer-Encoding: 8bit\r\n\
+            \r\n\
+            Sch\xf6ne Gr\xfc\xdfe\r\n";
+        let message = Message::parse(raw);
+        assert_eq!(message.contents, b"Sch\xf6ne Gr\xfc\xdfe\r\n"[..]);
+        assert!(!message.contents.is_utf8());
+
+        // The raw form keeps the body byte for byte, JSON replaces what is
+        // not UTF-8.
+        assert_eq!(
+            &message.to_rfc5322()[raw.len() - 16..],
+            &raw[raw.len() - 16..]
+        );
+        let json = serde_json::to_string(&message).unwrap();
+        let parsed: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.contents, "Sch\u{fffd}ne Gr\u{fffd}\u{fffd}e\r\n");
+
+        let modification = Modification::replace_contents(message.contents.clone());
+        let json = serde_json::to_string(&modification).unwrap();
+        match serde_json::from_str(&json).unwrap() {
+            Modification::ReplaceContents { value } => {
+                assert_eq!(value, message.contents.to_str_lossy().as_ref())
+            }
+            _ => panic!("expected ReplaceContents"),
+        }
+    }
 }
@@ -5,10 +5,11 @@
  * SPDX-License-Identifier: MIT OR Apache-2.0
  */
 
-use crate::modifications::Modification;
+use super::modifications::Modification;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Response {
     pub action: Action,
     #[serde(default)]
@@ -17,7 +18,8 @@ pub struct Response {
     pub modifications: Vec<Modification>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum Action {
     #[serde(rename = "accept")]
@@ -31,6 +33,7 @@ pub enum Action {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SmtpResponse {
     #[serde(default)]
     pub status: Option<u16>,
@@ -85,7 +88,8 @@ impl Response {
     /// Creates a quarantine response with no modifications
     ///
     /// Note: that quarantine is not yet implemented in Stalwart MTA
-    /// see https://github.com/stalwartlabs/stalwart/issues/620
+    /// see https://github.com/stalwartlabs/stalwart/issues/620,
+    /// [`crate::quarantine`] implements it on the hook side.
     pub fn quarantine() -> Self {
         Self {
             action: Action::Quarantine,
@@ -103,7 +107,7 @@ impl Response {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::modifications::Modification;
+    use crate::v1::modifications::Modification;
 
     #[test]
     fn test_response_serialization() {
@@ -188,7 +192,7 @@ mod tests {
         assert_eq!(smtp_response.status, Some(250));
         assert_eq!(smtp_response.enhanced_status, Some("2.0.0".to_string()));
         assert_eq!(smtp_response.message, Some("Message accepted".to_string()));
-        assert_eq!(smtp_response.disconnect, false);
+        assert!(!smtp_response.disconnect);
 
         // Verify modifications
         assert_eq!(response.modifications.len(), 8);